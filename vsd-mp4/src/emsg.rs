@@ -0,0 +1,225 @@
+//! Mp4 `EMSG` box parser.
+
+/*
+    REFERENCES
+    ----------
+
+    1. ISO/IEC 23009-1 Section 5.10.3.3 (Event message box)
+    2. https://github.com/shaka-project/shaka-player/blob/main/lib/media/media_source_engine.js (parseEMSG_)
+
+*/
+
+use crate::{parser::Mp4Parser, Error, ParsedBox, Result};
+use std::sync::{Arc, Mutex};
+
+/// Event carried by an `emsg` box.
+#[derive(Clone, Debug)]
+pub struct EventMessage {
+    pub scheme_id_uri: String,
+    pub value: String,
+    pub timescale: u32,
+    /// Presentation time of event relative to the earliest presentation time of segment (version 0).
+    pub presentation_time_delta: Option<u32>,
+    /// Presentation time of event on the media timeline (version 1).
+    pub presentation_time: Option<u64>,
+    /// Duration of event, `0xFFFFFFFF` indicates an unknown duration.
+    pub event_duration: u32,
+    pub id: u32,
+    pub message_data: Vec<u8>,
+}
+
+/// Parse `EMSG` boxes from mp4 segments.
+#[derive(Default)]
+pub struct Emsg {
+    pub messages: Vec<EventMessage>,
+}
+
+impl Emsg {
+    /// Parse all top level `emsg` boxes found in data.
+    pub fn new(data: &[u8]) -> Result<Self> {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let messages_c = messages.clone();
+
+        Mp4Parser::default()
+            .full_box(
+                "emsg",
+                Arc::new(move |mut _box| {
+                    messages_c
+                        .lock()
+                        .unwrap()
+                        .push(Self::parse_emsg_box(&mut _box)?);
+                    Ok(())
+                }),
+            )
+            .parse(data, Some(true), None)?;
+
+        let messages = messages.lock().unwrap().drain(..).collect();
+        Ok(Self { messages })
+    }
+
+    fn parse_emsg_box(_box: &mut ParsedBox) -> Result<EventMessage> {
+        let Some(version) = _box.version else {
+            return Err(Error::new(
+                "EMSG boxes are full boxes and must have a valid version",
+            ));
+        };
+
+        let mut message = if version == 0 {
+            let scheme_id_uri = _box
+                .reader
+                .read_terminated_string()
+                .map_err(|_| Error::new_read_err("EMSG box scheme id uri (string)"))?;
+            let value = _box
+                .reader
+                .read_terminated_string()
+                .map_err(|_| Error::new_read_err("EMSG box value (string)"))?;
+            let timescale = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("EMSG box timescale (u32)"))?;
+            let presentation_time_delta = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("EMSG box presentation time delta (u32)"))?;
+            let event_duration = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("EMSG box event duration (u32)"))?;
+            let id = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("EMSG box id (u32)"))?;
+
+            EventMessage {
+                scheme_id_uri,
+                value,
+                timescale,
+                presentation_time_delta: Some(presentation_time_delta),
+                presentation_time: None,
+                event_duration,
+                id,
+                message_data: vec![],
+            }
+        } else {
+            let timescale = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("EMSG box timescale (u32)"))?;
+            let presentation_time = _box
+                .reader
+                .read_u64()
+                .map_err(|_| Error::new_read_err("EMSG box presentation time (u64)"))?;
+            let event_duration = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("EMSG box event duration (u32)"))?;
+            let id = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("EMSG box id (u32)"))?;
+            let scheme_id_uri = _box
+                .reader
+                .read_terminated_string()
+                .map_err(|_| Error::new_read_err("EMSG box scheme id uri (string)"))?;
+            let value = _box
+                .reader
+                .read_terminated_string()
+                .map_err(|_| Error::new_read_err("EMSG box value (string)"))?;
+
+            EventMessage {
+                scheme_id_uri,
+                value,
+                timescale,
+                presentation_time_delta: None,
+                presentation_time: Some(presentation_time),
+                event_duration,
+                id,
+                message_data: vec![],
+            }
+        };
+
+        if message.timescale == 0 {
+            return Err(Error::new("EMSG box has invalid timescale (0)"));
+        }
+
+        let remaining = _box.reader.get_length() - _box.reader.get_position();
+        message.message_data = _box
+            .reader
+            .read_bytes_u8(remaining as usize)
+            .map_err(|_| Error::new_read_err("EMSG box message data"))?;
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Emsg;
+
+    fn full_box(name: &[u8; 4], version: u8, payload: &[u8]) -> Vec<u8> {
+        [
+            &(12 + payload.len() as u32).to_be_bytes()[..],
+            name,
+            &[version, 0, 0, 0],
+            payload,
+        ]
+        .concat()
+    }
+
+    fn emsg_v0() -> Vec<u8> {
+        let mut payload = b"urn:scte:scte35:2013:bin\0splice\0".to_vec();
+        payload.extend(90000_u32.to_be_bytes()); // timescale
+        payload.extend(180000_u32.to_be_bytes()); // presentation_time_delta
+        payload.extend(u32::MAX.to_be_bytes()); // event_duration
+        payload.extend(7_u32.to_be_bytes()); // id
+        payload.extend([0xfc, 0x30]); // message_data
+        full_box(b"emsg", 0, &payload)
+    }
+
+    fn emsg_v1() -> Vec<u8> {
+        let mut payload = vec![];
+        payload.extend(1000_u32.to_be_bytes()); // timescale
+        payload.extend(5_000_000_000_u64.to_be_bytes()); // presentation_time
+        payload.extend(2500_u32.to_be_bytes()); // event_duration
+        payload.extend(8_u32.to_be_bytes()); // id
+        payload.extend(b"urn:example:id3\0\0");
+        full_box(b"emsg", 1, &payload)
+    }
+
+    #[test]
+    fn versions() {
+        let mut data = emsg_v0();
+        data.extend(full_box(b"moof", 0, &[]));
+        data.extend(emsg_v1());
+        let emsg = Emsg::new(&data).unwrap();
+
+        assert_eq!(emsg.messages.len(), 2);
+
+        let v0 = &emsg.messages[0];
+        assert_eq!(v0.scheme_id_uri, "urn:scte:scte35:2013:bin");
+        assert_eq!(v0.value, "splice");
+        assert_eq!(v0.timescale, 90000);
+        assert_eq!(v0.presentation_time_delta, Some(180000));
+        assert_eq!(v0.presentation_time, None);
+        assert_eq!(v0.event_duration, u32::MAX);
+        assert_eq!(v0.id, 7);
+        assert_eq!(v0.message_data, [0xfc, 0x30]);
+
+        let v1 = &emsg.messages[1];
+        assert_eq!(v1.scheme_id_uri, "urn:example:id3");
+        assert_eq!(v1.value, "");
+        assert_eq!(v1.timescale, 1000);
+        assert_eq!(v1.presentation_time_delta, None);
+        assert_eq!(v1.presentation_time, Some(5_000_000_000));
+        assert_eq!(v1.event_duration, 2500);
+        assert_eq!(v1.id, 8);
+        assert!(v1.message_data.is_empty());
+    }
+
+    #[test]
+    fn invalid_timescale() {
+        let mut payload = b"urn:example\0\0".to_vec();
+        payload.extend([0; 16]);
+        assert!(Emsg::new(&full_box(b"emsg", 0, &payload)).is_err());
+    }
+}
@@ -8,7 +8,10 @@
 //! The following are a list of [Cargo features](https://doc.rust-lang.org/stable/cargo/reference/features.html#the-features-section) that can be
 //! enabled or disabled:
 //!
+//! - **emsg**: Enables support for parsing `EMSG` (event message) boxes.
 //! - **pssh**: Enables support for parsing `PSSH` boxes.
+//! - **sidx**: Enables support for parsing `SIDX` (segment index) boxes.
+//! - **text-cea**: Enables support for extracting CEA-608/708 closed captions from video.
 //! - **text-ttml**: Enables support for extracting ttml subtitles.
 //! - **text-vtt**: Enables support for extracting vtt subtitles.
 
@@ -16,12 +19,23 @@ mod error;
 mod parser;
 mod reader;
 
+#[cfg(feature = "emsg")]
+#[cfg_attr(docsrs, doc(cfg(feature = "emsg")))]
+pub mod emsg;
+
 #[cfg(feature = "pssh")]
 #[cfg_attr(docsrs, doc(cfg(feature = "pssh")))]
 pub mod pssh;
 
-#[cfg(any(feature = "text-ttml", feature = "text-vtt"))]
-#[cfg_attr(docsrs, doc(cfg(any(feature = "text-ttml", feature = "text-vtt"))))]
+#[cfg(feature = "sidx")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sidx")))]
+pub mod sidx;
+
+#[cfg(any(feature = "text-cea", feature = "text-ttml", feature = "text-vtt"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(feature = "text-cea", feature = "text-ttml", feature = "text-vtt")))
+)]
 pub mod text;
 
 pub use error::Error;
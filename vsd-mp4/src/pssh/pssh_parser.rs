@@ -104,6 +104,23 @@ impl Pssh {
         })
     }
 
+    /// Parse key ids from a PlayReady object (e.g. `mspr:pro` element of DASH playlists),
+    /// which is the data of a PlayReady `pssh` box without the box header.
+    pub fn from_playready_object(data: &[u8]) -> Result<Self> {
+        let mut key_ids: Vec<KeyId> = vec![];
+
+        for key_id in playready::parse(data)? {
+            if !key_ids.iter().any(|x| x.value == key_id.value) {
+                key_ids.push(key_id)
+            }
+        }
+
+        Ok(Self {
+            key_ids,
+            system_ids: vec![PLAYREADY_SYSTEM_ID.to_owned()],
+        })
+    }
+
     fn parse_pssh_box(&mut self, _box: &mut ParsedBox) -> Result<()> {
         if _box.version.is_none() {
             return Err(Error::new(
@@ -100,6 +100,24 @@ impl Reader {
             .collect::<Vec<_>>())
     }
 
+    /// Read a null terminated utf-8 string, null byte is consumed but not returned.
+    pub fn read_terminated_string(&mut self) -> Result<String> {
+        let mut buf = vec![];
+        let mut byte = [0; 1];
+
+        loop {
+            self.inner.read_exact(&mut byte)?;
+
+            if byte[0] == 0 {
+                break;
+            }
+
+            buf.push(byte[0]);
+        }
+
+        String::from_utf8(buf).map_err(|x| Error::new(ErrorKind::InvalidData, x))
+    }
+
     pub fn skip(&mut self, bytes: u64) -> Result<()> {
         let position = self.get_position() + bytes;
 
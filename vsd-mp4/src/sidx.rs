@@ -0,0 +1,242 @@
+//! Mp4 `SIDX` box parser.
+
+/*
+    REFERENCES
+    ----------
+
+    1. https://github.com/shaka-project/shaka-player/blob/main/lib/media/mp4_segment_index_parser.js
+
+*/
+
+use crate::{parser::Mp4Parser, Error, ParsedBox, Result};
+use std::sync::{Arc, Mutex};
+
+/// Subsegment referenced by a `sidx` box.
+#[derive(Clone, Debug)]
+pub struct SidxReference {
+    /// Byte offset of the first byte of subsegment.
+    pub start: u64,
+    /// Byte offset of the last byte of subsegment (inclusive).
+    pub end: u64,
+    /// Presentation time of subsegment (in seconds).
+    pub start_time: f64,
+    /// Duration of subsegment (in seconds).
+    pub duration: f64,
+}
+
+/// Parse `SIDX` box from mp4 files.
+#[derive(Default)]
+pub struct Sidx {
+    /// Subsegments in presentation order, with absolute byte ranges.
+    pub references: Vec<SidxReference>,
+    /// Number of time units per second, used by `earliest_presentation_time` and subsegment durations.
+    pub timescale: u32,
+}
+
+impl Sidx {
+    /// Parse first `sidx` box found in data.
+    /// `sidx_offset` is the byte offset of data inside the media file,
+    /// it is used to compute absolute byte ranges of subsegments.
+    pub fn new(data: &[u8], sidx_offset: u64) -> Result<Self> {
+        let sidx = Arc::new(Mutex::new(None));
+        let sidx_c = sidx.clone();
+
+        Mp4Parser::default()
+            .full_box(
+                "sidx",
+                Arc::new(move |mut _box| {
+                    let mut sidx = sidx_c.lock().unwrap();
+
+                    if sidx.is_none() {
+                        *sidx = Some(Self::parse_sidx_box(&mut _box, sidx_offset)?);
+                    }
+
+                    Ok(())
+                }),
+            )
+            .parse(data, Some(true), None)?;
+
+        let sidx = sidx.lock().unwrap().take();
+        sidx.ok_or_else(|| Error::new("SIDX box not found"))
+    }
+
+    fn parse_sidx_box(_box: &mut ParsedBox, sidx_offset: u64) -> Result<Self> {
+        let Some(version) = _box.version else {
+            return Err(Error::new(
+                "SIDX boxes are full boxes and must have a valid version",
+            ));
+        };
+
+        // reference_ID
+        _box.reader
+            .skip(4)
+            .map_err(|_| Error::new_read_err("SIDX box reference id (u32)"))?;
+
+        let timescale = _box
+            .reader
+            .read_u32()
+            .map_err(|_| Error::new_read_err("SIDX box timescale (u32)"))?;
+
+        if timescale == 0 {
+            return Err(Error::new("SIDX box has invalid timescale (0)"));
+        }
+
+        let (earliest_presentation_time, first_offset) = if version == 0 {
+            (
+                _box.reader
+                    .read_u32()
+                    .map_err(|_| Error::new_read_err("SIDX box earliest presentation time (u32)"))?
+                    as u64,
+                _box.reader
+                    .read_u32()
+                    .map_err(|_| Error::new_read_err("SIDX box first offset (u32)"))?
+                    as u64,
+            )
+        } else {
+            (
+                _box.reader.read_u64().map_err(|_| {
+                    Error::new_read_err("SIDX box earliest presentation time (u64)")
+                })?,
+                _box.reader
+                    .read_u64()
+                    .map_err(|_| Error::new_read_err("SIDX box first offset (u64)"))?,
+            )
+        };
+
+        // reserved
+        _box.reader
+            .skip(2)
+            .map_err(|_| Error::new_read_err("SIDX box reserved (u16)"))?;
+
+        let reference_count = _box
+            .reader
+            .read_u16()
+            .map_err(|_| Error::new_read_err("SIDX box reference count (u16)"))?;
+
+        let mut unscaled_start_time = earliest_presentation_time;
+        let mut start_byte = sidx_offset + _box.start + _box.size as u64 + first_offset;
+        let mut references = vec![];
+
+        for _ in 0..reference_count {
+            // |reference_type| (1 bit) |referenced_size| (31 bits)
+            let chunk = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("SIDX box reference size (u32)"))?;
+            let reference_type = chunk >> 31;
+            let reference_size = (chunk & 0x7FFFFFFF) as u64;
+
+            let subsegment_duration = _box
+                .reader
+                .read_u32()
+                .map_err(|_| Error::new_read_err("SIDX box subsegment duration (u32)"))?;
+
+            // |starts_with_SAP| (1 bit) |SAP_type| (3 bits) |SAP_delta_time| (28 bits)
+            _box.reader
+                .skip(4)
+                .map_err(|_| Error::new_read_err("SIDX box stream access point (u32)"))?;
+
+            // If |reference_type| is 1 then the reference points to another SIDX box.
+            if reference_type == 1 {
+                return Err(Error::new("hierarchical SIDX boxes are not supported"));
+            }
+
+            if reference_size == 0 {
+                return Err(Error::new("SIDX box has a reference of size 0"));
+            }
+
+            references.push(SidxReference {
+                start: start_byte,
+                end: start_byte + reference_size - 1,
+                start_time: unscaled_start_time as f64 / timescale as f64,
+                duration: subsegment_duration as f64 / timescale as f64,
+            });
+
+            unscaled_start_time += subsegment_duration as u64;
+            start_byte += reference_size;
+        }
+
+        Ok(Self {
+            references,
+            timescale,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sidx;
+
+    fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        [&(8 + payload.len() as u32).to_be_bytes()[..], name, payload].concat()
+    }
+
+    /// `sidx` box with `(referenced_size, subsegment_duration)` media references.
+    fn sidx(version: u8, first_offset: u64, references: &[(u32, u32)]) -> Vec<u8> {
+        let mut payload = vec![version, 0, 0, 0];
+        payload.extend(1_u32.to_be_bytes()); // reference_ID
+        payload.extend(1000_u32.to_be_bytes()); // timescale
+
+        if version == 0 {
+            payload.extend(2000_u32.to_be_bytes());
+            payload.extend((first_offset as u32).to_be_bytes());
+        } else {
+            payload.extend(2000_u64.to_be_bytes());
+            payload.extend(first_offset.to_be_bytes());
+        }
+
+        payload.extend(0_u16.to_be_bytes()); // reserved
+        payload.extend((references.len() as u16).to_be_bytes());
+
+        for (size, duration) in references {
+            payload.extend(size.to_be_bytes());
+            payload.extend(duration.to_be_bytes());
+            payload.extend(0x9000_0000_u32.to_be_bytes()); // starts_with_SAP, SAP_type = 1
+        }
+
+        mp4_box(b"sidx", &payload)
+    }
+
+    #[test]
+    fn v0() {
+        let data = sidx(0, 0, &[(100, 4000), (200, 2000)]);
+        let sidx = Sidx::new(&data, 500).unwrap();
+
+        assert_eq!(sidx.timescale, 1000);
+        assert_eq!(sidx.references.len(), 2);
+
+        // Subsegments start right after the sidx box (at 500 + 56).
+        assert_eq!(sidx.references[0].start, 556);
+        assert_eq!(sidx.references[0].end, 655);
+        assert_eq!(sidx.references[0].start_time, 2.0);
+        assert_eq!(sidx.references[0].duration, 4.0);
+        assert_eq!(sidx.references[1].start, 656);
+        assert_eq!(sidx.references[1].end, 855);
+        assert_eq!(sidx.references[1].start_time, 6.0);
+        assert_eq!(sidx.references[1].duration, 2.0);
+    }
+
+    #[test]
+    fn v1_first_offset() {
+        // Boxes before sidx shift it within data, first offset is counted from its end.
+        let mut data = mp4_box(b"styp", b"msdh");
+        data.extend(sidx(1, 1000, &[(300, 1000)]));
+        let sidx = Sidx::new(&data, 0).unwrap();
+
+        assert_eq!(sidx.references.len(), 1);
+        assert_eq!(sidx.references[0].start, 12 + 52 + 1000);
+        assert_eq!(sidx.references[0].end, 12 + 52 + 1000 + 299);
+        assert_eq!(sidx.references[0].start_time, 2.0);
+    }
+
+    #[test]
+    fn invalid_references() {
+        let data = sidx(0, 0, &[(0x8000_0000 | 100, 1000)]);
+        assert!(Sidx::new(&data, 0).is_err());
+
+        let data = sidx(0, 0, &[(100, 1000), (0, 1000)]);
+        assert!(Sidx::new(&data, 0).is_err());
+
+        assert!(Sidx::new(&mp4_box(b"styp", b"msdh"), 0).is_err());
+    }
+}
@@ -17,7 +17,7 @@ pub(super) struct TFHDBox {
     pub(super) default_sample_duration: Option<u32>,
     /// If specified via flags, this overrides the default sample
     /// size in the Track Extends Box for this fragment
-    pub(super) _default_sample_size: Option<u32>,
+    pub(super) default_sample_size: Option<u32>,
     /// If specified via flags, this indicate the base data offset
     pub(super) _base_data_offset: Option<u64>,
 }
@@ -70,7 +70,7 @@ impl TFHDBox {
         Ok(Self {
             _track_id: track_id,
             default_sample_duration,
-            _default_sample_size: default_sample_size,
+            default_sample_size,
             _base_data_offset: base_data_offset,
         })
     }
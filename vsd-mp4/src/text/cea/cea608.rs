@@ -0,0 +1,504 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://github.com/shaka-project/shaka-player/blob/main/lib/cea/cea608_data_channel.js
+    2. https://github.com/shaka-project/shaka-player/blob/main/lib/cea/cea608_memory.js
+    3. https://en.wikipedia.org/wiki/EIA-608#Characters
+
+*/
+
+use crate::text::Cue;
+
+const ROWS: usize = 15;
+const COLUMNS: usize = 32;
+
+/// Row index of preamble address codes, indexed by `((b1 & 0x07) << 1) | ((b2 >> 5) & 0x01)`.
+const PAC_ROWS: [Option<usize>; 16] = [
+    Some(11),
+    None,
+    Some(1),
+    Some(2),
+    Some(3),
+    Some(4),
+    Some(12),
+    Some(13),
+    Some(14),
+    Some(15),
+    Some(5),
+    Some(6),
+    Some(7),
+    Some(8),
+    Some(9),
+    Some(10),
+];
+
+const SPECIAL_CHARACTERS: [char; 16] = [
+    '®', '°', '½', '¿', '™', '¢', '£', '♪', 'à', ' ', 'è', 'â', 'ê', 'î', 'ô', 'û',
+];
+
+const EXTENDED_SPANISH_FRENCH: [char; 32] = [
+    'Á', 'É', 'Ó', 'Ú', 'Ü', 'ü', '‘', '¡', '*', '\'', '—', '©', '℠', '•', '“', '”', 'À', 'Â', 'Ç',
+    'È', 'Ê', 'Ë', 'ë', 'Î', 'Ï', 'ï', 'Ô', 'Ù', 'ù', 'Û', '«', '»',
+];
+
+const EXTENDED_PORTUGUESE_GERMAN: [char; 32] = [
+    'Ã', 'ã', 'Í', 'Ì', 'ì', 'Ò', 'ò', 'Õ', 'õ', '{', '}', '\\', '^', '_', '|', '~', 'Ä', 'ä', 'Ö',
+    'ö', 'ß', '¥', '¤', '¦', 'Å', 'å', 'Ø', 'ø', '┌', '┐', '└', '┘',
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    PaintOn,
+    PopOn,
+    RollUp,
+    Text,
+}
+
+/// Caption memory with a cursor.
+#[derive(Clone)]
+struct Memory {
+    column: usize,
+    row: usize,
+    rows: Vec<Vec<char>>,
+}
+
+impl Memory {
+    fn new() -> Self {
+        Self {
+            column: 0,
+            row: ROWS - 1,
+            rows: vec![vec![' '; COLUMNS]; ROWS],
+        }
+    }
+
+    fn write(&mut self, character: char) {
+        // Characters beyond last column overwrite the last column.
+        let column = self.column.min(COLUMNS - 1);
+        self.rows[self.row][column] = character;
+        self.column = (column + 1).min(COLUMNS);
+    }
+
+    fn backspace(&mut self) {
+        if self.column > 0 {
+            self.column -= 1;
+            self.rows[self.row][self.column] = ' ';
+        }
+    }
+
+    fn erase_to_end_of_row(&mut self) {
+        for character in self.rows[self.row].iter_mut().skip(self.column) {
+            *character = ' ';
+        }
+    }
+
+    fn clear(&mut self) {
+        for row in &mut self.rows {
+            row.fill(' ');
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rows.iter().all(|x| x.iter().all(|y| *y == ' '))
+    }
+
+    /// Move rows of roll-up window one row up and clear the base row.
+    fn roll_up(&mut self, window: usize) {
+        let top = (self.row + 1).saturating_sub(window);
+
+        for row in 0..ROWS {
+            if row < top || row > self.row {
+                self.rows[row].fill(' ');
+            }
+        }
+
+        if top < self.row {
+            self.rows[top..=self.row].rotate_left(1);
+        }
+
+        self.rows[self.row].fill(' ');
+        self.column = 0;
+    }
+
+    /// Move roll-up window so that its base row becomes `row`.
+    fn move_base_row(&mut self, row: usize, window: usize) {
+        let row = row.max(window - 1);
+
+        if row != self.row {
+            let top = (self.row + 1).saturating_sub(window);
+            let content = self.rows[top..=self.row].to_vec();
+            self.clear();
+
+            let new_top = row + 1 - content.len();
+            for (i, content_row) in content.into_iter().enumerate() {
+                self.rows[new_top + i] = content_row;
+            }
+
+            self.row = row;
+        }
+    }
+
+    fn text(&self) -> String {
+        self.rows
+            .iter()
+            .map(|x| x.iter().collect::<String>().trim().to_owned())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Caption data channel (CC1, CC2, CC3 or CC4).
+struct Channel {
+    cues: Vec<Cue>,
+    displayed: Memory,
+    mode: Mode,
+    non_displayed: Memory,
+    roll_up_rows: usize,
+    /// Time since displayed memory is visible.
+    start_time: Option<f32>,
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            cues: vec![],
+            displayed: Memory::new(),
+            mode: Mode::PopOn,
+            non_displayed: Memory::new(),
+            roll_up_rows: 2,
+            start_time: None,
+        }
+    }
+
+    /// Memory which is currently being written.
+    fn memory(&mut self) -> Option<&mut Memory> {
+        match self.mode {
+            Mode::PaintOn | Mode::RollUp => Some(&mut self.displayed),
+            Mode::PopOn => Some(&mut self.non_displayed),
+            Mode::Text => None,
+        }
+    }
+
+    fn emit(&mut self, end_time: f32) {
+        if let Some(start_time) = self.start_time.take() {
+            let payload = self.displayed.text();
+
+            if !payload.is_empty() && end_time > start_time {
+                self.cues.push(Cue {
+                    end_time,
+                    _id: String::new(),
+                    payload,
+                    settings: String::new(),
+                    start_time,
+                });
+            }
+        }
+    }
+
+    fn write(&mut self, character: char, pts: f32) {
+        if matches!(self.mode, Mode::PaintOn | Mode::RollUp) && self.start_time.is_none() {
+            self.start_time = Some(pts);
+        }
+
+        if let Some(memory) = self.memory() {
+            memory.write(character);
+        }
+    }
+
+    fn backspace(&mut self) {
+        if let Some(memory) = self.memory() {
+            memory.backspace();
+        }
+    }
+
+    fn preamble_address(&mut self, row: usize, column: usize) {
+        let mode = self.mode;
+        let roll_up_rows = self.roll_up_rows;
+
+        if let Some(memory) = self.memory() {
+            if mode == Mode::RollUp {
+                memory.move_base_row(row, roll_up_rows);
+            } else {
+                memory.row = row;
+            }
+
+            memory.column = column;
+        }
+    }
+
+    fn tab_offset(&mut self, columns: usize) {
+        if let Some(memory) = self.memory() {
+            memory.column = (memory.column + columns).min(COLUMNS - 1);
+        }
+    }
+
+    fn control(&mut self, code: u8, pts: f32) {
+        match code {
+            // Resume caption loading
+            0x20 => self.mode = Mode::PopOn,
+            // Backspace
+            0x21 => self.backspace(),
+            // Delete to end of row
+            0x24 => {
+                if let Some(memory) = self.memory() {
+                    memory.erase_to_end_of_row();
+                }
+            }
+            // Roll-up captions (2, 3 or 4 rows)
+            0x25..=0x27 => {
+                if self.mode != Mode::RollUp {
+                    self.emit(pts);
+                    self.displayed.clear();
+                    self.non_displayed.clear();
+                    self.displayed.row = ROWS - 1;
+                    self.displayed.column = 0;
+                }
+
+                self.mode = Mode::RollUp;
+                self.roll_up_rows = (code - 0x23) as usize;
+            }
+            // Resume direct captioning
+            0x29 => self.mode = Mode::PaintOn,
+            // Text restart and resume text display
+            0x2A | 0x2B => self.mode = Mode::Text,
+            // Erase displayed memory
+            0x2C => {
+                self.emit(pts);
+                self.displayed.clear();
+            }
+            // Carriage return
+            0x2D if self.mode == Mode::RollUp => {
+                self.emit(pts);
+                self.displayed.roll_up(self.roll_up_rows);
+
+                if !self.displayed.is_empty() {
+                    self.start_time = Some(pts);
+                }
+            }
+            // Erase non-displayed memory
+            0x2E => self.non_displayed.clear(),
+            // End of caption (flip memories)
+            0x2F => {
+                self.emit(pts);
+                std::mem::swap(&mut self.displayed, &mut self.non_displayed);
+                self.mode = Mode::PopOn;
+
+                if !self.displayed.is_empty() {
+                    self.start_time = Some(pts);
+                }
+            }
+            // Alarm off, alarm on and flash on
+            _ => (),
+        }
+    }
+}
+
+/// Decoder of CEA-608 byte pairs carried in both fields of line 21.
+pub(super) struct Cea608Decoder {
+    channels: [Channel; 4],
+    /// Active channel (0 or 1) and last control code of each field.
+    fields: [(usize, Option<(u8, u8)>); 2],
+}
+
+impl Cea608Decoder {
+    pub(super) fn new() -> Self {
+        Self {
+            channels: [
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+            ],
+            fields: [(0, None), (0, None)],
+        }
+    }
+
+    pub(super) fn decode(&mut self, field: usize, b1: u8, b2: u8, pts: f32) {
+        // Strip odd parity bits.
+        let (b1, b2) = (b1 & 0x7F, b2 & 0x7F);
+
+        if b1 == 0 && b2 == 0 {
+            return;
+        }
+
+        if (0x10..=0x1F).contains(&b1) {
+            // Control codes are usually transmitted twice for redundancy.
+            if self.fields[field].1 == Some((b1, b2)) {
+                self.fields[field].1 = None;
+                return;
+            }
+
+            self.fields[field].1 = Some((b1, b2));
+            self.fields[field].0 = ((b1 & 0x08) >> 3) as usize;
+            let channel = &mut self.channels[field * 2 + self.fields[field].0];
+            let code = b1 & 0xF7;
+
+            match (code, b2) {
+                (0x14 | 0x15, 0x20..=0x2F) => channel.control(b2, pts),
+                (0x17, 0x21..=0x23) => channel.tab_offset((b2 - 0x20) as usize),
+                // Mid-row codes are displayed as a space.
+                (0x11, 0x20..=0x2F) => channel.write(' ', pts),
+                (0x11, 0x30..=0x3F) => channel.write(SPECIAL_CHARACTERS[(b2 - 0x30) as usize], pts),
+                // Extended characters replace the preceding standard character.
+                (0x12, 0x20..=0x3F) => {
+                    channel.backspace();
+                    channel.write(EXTENDED_SPANISH_FRENCH[(b2 - 0x20) as usize], pts);
+                }
+                (0x13, 0x20..=0x3F) => {
+                    channel.backspace();
+                    channel.write(EXTENDED_PORTUGUESE_GERMAN[(b2 - 0x20) as usize], pts);
+                }
+                (0x10..=0x17, 0x40..=0x7F) => {
+                    if let Some(row) = PAC_ROWS[(((b1 & 0x07) << 1) | ((b2 >> 5) & 0x01)) as usize]
+                    {
+                        let column = if b2 & 0x10 != 0 {
+                            ((b2 & 0x0E) >> 1) as usize * 4
+                        } else {
+                            0
+                        };
+
+                        channel.preamble_address(row - 1, column);
+                    }
+                }
+                _ => (),
+            }
+        } else if b1 >= 0x20 {
+            self.fields[field].1 = None;
+            let channel = &mut self.channels[field * 2 + self.fields[field].0];
+            channel.write(basic_character(b1), pts);
+
+            if b2 >= 0x20 {
+                channel.write(basic_character(b2), pts);
+            }
+        }
+    }
+
+    /// Cues of all data channels as (CC1, CC2, CC3, CC4).
+    pub(super) fn finish(mut self, end_time: f32) -> Vec<(String, Vec<Cue>)> {
+        self.channels
+            .iter_mut()
+            .enumerate()
+            .map(|(i, channel)| {
+                channel.emit(end_time);
+                (format!("CC{}", i + 1), std::mem::take(&mut channel.cues))
+            })
+            .collect()
+    }
+}
+
+fn basic_character(byte: u8) -> char {
+    match byte {
+        0x2A => 'á',
+        0x5C => 'é',
+        0x5E => 'í',
+        0x5F => 'ó',
+        0x60 => 'ú',
+        0x7B => 'ç',
+        0x7C => '÷',
+        0x7D => 'Ñ',
+        0x7E => 'ñ',
+        0x7F => '█',
+        x => x as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cea608Decoder;
+
+    fn cues(pairs: &[(u8, u8, f32)], end_time: f32) -> Vec<(String, f32, f32)> {
+        let mut decoder = Cea608Decoder::new();
+
+        for (b1, b2, pts) in pairs {
+            decoder.decode(0, *b1, *b2, *pts);
+        }
+
+        decoder.finish(end_time)[0]
+            .1
+            .iter()
+            .map(|x| (x.payload.clone(), x.start_time, x.end_time))
+            .collect()
+    }
+
+    #[test]
+    fn pop_on() {
+        assert_eq!(
+            cues(
+                &[
+                    // Resume caption loading (sent twice)
+                    (0x94, 0x20, 0.0),
+                    (0x94, 0x20, 0.0),
+                    // Erase non-displayed memory
+                    (0x94, 0xAE, 0.0),
+                    // Preamble address code, row 15 column 0
+                    (0x94, 0xE0, 0.0),
+                    (0xC8, 0x49, 0.5),
+                    // Special character ♪ (0x11 0x37) with odd parity
+                    (0x91, 0x37, 0.5),
+                    // End of caption
+                    (0x94, 0x2F, 1.0),
+                    // Erase displayed memory
+                    (0x94, 0x2C, 3.0),
+                ],
+                10.0
+            ),
+            vec![("HI♪".to_owned(), 1.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn roll_up() {
+        assert_eq!(
+            cues(
+                &[
+                    // Roll-up captions, 2 rows
+                    (0x94, 0x25, 0.0),
+                    (0x41, 0x80, 1.0),
+                    // Carriage return
+                    (0x94, 0xAD, 2.0),
+                    (0xC2, 0x80, 2.5),
+                    (0x94, 0xAD, 3.0),
+                ],
+                4.0
+            ),
+            vec![
+                ("A".to_owned(), 1.0, 2.0),
+                ("A\nB".to_owned(), 2.0, 3.0),
+                ("B".to_owned(), 3.0, 4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn channels() {
+        let mut decoder = Cea608Decoder::new();
+        // Resume direct captioning on CC2 (data channel 2 of field 1)
+        decoder.decode(0, 0x1C, 0x29, 0.0);
+        decoder.decode(0, 0x41, 0x42, 1.0);
+        // Resume direct captioning on CC3 (data channel 1 of field 2)
+        decoder.decode(1, 0x14, 0x29, 0.0);
+        decoder.decode(1, 0x43, 0x00, 1.0);
+
+        let channels = decoder
+            .finish(2.0)
+            .into_iter()
+            .map(|(channel, cues)| {
+                (
+                    channel,
+                    cues.into_iter().map(|x| x.payload).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            channels,
+            vec![
+                ("CC1".to_owned(), vec![]),
+                ("CC2".to_owned(), vec!["AB".to_owned()]),
+                ("CC3".to_owned(), vec!["C".to_owned()]),
+                ("CC4".to_owned(), vec![]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,577 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://github.com/shaka-project/shaka-player/blob/main/lib/cea/dtvcc_packet_builder.js
+    2. https://github.com/shaka-project/shaka-player/blob/main/lib/cea/cea708_service.js
+    3. https://github.com/shaka-project/shaka-player/blob/main/lib/cea/cea708_window.js
+
+*/
+
+use crate::text::Cue;
+use std::collections::HashMap;
+
+/// Assembles DTVCC packets from `cc_data` triplets of type 2 and 3.
+#[derive(Default)]
+pub(super) struct PacketBuilder {
+    data: Vec<u8>,
+    pts: f32,
+    size: usize,
+}
+
+impl PacketBuilder {
+    /// Push a byte pair, returns a complete packet with its start time when available.
+    pub(super) fn push(&mut self, start: bool, b1: u8, b2: u8, pts: f32) -> Option<(Vec<u8>, f32)> {
+        let mut packet = None;
+
+        if start {
+            if self.size != 0 && !self.data.is_empty() {
+                packet = Some((std::mem::take(&mut self.data), self.pts));
+            }
+
+            let size_code = (b1 & 0x3F) as usize;
+            // Packet size includes header byte.
+            self.size = if size_code == 0 {
+                127
+            } else {
+                size_code * 2 - 1
+            };
+            self.data = vec![b2];
+            self.pts = pts;
+        } else if self.size != 0 {
+            self.data.extend_from_slice(&[b1, b2]);
+        } else {
+            return None;
+        }
+
+        if self.data.len() >= self.size {
+            self.data.truncate(self.size);
+            self.size = 0;
+            return Some((std::mem::take(&mut self.data), self.pts));
+        }
+
+        packet
+    }
+}
+
+struct Window {
+    columns: usize,
+    pen_column: usize,
+    pen_row: usize,
+    rows: Vec<Vec<char>>,
+    /// Time since window content is visible.
+    start_time: Option<f32>,
+    visible: bool,
+}
+
+impl Window {
+    fn text(&self) -> String {
+        self.rows
+            .iter()
+            .map(|x| x.iter().collect::<String>().trim().to_owned())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn clear(&mut self) {
+        for row in &mut self.rows {
+            row.fill(' ');
+        }
+
+        self.pen_row = 0;
+        self.pen_column = 0;
+    }
+
+    fn write(&mut self, character: char, pts: f32) {
+        if self.visible && self.start_time.is_none() {
+            self.start_time = Some(pts);
+        }
+
+        if self.pen_row < self.rows.len() && self.pen_column < self.columns {
+            self.rows[self.pen_row][self.pen_column] = character;
+            self.pen_column += 1;
+        }
+    }
+}
+
+/// Decoder of a single caption service.
+#[derive(Default)]
+pub(super) struct Service {
+    cues: Vec<Cue>,
+    current_window: Option<usize>,
+    windows: HashMap<usize, Window>,
+}
+
+impl Service {
+    /// Decode a service block.
+    pub(super) fn decode(&mut self, data: &[u8], pts: f32) {
+        let mut i = 0;
+
+        while i < data.len() {
+            let code = data[i];
+            i += 1;
+
+            match code {
+                // End of text
+                0x03 => (),
+                // Backspace
+                0x08 => {
+                    if let Some(window) = self.window() {
+                        if window.pen_column > 0 {
+                            window.pen_column -= 1;
+                            window.rows[window.pen_row][window.pen_column] = ' ';
+                        }
+                    }
+                }
+                // Form feed
+                0x0C => {
+                    self.emit(self.current_window, pts);
+
+                    if let Some(window) = self.window() {
+                        window.clear();
+                    }
+                }
+                // Carriage return
+                0x0D => self.carriage_return(pts),
+                // Horizontal carriage return
+                0x0E => {
+                    if let Some(window) = self.window() {
+                        window.rows[window.pen_row].fill(' ');
+                        window.pen_column = 0;
+                    }
+                }
+                // Extended code set
+                0x10 => {
+                    let Some(&code) = data.get(i) else {
+                        break;
+                    };
+                    i += 1;
+
+                    match code {
+                        // C2 codes have no meaning but parameters of variable length.
+                        0x00..=0x07 => (),
+                        0x08..=0x0F => i += 1,
+                        0x10..=0x17 => i += 2,
+                        0x18..=0x1F => i += 3,
+                        0x20..=0x7F => {
+                            if let Some(character) = g2_character(code) {
+                                self.write(character, pts);
+                            }
+                        }
+                        // C3 codes
+                        0x80..=0x87 => i += 4,
+                        0x88..=0x8F => i += 5,
+                        // Variable length commands are not supported.
+                        0x90..=0x9F => break,
+                        // G3 contains only [CC] icon.
+                        _ => (),
+                    }
+                }
+                0x11..=0x17 => i += 1,
+                // P16 (16 bit character)
+                0x18 => {
+                    if let Some(bytes) = data.get(i..(i + 2)) {
+                        if let Some(character) =
+                            char::from_u32(u16::from_be_bytes([bytes[0], bytes[1]]) as u32)
+                        {
+                            self.write(character, pts);
+                        }
+                    }
+
+                    i += 2;
+                }
+                0x19..=0x1F => i += 2,
+                0x7F => self.write('♪', pts),
+                0x20..=0x7E | 0xA0..=0xFF => self.write(code as char, pts),
+                // Set current window
+                0x80..=0x87 => {
+                    let id = (code - 0x80) as usize;
+
+                    if self.windows.contains_key(&id) {
+                        self.current_window = Some(id);
+                    }
+                }
+                // Clear, display, hide, toggle and delete windows
+                0x88..=0x8C => {
+                    let Some(&bitmap) = data.get(i) else {
+                        break;
+                    };
+                    i += 1;
+
+                    for id in (0..8).filter(|x| bitmap & (1 << x) != 0) {
+                        self.window_command(code, id, pts);
+                    }
+                }
+                // Delay
+                0x8D => i += 1,
+                // Delay cancel
+                0x8E => (),
+                // Reset
+                0x8F => {
+                    for id in 0..8 {
+                        self.window_command(0x8C, id, pts);
+                    }
+
+                    self.current_window = None;
+                }
+                // Set pen attributes
+                0x90 => i += 2,
+                // Set pen color
+                0x91 => i += 3,
+                // Set pen location
+                0x92 => {
+                    if let Some(bytes) = data.get(i..(i + 2)) {
+                        let (row, column) =
+                            ((bytes[0] & 0x0F) as usize, (bytes[1] & 0x3F) as usize);
+
+                        if let Some(window) = self.window() {
+                            window.pen_row = row.min(window.rows.len() - 1);
+                            window.pen_column = column.min(window.columns - 1);
+                        }
+                    }
+
+                    i += 2;
+                }
+                // Set window attributes
+                0x97 => i += 4,
+                // Define window
+                0x98..=0x9F => {
+                    let Some(bytes) = data.get(i..(i + 6)) else {
+                        break;
+                    };
+                    i += 6;
+
+                    let id = (code - 0x98) as usize;
+                    let visible = bytes[0] & 0x20 != 0;
+                    let rows = (bytes[3] & 0x0F) as usize + 1;
+                    let columns = (bytes[4] & 0x3F) as usize + 1;
+
+                    if let Some(window) = self.windows.get_mut(&id) {
+                        window.columns = columns;
+                        window.rows.resize(rows, vec![' '; columns]);
+
+                        for row in &mut window.rows {
+                            row.resize(columns, ' ');
+                        }
+
+                        window.pen_row = window.pen_row.min(rows - 1);
+                        window.pen_column = window.pen_column.min(columns - 1);
+                    } else {
+                        self.windows.insert(
+                            id,
+                            Window {
+                                columns,
+                                pen_column: 0,
+                                pen_row: 0,
+                                rows: vec![vec![' '; columns]; rows],
+                                start_time: None,
+                                visible: false,
+                            },
+                        );
+                    }
+
+                    self.window_command(if visible { 0x89 } else { 0x8A }, id, pts);
+                    self.current_window = Some(id);
+                }
+                // Reserved
+                _ => (),
+            }
+        }
+    }
+
+    fn window(&mut self) -> Option<&mut Window> {
+        self.current_window.and_then(|x| self.windows.get_mut(&x))
+    }
+
+    fn write(&mut self, character: char, pts: f32) {
+        if let Some(window) = self.window() {
+            window.write(character, pts);
+        }
+    }
+
+    fn carriage_return(&mut self, pts: f32) {
+        let Some(id) = self.current_window else {
+            return;
+        };
+
+        let scroll = self
+            .windows
+            .get(&id)
+            .map(|x| x.pen_row + 1 >= x.rows.len())
+            .unwrap_or(false);
+
+        if scroll {
+            self.emit(Some(id), pts);
+        }
+
+        if let Some(window) = self.windows.get_mut(&id) {
+            if scroll {
+                window.rows.rotate_left(1);
+                window.rows.last_mut().unwrap().fill(' ');
+
+                if window.visible && !window.text().is_empty() {
+                    window.start_time = Some(pts);
+                }
+            } else {
+                window.pen_row += 1;
+            }
+
+            window.pen_column = 0;
+        }
+    }
+
+    fn window_command(&mut self, code: u8, id: usize, pts: f32) {
+        if !self.windows.contains_key(&id) {
+            return;
+        }
+
+        match code {
+            // Clear
+            0x88 => {
+                self.emit(Some(id), pts);
+                self.windows.get_mut(&id).unwrap().clear();
+            }
+            // Display
+            0x89 => {
+                let window = self.windows.get_mut(&id).unwrap();
+
+                if !window.visible {
+                    window.visible = true;
+
+                    if !window.text().is_empty() {
+                        window.start_time = Some(pts);
+                    }
+                }
+            }
+            // Hide
+            0x8A => {
+                self.emit(Some(id), pts);
+                self.windows.get_mut(&id).unwrap().visible = false;
+            }
+            // Toggle
+            0x8B => {
+                let visible = self.windows[&id].visible;
+                self.window_command(if visible { 0x8A } else { 0x89 }, id, pts);
+            }
+            // Delete
+            0x8C => {
+                self.emit(Some(id), pts);
+                self.windows.remove(&id);
+
+                if self.current_window == Some(id) {
+                    self.current_window = None;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn emit(&mut self, id: Option<usize>, end_time: f32) {
+        let Some(window) = id.and_then(|x| self.windows.get_mut(&x)) else {
+            return;
+        };
+
+        if let Some(start_time) = window.start_time.take() {
+            let payload = window.text();
+
+            if !payload.is_empty() && end_time > start_time {
+                self.cues.push(Cue {
+                    end_time,
+                    _id: String::new(),
+                    payload,
+                    settings: String::new(),
+                    start_time,
+                });
+            }
+        }
+    }
+
+    pub(super) fn finish(mut self, end_time: f32) -> Vec<Cue> {
+        let mut ids = self.windows.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+
+        for id in ids {
+            self.emit(Some(id), end_time);
+        }
+
+        self.cues
+            .sort_by(|x, y| x.start_time.total_cmp(&y.start_time));
+        self.cues
+    }
+}
+
+fn g2_character(code: u8) -> Option<char> {
+    Some(match code {
+        0x20 | 0x21 => ' ',
+        0x25 => '…',
+        0x2A => 'Š',
+        0x2C => 'Œ',
+        0x30 => '█',
+        0x31 => '‘',
+        0x32 => '’',
+        0x33 => '“',
+        0x34 => '”',
+        0x35 => '•',
+        0x39 => '™',
+        0x3A => 'š',
+        0x3C => 'œ',
+        0x3D => '℠',
+        0x3F => 'Ÿ',
+        0x76 => '⅛',
+        0x77 => '⅜',
+        0x78 => '⅝',
+        0x79 => '⅞',
+        0x7A => '│',
+        0x7B => '┐',
+        0x7C => '└',
+        0x7D => '─',
+        0x7E => '┘',
+        0x7F => '┌',
+        _ => return None,
+    })
+}
+
+/// Split a DTVCC packet into service blocks as (service number, block data).
+pub(super) fn service_blocks(packet: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut blocks = vec![];
+    let mut i = 0;
+
+    while i < packet.len() {
+        let mut service_number = (packet[i] >> 5) as usize;
+        let block_size = (packet[i] & 0x1F) as usize;
+        i += 1;
+
+        if service_number == 7 {
+            let Some(&extended) = packet.get(i) else {
+                break;
+            };
+            service_number = (extended & 0x3F) as usize;
+            i += 1;
+        }
+
+        // Null service block fills remaining packet.
+        if service_number == 0 || block_size == 0 {
+            break;
+        }
+
+        let Some(block) = packet.get(i..(i + block_size)) else {
+            break;
+        };
+        blocks.push((service_number, block));
+        i += block_size;
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{service_blocks, PacketBuilder, Service};
+
+    /// Define window command, 2 rows and 32 columns.
+    fn define_window(id: u8, visible: bool) -> [u8; 7] {
+        [
+            0x98 + id,
+            if visible { 0x20 } else { 0x00 },
+            0x00,
+            0x00,
+            0x01,
+            0x1F,
+            0x00,
+        ]
+    }
+
+    fn cues(service: Service, end_time: f32) -> Vec<(String, f32, f32)> {
+        service
+            .finish(end_time)
+            .into_iter()
+            .map(|x| (x.payload, x.start_time, x.end_time))
+            .collect()
+    }
+
+    #[test]
+    fn packets() {
+        let mut builder = PacketBuilder::default();
+        // Packet size code 2 (3 bytes after header)
+        assert_eq!(builder.push(true, 0x02, 0x22, 1.0), None);
+        assert_eq!(
+            builder.push(false, b'H', b'I', 1.1),
+            Some((vec![0x22, b'H', b'I'], 1.0))
+        );
+        // Incomplete packet is returned at start of next packet.
+        assert_eq!(builder.push(true, 0x43, 0x21, 2.0), None);
+        assert_eq!(builder.push(true, 0x82, 0x00, 3.0), Some((vec![0x21], 2.0)));
+        // Data without packet start is ignored.
+        let mut builder = PacketBuilder::default();
+        assert_eq!(builder.push(false, 0x00, 0x00, 0.0), None);
+    }
+
+    #[test]
+    fn blocks() {
+        let packet = [
+            // Service 1, 3 bytes
+            0x23, 0x41, 0x42, 0x43, // Extended service 8, 2 bytes
+            0xE2, 0x08, 0x44, 0x45, // Null service block
+            0x00, 0x00,
+        ];
+
+        assert_eq!(
+            service_blocks(&packet),
+            vec![(1, &[0x41, 0x42, 0x43][..]), (8, &[0x44, 0x45][..])]
+        );
+        // Block size beyond packet end.
+        assert!(service_blocks(&[0x25, 0x41]).is_empty());
+    }
+
+    #[test]
+    fn visible_window() {
+        let mut service = Service::default();
+        service.decode(&define_window(0, true), 0.0);
+        service.decode(b"HI", 1.0);
+        // Carriage return and text on second row.
+        service.decode(&[0x0D, b'Y', b'O'], 1.5);
+        // Clear window 0
+        service.decode(&[0x88, 0x01], 2.0);
+
+        assert_eq!(cues(service, 10.0), vec![("HI\nYO".to_owned(), 1.0, 2.0)]);
+    }
+
+    #[test]
+    fn hidden_window() {
+        let mut service = Service::default();
+        service.decode(&define_window(1, false), 0.0);
+        service.decode(b"POP", 1.0);
+        // Display window 1
+        service.decode(&[0x89, 0x02], 2.0);
+        // Toggle window 1
+        service.decode(&[0x8B, 0x02], 3.0);
+        service.decode(&[0x8B, 0x02], 4.0);
+
+        assert_eq!(
+            cues(service, 5.0),
+            vec![("POP".to_owned(), 2.0, 3.0), ("POP".to_owned(), 4.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn scroll() {
+        let mut service = Service::default();
+        service.decode(&define_window(0, true), 0.0);
+        service.decode(&[b'A', 0x0D, b'B'], 1.0);
+        // Carriage return on last row scrolls the window.
+        service.decode(&[0x0D, b'C'], 2.0);
+        // G2 character (ellipsis) and P16 character.
+        service.decode(&[0x10, 0x25, 0x18, 0x00, 0xE9], 2.5);
+        // Reset
+        service.decode(&[0x8F], 3.0);
+
+        assert_eq!(
+            cues(service, 5.0),
+            vec![
+                ("A\nB".to_owned(), 1.0, 2.0),
+                ("B\nC…é".to_owned(), 2.0, 3.0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use super::{
+    cea608::Cea608Decoder,
+    cea708::{self, PacketBuilder, Service},
+    CaptionPacket,
+};
+use crate::text::Subtitles;
+use std::collections::HashMap;
+
+/// Decode CEA-608 and CEA-708 closed captions into subtitles.
+#[derive(Default)]
+pub struct CeaDecoder {
+    packets: Vec<CaptionPacket>,
+}
+
+impl CeaDecoder {
+    /// Add caption packets extracted by [Mp4CeaParser](super::Mp4CeaParser) or [TsCeaParser](super::TsCeaParser).
+    pub fn extend<T: IntoIterator<Item = CaptionPacket>>(&mut self, packets: T) {
+        self.packets.extend(packets);
+    }
+
+    /// Decode all caption channels. Channels are named after `INSTREAM-ID` values used by HLS
+    /// i.e. `CC1` to `CC4` for CEA-608 and `SERVICE1` to `SERVICE63` for CEA-708.
+    /// Channels without any captions are not returned.
+    ///
+    /// # Arguments
+    ///
+    /// - `start_time` - Presentation time of the first video frame, which is subtracted from all cues.
+    pub fn decode(mut self, start_time: f64) -> Vec<(String, Subtitles)> {
+        // Caption data is stored in decoding order of frames.
+        self.packets.sort_by(|x, y| x.pts.total_cmp(&y.pts));
+
+        let mut cea608 = Cea608Decoder::new();
+        let mut builder = PacketBuilder::default();
+        let mut services: HashMap<usize, Service> = HashMap::new();
+        let mut end_time = start_time;
+
+        for packet in &self.packets {
+            // Cue times are relative, so they fit in f32 even when presentation times don't.
+            let pts = (packet.pts - start_time) as f32;
+            end_time = packet.pts;
+
+            for triplet in packet.data.chunks_exact(3) {
+                let dtvcc_packet = match triplet[0] & 0x03 {
+                    cc_type @ (0 | 1) => {
+                        cea608.decode(cc_type as usize, triplet[1], triplet[2], pts);
+                        None
+                    }
+                    cc_type => builder.push(cc_type == 3, triplet[1], triplet[2], pts),
+                };
+
+                if let Some((dtvcc_packet, pts)) = dtvcc_packet {
+                    for (service_number, block) in cea708::service_blocks(&dtvcc_packet) {
+                        services
+                            .entry(service_number)
+                            .or_default()
+                            .decode(block, pts);
+                    }
+                }
+            }
+        }
+
+        let end_time = (end_time - start_time) as f32;
+        let mut services = services.into_iter().collect::<Vec<_>>();
+        services.sort_by_key(|x| x.0);
+
+        cea608
+            .finish(end_time)
+            .into_iter()
+            .chain(services.into_iter().map(|(service_number, service)| {
+                (
+                    format!("SERVICE{}", service_number),
+                    service.finish(end_time),
+                )
+            }))
+            .filter(|x| !x.1.is_empty())
+            .map(|(channel, cues)| {
+                let cues = cues
+                    .into_iter()
+                    .map(|mut x| {
+                        x.start_time = x.start_time.max(0.0);
+                        x
+                    })
+                    .collect();
+                (channel, Subtitles::new(cues))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CaptionPacket, CeaDecoder};
+
+    #[test]
+    fn decode() {
+        // Presentation times close to PTS rollover (~26.5 hours) lose precision as f32.
+        let start_time = 95000.0;
+        let mut decoder = CeaDecoder::default();
+        decoder.extend([
+            // Packets are sorted by presentation time.
+            CaptionPacket {
+                // End of caption
+                data: vec![0xFC, 0x94, 0x2F],
+                pts: start_time + 1.001,
+            },
+            CaptionPacket {
+                // Resume caption loading, text and DTVCC packet start (window 0 definition)
+                data: vec![
+                    0xFC, 0x94, 0x20, 0xFC, 0xC8, 0x49, 0xFF, 0x05, 0x28, 0xFE, 0x98, 0x20, 0xFE,
+                    0x00, 0x00, 0xFE, 0x01, 0x1F, 0xFE, 0x00, 0x41,
+                ],
+                pts: start_time,
+            },
+            CaptionPacket {
+                // Erase displayed memory and DTVCC packet (clear window 0)
+                data: vec![0xFC, 0x94, 0x2C, 0xFF, 0x02, 0x22, 0xFE, 0x88, 0x01],
+                pts: start_time + 2.002,
+            },
+        ]);
+
+        let channels = decoder
+            .decode(start_time)
+            .into_iter()
+            .map(|(channel, subtitles)| (channel, subtitles.as_vtt()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            channels,
+            vec![
+                (
+                    "CC1".to_owned(),
+                    "WEBVTT\n\n00:00:01.001 --> 00:00:02.002 \nHI\n\n".to_owned()
+                ),
+                (
+                    "SERVICE1".to_owned(),
+                    "WEBVTT\n\n00:00:00.000 --> 00:00:02.002 \nA\n\n".to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,37 @@
+//! CEA-608/708 closed captions carried inside H.264/H.265 video streams.
+
+/*
+    REFERENCES
+    ----------
+
+    1. https://github.com/shaka-project/shaka-player/tree/main/lib/cea
+    2. https://en.wikipedia.org/wiki/EIA-608
+    3. https://en.wikipedia.org/wiki/CEA-708
+
+*/
+
+mod cea608;
+mod cea708;
+mod decoder;
+mod mp4_cea_parser;
+mod sei;
+mod ts_cea_parser;
+
+pub use decoder::CeaDecoder;
+pub use mp4_cea_parser::Mp4CeaParser;
+pub use ts_cea_parser::TsCeaParser;
+
+/// Closed caption data (`cc_data` triplets) of a single video frame.
+#[derive(Clone)]
+pub struct CaptionPacket {
+    pub(super) data: Vec<u8>,
+    /// Presentation time in seconds.
+    pub(super) pts: f64,
+}
+
+/// Video codec of the stream which carries closed captions.
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum VideoCodec {
+    H264,
+    H265,
+}
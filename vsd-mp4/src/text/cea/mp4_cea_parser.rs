@@ -0,0 +1,240 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://github.com/shaka-project/shaka-player/blob/main/lib/cea/mp4_cea_parser.js
+
+*/
+
+use super::{sei, CaptionPacket, VideoCodec};
+use crate::{
+    parser,
+    parser::Mp4Parser,
+    text::boxes::{MDHDBox, TFDTBox, TFHDBox, TRUNBox},
+    Error, Result,
+};
+use std::sync::{Arc, Mutex};
+
+/// Parse closed captions from SEI messages of fragmented mp4 (H.264/H.265) video.
+pub struct Mp4CeaParser {
+    /// The time scale of the video track.
+    pub timescale: u32,
+    codec: VideoCodec,
+    nalu_length_size: usize,
+    start_time: Option<f64>,
+}
+
+impl Mp4CeaParser {
+    /// Parse intialization segment, a valid `avcC` or `hvcC` box should be present.
+    pub fn parse_init(data: &[u8]) -> Result<Self> {
+        let timescale = Arc::new(Mutex::new(None));
+        let video = Arc::new(Mutex::new(None));
+
+        let timescale_c = timescale.clone();
+        let timescale_avcc = timescale.clone();
+        let timescale_hvcc = timescale.clone();
+        let video_avcc = video.clone();
+        let video_hvcc = video.clone();
+
+        Mp4Parser::default()
+            ._box("moov", Arc::new(parser::children))
+            ._box("trak", Arc::new(parser::children))
+            ._box("mdia", Arc::new(parser::children))
+            .full_box(
+                "mdhd",
+                Arc::new(move |mut _box| {
+                    let _box_version = _box.version.unwrap();
+                    if _box_version != 0 && _box_version != 1 {
+                        return Err(Error::new("MDHD box version can only be 0 or 1"));
+                    }
+                    let parsed_mdhd_box = MDHDBox::parse(&mut _box.reader, _box_version)?;
+                    *timescale_c.lock().unwrap() = Some(parsed_mdhd_box.timescale);
+                    Ok(())
+                }),
+            )
+            ._box("minf", Arc::new(parser::children))
+            ._box("stbl", Arc::new(parser::children))
+            .full_box("stsd", Arc::new(parser::sample_description))
+            ._box("avc1", Arc::new(parser::visual_sample_entry))
+            ._box("avc3", Arc::new(parser::visual_sample_entry))
+            ._box("hev1", Arc::new(parser::visual_sample_entry))
+            ._box("hvc1", Arc::new(parser::visual_sample_entry))
+            ._box("encv", Arc::new(parser::visual_sample_entry))
+            ._box(
+                "avcC",
+                parser::alldata(Arc::new(move |data| {
+                    let nalu_length_size = data
+                        .get(4)
+                        .ok_or_else(|| Error::new_read_err("AVCC box length size minus one"))?
+                        & 0x03;
+                    *video_avcc.lock().unwrap() = Some((
+                        VideoCodec::H264,
+                        nalu_length_size as usize + 1,
+                        *timescale_avcc.lock().unwrap(),
+                    ));
+                    Ok(())
+                })),
+            )
+            ._box(
+                "hvcC",
+                parser::alldata(Arc::new(move |data| {
+                    let nalu_length_size = data
+                        .get(21)
+                        .ok_or_else(|| Error::new_read_err("HVCC box length size minus one"))?
+                        & 0x03;
+                    *video_hvcc.lock().unwrap() = Some((
+                        VideoCodec::H265,
+                        nalu_length_size as usize + 1,
+                        *timescale_hvcc.lock().unwrap(),
+                    ));
+                    Ok(())
+                })),
+            )
+            .parse(data, None, None)?;
+
+        let video = *video.lock().unwrap();
+
+        match video {
+            Some((codec, nalu_length_size, Some(timescale))) => Ok(Self {
+                timescale,
+                codec,
+                nalu_length_size,
+                start_time: None,
+            }),
+            Some(_) => Err(Error::new(
+                "Missing timescale (should exist inside MDHD box)",
+            )),
+            None => Err(Error::new("AVCC or HVCC box not found")),
+        }
+    }
+
+    /// Parse media segments, only if valid `mdat` box(s) are present.
+    pub fn parse_media(&mut self, data: &[u8]) -> Result<Vec<CaptionPacket>> {
+        let base_time = Arc::new(Mutex::new(0_u64));
+        let default_duration = Arc::new(Mutex::new(None));
+        let default_size = Arc::new(Mutex::new(None));
+        let samples = Arc::new(Mutex::new(vec![]));
+        let packets = Arc::new(Mutex::new(vec![]));
+        let start_time = Arc::new(Mutex::new(self.start_time));
+
+        let base_time_c = base_time.clone();
+        let default_duration_c = default_duration.clone();
+        let default_size_c = default_size.clone();
+        let samples_c = samples.clone();
+        let packets_c = packets.clone();
+        let start_time_c = start_time.clone();
+
+        let codec = self.codec;
+        let nalu_length_size = self.nalu_length_size;
+        let timescale = self.timescale as f64;
+
+        Mp4Parser::default()
+            ._box("moof", Arc::new(parser::children))
+            ._box("traf", Arc::new(parser::children))
+            .full_box(
+                "tfdt",
+                Arc::new(move |mut _box| {
+                    let _box_version = _box.version.unwrap();
+                    if _box_version != 0 && _box_version != 1 {
+                        return Err(Error::new("TFDT version can only be 0 or 1"));
+                    }
+
+                    let parsed_tfdt_box = TFDTBox::parse(&mut _box.reader, _box_version)?;
+                    *base_time_c.lock().unwrap() = parsed_tfdt_box.base_media_decode_time;
+                    Ok(())
+                }),
+            )
+            .full_box(
+                "tfhd",
+                Arc::new(move |mut _box| {
+                    if _box.flags.is_none() {
+                        return Err(Error::new("TFHD box should have a valid flags value"));
+                    }
+
+                    let parsed_tfhd_box = TFHDBox::parse(&mut _box.reader, _box.flags.unwrap())?;
+                    *default_duration_c.lock().unwrap() = parsed_tfhd_box.default_sample_duration;
+                    *default_size_c.lock().unwrap() = parsed_tfhd_box.default_sample_size;
+                    Ok(())
+                }),
+            )
+            .full_box(
+                "trun",
+                Arc::new(move |mut _box| {
+                    if _box.version.is_none() {
+                        return Err(Error::new("TRUN box should have a valid version value"));
+                    }
+                    if _box.flags.is_none() {
+                        return Err(Error::new("TRUN box should have a valid flags value"));
+                    }
+
+                    let parsed_trun_box = TRUNBox::parse(
+                        &mut _box.reader,
+                        _box.version.unwrap(),
+                        _box.flags.unwrap(),
+                    )?;
+                    samples_c
+                        .lock()
+                        .unwrap()
+                        .extend(parsed_trun_box.sample_data);
+                    Ok(())
+                }),
+            )
+            ._box(
+                "mdat",
+                parser::alldata(Arc::new(move |data| {
+                    let mut base_time = base_time.lock().unwrap();
+                    let default_duration = default_duration.lock().unwrap().unwrap_or(0);
+                    let default_size = *default_size.lock().unwrap();
+                    let samples = std::mem::take(&mut *samples.lock().unwrap());
+                    let mut start_time = start_time_c.lock().unwrap();
+                    let mut packets = packets_c.lock().unwrap();
+                    let mut offset = 0;
+
+                    for sample in samples {
+                        let size = sample.sample_size.or(default_size).ok_or_else(|| {
+                            Error::new("Sample size unknown, and no default found")
+                        })? as usize;
+                        let pts = (*base_time as i64
+                            + sample.sample_composition_time_offset.unwrap_or(0) as i64)
+                            as f64
+                            / timescale;
+
+                        *start_time = Some(start_time.map(|x| x.min(pts)).unwrap_or(pts));
+                        *base_time += sample.sample_duration.unwrap_or(default_duration) as u64;
+
+                        let sample_data = data.get(offset..(offset + size)).ok_or_else(|| {
+                            Error::new_read_err(format!("sample data ({} bytes)", size))
+                        })?;
+                        offset += size;
+
+                        let mut i = 0;
+
+                        while i + nalu_length_size <= sample_data.len() {
+                            let nalu_size = sample_data[i..(i + nalu_length_size)]
+                                .iter()
+                                .fold(0, |x, y| (x << 8) | *y as usize);
+                            i += nalu_length_size;
+
+                            if let Some(nalu) = sample_data.get(i..(i + nalu_size)) {
+                                packets.extend(sei::parse_nalu(codec, nalu, pts));
+                            }
+
+                            i += nalu_size;
+                        }
+                    }
+
+                    Ok(())
+                })),
+            )
+            .parse(data, Some(false), None)?;
+
+        self.start_time = *start_time.lock().unwrap();
+        let packets = std::mem::take(&mut *packets.lock().unwrap());
+        Ok(packets)
+    }
+
+    /// Presentation time of the earliest video frame parsed so far.
+    pub fn start_time(&self) -> Option<f64> {
+        self.start_time
+    }
+}
@@ -0,0 +1,141 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://github.com/shaka-project/shaka-player/blob/main/lib/cea/sei_processor.js
+    2. https://github.com/shaka-project/shaka-player/blob/main/lib/cea/mp4_cea_parser.js
+    3. ANSI/SCTE 128-1 (user_data_registered_itu_t_t35 with ATSC1_data)
+
+*/
+
+use super::{CaptionPacket, VideoCodec};
+
+/// Extract closed caption data from a NAL unit, if it is a SEI message.
+pub(super) fn parse_nalu(codec: VideoCodec, nalu: &[u8], pts: f64) -> Option<CaptionPacket> {
+    let header_size = match codec {
+        VideoCodec::H264 if nalu.first().map(|x| x & 0x1F) == Some(6) => 1,
+        // Prefix and suffix SEI messages.
+        VideoCodec::H265 if matches!(nalu.first().map(|x| (x >> 1) & 0x3F), Some(39 | 40)) => 2,
+        _ => return None,
+    };
+
+    let data = parse_sei(&remove_emulation_prevention(nalu.get(header_size..)?));
+
+    if data.is_empty() {
+        None
+    } else {
+        Some(CaptionPacket { data, pts })
+    }
+}
+
+/// Iterate over NAL units of an Annex B byte stream (start code prefixed).
+pub(super) fn annex_b_nalus(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = vec![];
+    let mut i = 0;
+
+    while i + 2 < data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+
+    let mut ends = starts.iter().skip(1).map(|x| x - 3).collect::<Vec<_>>();
+    ends.push(data.len());
+
+    starts.into_iter().zip(ends).map(|(start, mut end)| {
+        // Trailing zero bytes belong to the next four byte start code.
+        while end > start && data[end - 1] == 0 {
+            end -= 1;
+        }
+
+        &data[start..end]
+    })
+}
+
+fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+
+    rbsp
+}
+
+/// Parse SEI messages and collect `cc_data` triplets of `user_data_registered_itu_t_t35` payloads.
+fn parse_sei(data: &[u8]) -> Vec<u8> {
+    let mut cc_data = vec![];
+    let mut i = 0;
+
+    // rbsp_trailing_bits starts with 0x80.
+    while i < data.len() && data[i] != 0x80 {
+        let mut payload_type = 0;
+
+        while i < data.len() && data[i] == 0xFF {
+            payload_type += 255;
+            i += 1;
+        }
+
+        payload_type += *data.get(i).unwrap_or(&0) as usize;
+        i += 1;
+
+        let mut payload_size = 0;
+
+        while i < data.len() && data[i] == 0xFF {
+            payload_size += 255;
+            i += 1;
+        }
+
+        payload_size += *data.get(i).unwrap_or(&0) as usize;
+        i += 1;
+
+        let Some(payload) = data.get(i..(i + payload_size)) else {
+            break;
+        };
+
+        if payload_type == 4 {
+            parse_itu_t_t35(payload, &mut cc_data);
+        }
+
+        i += payload_size;
+    }
+
+    cc_data
+}
+
+fn parse_itu_t_t35(payload: &[u8], cc_data: &mut Vec<u8>) {
+    // country_code (United States), provider_code (ATSC), user_identifier (GA94)
+    // and user_data_type_code (cc_data).
+    if payload.len() < 10
+        || payload[0] != 0xB5
+        || payload[1..3] != [0x00, 0x31]
+        || &payload[3..7] != b"GA94"
+        || payload[7] != 0x03
+    {
+        return;
+    }
+
+    // process_cc_data_flag
+    if payload[8] & 0x40 == 0 {
+        return;
+    }
+
+    let cc_count = (payload[8] & 0x1F) as usize;
+
+    for triplet in payload[10..].chunks_exact(3).take(cc_count) {
+        // cc_valid
+        if triplet[0] & 0x04 != 0 {
+            cc_data.extend_from_slice(triplet);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://github.com/shaka-project/shaka-player/blob/main/lib/util/ts_parser.js
+    2. https://en.wikipedia.org/wiki/MPEG_transport_stream
+    3. https://en.wikipedia.org/wiki/Packetized_elementary_stream
+
+*/
+
+use super::{sei, CaptionPacket, VideoCodec};
+use crate::{Error, Result};
+
+const PACKET_SIZE: usize = 188;
+/// PTS is a 33 bit counter of a 90 kHz clock, which wraps around after ~26.5 hours.
+const PTS_WRAP: i64 = 1 << 33;
+
+/// Parse closed captions from SEI messages of MPEG-2 transport stream (H.264/H.265) video.
+///
+/// Data can be passed in chunks of any size, call [TsCeaParser::flush] after passing the last chunk.
+#[derive(Default)]
+pub struct TsCeaParser {
+    pending: Vec<u8>,
+    pes: Vec<u8>,
+    pmt_pid: Option<u16>,
+    /// Last unwrapped PTS (90 kHz ticks).
+    previous_pts: Option<i64>,
+    start_time: Option<f64>,
+    video: Option<(u16, VideoCodec)>,
+}
+
+impl TsCeaParser {
+    /// Parse transport stream packets.
+    pub fn parse(&mut self, data: &[u8]) -> Result<Vec<CaptionPacket>> {
+        let mut packets = vec![];
+        let mut data = data;
+        let pending = std::mem::take(&mut self.pending);
+
+        if !pending.is_empty() {
+            let needed = (PACKET_SIZE - pending.len()).min(data.len());
+            let mut packet = pending;
+            packet.extend_from_slice(&data[..needed]);
+            data = &data[needed..];
+
+            if packet.len() < PACKET_SIZE {
+                self.pending = packet;
+                return Ok(packets);
+            }
+
+            self.parse_packet(&packet, &mut packets)?;
+        }
+
+        let mut chunks = data.chunks_exact(PACKET_SIZE);
+
+        for packet in &mut chunks {
+            self.parse_packet(packet, &mut packets)?;
+        }
+
+        self.pending = chunks.remainder().to_vec();
+        Ok(packets)
+    }
+
+    /// Parse the last buffered PES packet.
+    pub fn flush(&mut self) -> Result<Vec<CaptionPacket>> {
+        let mut packets = vec![];
+        self.parse_pes(&mut packets);
+        Ok(packets)
+    }
+
+    /// Presentation time of the earliest video frame parsed so far.
+    pub fn start_time(&self) -> Option<f64> {
+        self.start_time
+    }
+
+    fn parse_packet(&mut self, packet: &[u8], packets: &mut Vec<CaptionPacket>) -> Result<()> {
+        if packet[0] != 0x47 {
+            return Err(Error::new("TS packet sync byte (0x47) not found"));
+        }
+
+        let payload_unit_start = packet[1] & 0x40 != 0;
+        let pid = (((packet[1] & 0x1F) as u16) << 8) | packet[2] as u16;
+        let adaptation_field_control = (packet[3] >> 4) & 0x03;
+
+        if adaptation_field_control & 0x01 == 0 {
+            return Ok(());
+        }
+
+        let mut offset = 4;
+
+        if adaptation_field_control & 0x02 != 0 {
+            offset += packet[4] as usize + 1;
+        }
+
+        let Some(payload) = packet.get(offset..) else {
+            return Ok(());
+        };
+
+        if pid == 0 && payload_unit_start {
+            self.parse_pat(payload);
+        } else if Some(pid) == self.pmt_pid && payload_unit_start {
+            self.parse_pmt(payload);
+        } else if Some(pid) == self.video.map(|x| x.0) {
+            if payload_unit_start {
+                self.parse_pes(packets);
+            }
+
+            self.pes.extend_from_slice(payload);
+        }
+
+        Ok(())
+    }
+
+    fn parse_pat(&mut self, payload: &[u8]) {
+        let Some(section) = psi_section(payload) else {
+            return;
+        };
+
+        for program in section.chunks_exact(4) {
+            let program_number = u16::from_be_bytes([program[0], program[1]]);
+
+            if program_number != 0 {
+                self.pmt_pid = Some((((program[2] & 0x1F) as u16) << 8) | program[3] as u16);
+                break;
+            }
+        }
+    }
+
+    fn parse_pmt(&mut self, payload: &[u8]) {
+        let Some(section) = psi_section(payload) else {
+            return;
+        };
+
+        if section.len() < 4 {
+            return;
+        }
+
+        let program_info_length = ((((section[2] & 0x0F) as usize) << 8) | section[3] as usize) + 4;
+        let mut i = program_info_length;
+
+        while i + 5 <= section.len() {
+            let stream_type = section[i];
+            let pid = (((section[i + 1] & 0x1F) as u16) << 8) | section[i + 2] as u16;
+            let es_info_length =
+                (((section[i + 3] & 0x0F) as usize) << 8) | section[i + 4] as usize;
+
+            let codec = match stream_type {
+                0x1B => Some(VideoCodec::H264),
+                0x24 => Some(VideoCodec::H265),
+                _ => None,
+            };
+
+            if let Some(codec) = codec {
+                self.video = Some((pid, codec));
+                break;
+            }
+
+            i += 5 + es_info_length;
+        }
+    }
+
+    fn parse_pes(&mut self, packets: &mut Vec<CaptionPacket>) {
+        let pes = std::mem::take(&mut self.pes);
+
+        let (Some((_, codec)), true) = (self.video, pes.len() > 9 && pes[0..3] == [0, 0, 1]) else {
+            return;
+        };
+
+        // PTS_DTS_flags
+        if pes[7] & 0x80 == 0 || pes.len() < 14 {
+            return;
+        }
+
+        let pts = ((((pes[9] >> 1) & 0x07) as u64) << 30)
+            | ((pes[10] as u64) << 22)
+            | (((pes[11] >> 1) as u64) << 15)
+            | ((pes[12] as u64) << 7)
+            | ((pes[13] >> 1) as u64);
+        let pts = self.unwrap_pts(pts as i64) as f64 / 90000.0;
+        self.start_time = Some(self.start_time.map(|x| x.min(pts)).unwrap_or(pts));
+
+        let Some(payload) = pes.get((9 + pes[8] as usize)..) else {
+            return;
+        };
+
+        for nalu in sei::annex_b_nalus(payload) {
+            packets.extend(sei::parse_nalu(codec, nalu, pts));
+        }
+    }
+
+    /// Unwrap a 33 bit PTS to the value closest to the previous one, so that presentation
+    /// times keep increasing across rollovers. Frames are in decoding order, which means
+    /// that PTS can go back by a few frames but never by half of the wrap period.
+    fn unwrap_pts(&mut self, pts: i64) -> i64 {
+        let Some(previous_pts) = self.previous_pts else {
+            self.previous_pts = Some(pts);
+            return pts;
+        };
+
+        let mut pts = pts + (previous_pts - previous_pts.rem_euclid(PTS_WRAP));
+
+        if pts - previous_pts > PTS_WRAP / 2 {
+            pts -= PTS_WRAP;
+        } else if previous_pts - pts > PTS_WRAP / 2 {
+            pts += PTS_WRAP;
+        }
+
+        self.previous_pts = Some(pts);
+        pts
+    }
+}
+
+/// Section data of a program specific information table (after section header and before CRC).
+fn psi_section(payload: &[u8]) -> Option<&[u8]> {
+    let pointer_field = *payload.first()? as usize;
+    let table = payload.get((pointer_field + 1)..)?;
+    let section_length = (((*table.get(1)? & 0x0F) as usize) << 8) | *table.get(2)? as usize;
+    table.get(8..(3 + section_length).checked_sub(4)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TsCeaParser, PTS_WRAP};
+
+    #[test]
+    fn unwrap_pts() {
+        let mut parser = TsCeaParser::default();
+        assert_eq!(parser.unwrap_pts(PTS_WRAP - 3003), PTS_WRAP - 3003);
+        assert_eq!(parser.unwrap_pts(PTS_WRAP - 6006), PTS_WRAP - 6006);
+        // Rollover
+        assert_eq!(parser.unwrap_pts(0), PTS_WRAP);
+        assert_eq!(parser.unwrap_pts(PTS_WRAP - 1501), PTS_WRAP - 1501);
+        assert_eq!(parser.unwrap_pts(3003), PTS_WRAP + 3003);
+        assert_eq!(parser.unwrap_pts(PTS_WRAP / 2), PTS_WRAP + PTS_WRAP / 2);
+        assert_eq!(parser.unwrap_pts(PTS_WRAP - 1), 2 * PTS_WRAP - 1);
+        // Second rollover
+        assert_eq!(parser.unwrap_pts(1), 2 * PTS_WRAP + 1);
+    }
+}
@@ -11,6 +11,9 @@
 mod boxes;
 mod subtitles;
 
+#[cfg(feature = "text-cea")]
+mod cea;
+
 use subtitles::Cue;
 
 pub use subtitles::Subtitles;
@@ -32,3 +35,7 @@ mod mp4_vtt_parser;
 #[cfg(feature = "text-vtt")]
 #[cfg_attr(docsrs, doc(cfg(feature = "text-vtt")))]
 pub use mp4_vtt_parser::Mp4VttParser;
+
+#[cfg(feature = "text-cea")]
+#[cfg_attr(docsrs, doc(cfg(feature = "text-cea")))]
+pub use cea::{CaptionPacket, CeaDecoder, Mp4CeaParser, TsCeaParser};
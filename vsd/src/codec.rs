@@ -0,0 +1,235 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://developer.mozilla.org/en-US/docs/Web/Media/Formats/codecs_parameter
+    2. https://professional.dolby.com/siteassets/pdfs/dolby-vision-streams-within-the-http-live-streaming-format-v2.0-13-november-2018.pdf
+    3. https://dashif.org/docs/IOP-Guidelines/DASH-IF-IOP-Part7-v5.0.0.pdf (Section 6.2.5 Video Transfer Characteristics)
+    4. ITU-T H.273 (TransferCharacteristics 16 = PQ, 18 = HLG)
+
+    Dynamic range is SDR unless it is declared by HLS `VIDEO-RANGE` (PQ, HLG),
+    DASH `urn:mpeg:mpegB:cicp:TransferCharacteristics` descriptor (16, 18) or a Dolby Vision codec.
+
+*/
+
+use clap::ValueEnum;
+use std::fmt::Display;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub(crate) enum CodecFamily {
+    Av1,
+    #[value(alias = "h264")]
+    Avc,
+    #[value(alias = "h265")]
+    Hevc,
+    Vp8,
+    Vp9,
+}
+
+impl Display for CodecFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Av1 => "AV1",
+                Self::Avc => "AVC",
+                Self::Hevc => "HEVC",
+                Self::Vp8 => "VP8",
+                Self::Vp9 => "VP9",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub(crate) enum DynamicRange {
+    Sdr,
+    #[value(alias = "pq")]
+    Hdr10,
+    Hlg,
+    #[value(name = "dv", alias = "dolby-vision")]
+    DolbyVision,
+}
+
+impl Display for DynamicRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Sdr => "SDR",
+                Self::Hdr10 => "HDR10",
+                Self::Hlg => "HLG",
+                Self::DolbyVision => "Dolby Vision",
+            }
+        )
+    }
+}
+
+impl DynamicRange {
+    /// Parse HLS `VIDEO-RANGE` attribute value.
+    pub(crate) fn from_video_range(video_range: &str) -> Option<Self> {
+        match video_range {
+            "SDR" => Some(Self::Sdr),
+            "PQ" => Some(Self::Hdr10),
+            "HLG" => Some(Self::Hlg),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) struct VideoCodec {
+    pub(crate) dolby_vision: bool,
+    pub(crate) family: CodecFamily,
+    pub(crate) profile: Option<String>,
+}
+
+impl VideoCodec {
+    /// Parse first video codec from `codecs` attribute.
+    pub(crate) fn parse(codecs: &str) -> Option<Self> {
+        codecs.split(',').find_map(|x| Self::parse_one(x.trim()))
+    }
+
+    fn parse_one(codec: &str) -> Option<Self> {
+        let mut parts = codec.split('.');
+        let fourcc = parts.next()?;
+        let first = parts.next();
+
+        let (family, dolby_vision) = match fourcc {
+            "avc1" | "avc3" => (CodecFamily::Avc, false),
+            "dva1" | "dvav" => (CodecFamily::Avc, true),
+            "hvc1" | "hev1" => (CodecFamily::Hevc, false),
+            "dvh1" | "dvhe" => (CodecFamily::Hevc, true),
+            "av01" => (CodecFamily::Av1, false),
+            "dav1" => (CodecFamily::Av1, true),
+            "vp09" | "vp9" => (CodecFamily::Vp9, false),
+            "vp08" | "vp8" => (CodecFamily::Vp8, false),
+            _ => return None,
+        };
+
+        let profile = if dolby_vision {
+            first
+                .and_then(|x| x.parse::<u8>().ok())
+                .map(|x| format!("DV {}", x))
+        } else {
+            match family {
+                CodecFamily::Avc => first
+                    .and_then(|x| x.get(..2))
+                    .and_then(|x| u8::from_str_radix(x, 16).ok())
+                    .map(|x| match x {
+                        66 => "Baseline".to_owned(),
+                        77 => "Main".to_owned(),
+                        88 => "Extended".to_owned(),
+                        100 => "High".to_owned(),
+                        110 => "High 10".to_owned(),
+                        122 => "High 4:2:2".to_owned(),
+                        244 => "High 4:4:4".to_owned(),
+                        x => x.to_string(),
+                    }),
+                CodecFamily::Hevc => first
+                    .map(|x| x.trim_start_matches(['A', 'B', 'C']))
+                    .and_then(|x| x.parse::<u8>().ok())
+                    .map(|x| match x {
+                        1 => "Main".to_owned(),
+                        2 => "Main 10".to_owned(),
+                        3 => "Main Still Picture".to_owned(),
+                        4 => "Range Extensions".to_owned(),
+                        x => x.to_string(),
+                    }),
+                CodecFamily::Av1 => first.and_then(|x| x.parse::<u8>().ok()).map(|x| match x {
+                    0 => "Main".to_owned(),
+                    1 => "High".to_owned(),
+                    2 => "Professional".to_owned(),
+                    x => x.to_string(),
+                }),
+                CodecFamily::Vp9 => first
+                    .and_then(|x| x.parse::<u8>().ok())
+                    .map(|x| x.to_string()),
+                CodecFamily::Vp8 => None,
+            }
+        };
+
+        Some(Self {
+            dolby_vision,
+            family,
+            profile,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CodecFamily, DynamicRange, VideoCodec};
+
+    fn parse(codecs: &str) -> (CodecFamily, bool, Option<String>) {
+        let codec = VideoCodec::parse(codecs).unwrap();
+        (codec.family, codec.dolby_vision, codec.profile)
+    }
+
+    #[test]
+    fn avc() {
+        assert_eq!(
+            parse("avc1.640028"),
+            (CodecFamily::Avc, false, Some("High".to_owned()))
+        );
+    }
+
+    #[test]
+    fn hevc() {
+        assert_eq!(
+            parse("hvc1.2.4.L150.B0"),
+            (CodecFamily::Hevc, false, Some("Main 10".to_owned()))
+        );
+    }
+
+    #[test]
+    fn dolby_vision() {
+        assert_eq!(
+            parse("dvh1.05.06"),
+            (CodecFamily::Hevc, true, Some("DV 5".to_owned()))
+        );
+    }
+
+    #[test]
+    fn av1() {
+        assert_eq!(
+            parse("av01.0.08M.10"),
+            (CodecFamily::Av1, false, Some("Main".to_owned()))
+        );
+    }
+
+    #[test]
+    fn vp9() {
+        assert_eq!(
+            parse("vp09.02.51.10"),
+            (CodecFamily::Vp9, false, Some("2".to_owned()))
+        );
+    }
+
+    #[test]
+    fn first_video_codec() {
+        assert_eq!(
+            parse("mp4a.40.2, avc1.4d401f"),
+            (CodecFamily::Avc, false, Some("Main".to_owned()))
+        );
+        assert!(VideoCodec::parse("mp4a.40.2,ec-3").is_none());
+    }
+
+    #[test]
+    fn video_range() {
+        assert_eq!(
+            DynamicRange::from_video_range("SDR"),
+            Some(DynamicRange::Sdr)
+        );
+        assert_eq!(
+            DynamicRange::from_video_range("PQ"),
+            Some(DynamicRange::Hdr10)
+        );
+        assert_eq!(
+            DynamicRange::from_video_range("HLG"),
+            Some(DynamicRange::Hlg)
+        );
+        assert_eq!(DynamicRange::from_video_range("UNKNOWN"), None);
+    }
+}
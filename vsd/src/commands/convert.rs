@@ -0,0 +1,88 @@
+use crate::downloader::{self, Prompts};
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use kdam::term::Colorizer;
+use reqwest::{blocking::Client, Url};
+use std::{fs::File, path::PathBuf};
+
+/// Convert DASH and Smooth Streaming playlists to other playlist formats.
+#[derive(Debug, Clone, Args)]
+pub struct Convert {
+    /// http(s):// | .mpd | .xml | .ism/Manifest
+    #[arg(required = true)]
+    input: String,
+
+    /// Base url to be used for building absolute url to segment.
+    /// This flag is usually needed for local input files.
+    /// By default redirected playlist url is used.
+    #[arg(long)]
+    base_url: Option<Url>,
+
+    /// Change directory path for converted playlists.
+    /// By default current working directory is used.
+    #[arg(short, long)]
+    directory: Option<PathBuf>,
+
+    /// Format of converted playlists.
+    #[arg(long, value_enum, default_value_t = Format::Hls)]
+    to: Format,
+
+    /// Update and set user agent header for requests.
+    #[arg(
+        long,
+        default_value = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Safari/537.36"
+    )]
+    user_agent: String,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Format {
+    Hls,
+}
+
+impl Convert {
+    pub fn execute(self) -> Result<()> {
+        let client = Client::builder()
+            .user_agent(self.user_agent)
+            .cookie_store(true)
+            .build()?;
+        let prompts = Prompts {
+            skip: true,
+            raw: false,
+        };
+        let meta =
+            downloader::fetch_playlist(self.base_url.clone(), &client, &self.input, &prompts)?;
+        let playlist = downloader::parse_all_streams(self.base_url, &client, &meta)?;
+        let directory = self.directory.unwrap_or_default();
+
+        if !directory.as_os_str().is_empty() && !directory.exists() {
+            std::fs::create_dir_all(&directory)?;
+        }
+
+        match self.to {
+            Format::Hls => {
+                let playlists = crate::hls::convert(&playlist);
+
+                for (name, media_playlist) in &playlists.media {
+                    let path = directory.join(name);
+                    media_playlist.write_to(&mut File::create(&path)?)?;
+                    println!(
+                        "      {} {}",
+                        "Saved".colorize("bold green"),
+                        path.display()
+                    );
+                }
+
+                let path = directory.join("master.m3u8");
+                playlists.master.write_to(&mut File::create(&path)?)?;
+                println!(
+                    "      {} {}",
+                    "Saved".colorize("bold green"),
+                    path.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
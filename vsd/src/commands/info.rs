@@ -0,0 +1,391 @@
+use crate::{
+    downloader::{self, Prompts},
+    playlist::{Key, KeyMethod, MasterPlaylist, MediaPlaylist, StreamPreferences},
+};
+use anyhow::Result;
+use clap::Args;
+use kdam::term::Colorizer;
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Url,
+};
+use serde::Serialize;
+
+/// List streams of DASH, HLS and Smooth Streaming playlists without downloading them.
+#[derive(Debug, Clone, Args)]
+pub struct Info {
+    /// http(s):// | .mpd | .xml | .m3u8 | .ism/Manifest
+    #[arg(required = true)]
+    input: String,
+
+    /// Base url to be used for building absolute url to segment.
+    /// This flag is usually needed for local input files.
+    /// By default redirected playlist url is used.
+    #[arg(long)]
+    base_url: Option<Url>,
+
+    /// Custom headers for requests.
+    /// This option can be used multiple times.
+    #[arg(long, num_args = 2, value_names = &["KEY", "VALUE"])]
+    header: Vec<String>, // Vec<(String, String)> not supported
+
+    /// List I-frame (trick play) video streams instead of normal video streams.
+    /// Streams are numbered in the same way as `save --i-frames` prompt.
+    #[arg(long)]
+    i_frames: bool,
+
+    /// Print streams in compact json format (one line) instead of a table.
+    #[arg(long)]
+    json: bool,
+
+    /// Update and set user agent header for requests.
+    #[arg(
+        long,
+        default_value = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Safari/537.36"
+    )]
+    user_agent: String,
+}
+
+#[derive(Serialize)]
+struct StreamInfo {
+    index: usize,
+    #[serde(rename = "type")]
+    media_type: String,
+    width: Option<u64>,
+    height: Option<u64>,
+    /// In bits per second.
+    bandwidth: Option<u64>,
+    codecs: Option<String>,
+    language: Option<String>,
+    channels: Option<f32>,
+    segments: usize,
+    /// In seconds.
+    duration: f64,
+    /// In bytes, exact when every segment has a byte range otherwise estimated from bandwidth.
+    size: Option<u64>,
+    encryption: Option<String>,
+    kids: Vec<String>,
+    uri: String,
+}
+
+impl StreamInfo {
+    fn new(index: usize, stream: &MediaPlaylist) -> Self {
+        // Sum of an empty iterator of floats is -0.0.
+        let duration = stream
+            .segments
+            .iter()
+            .fold(0.0, |duration, x| duration + x.duration as f64);
+
+        let ranges = stream
+            .segments
+            .iter()
+            .map(|x| x.range.as_ref().filter(|x| x.end != u64::MAX))
+            .collect::<Option<Vec<_>>>();
+        let size = match ranges {
+            Some(ranges) if !ranges.is_empty() => {
+                Some(ranges.iter().map(|x| x.end - x.start + 1).sum())
+            }
+            _ => stream
+                .bandwidth
+                .map(|x| (x as f64 * duration / 8.0) as u64)
+                .filter(|x| *x != 0),
+        };
+
+        let mut encryption = None;
+        let mut kids = vec![];
+
+        if let Some(Key {
+            key_ids,
+            method,
+            scheme,
+            ..
+        }) = stream
+            .key
+            .as_ref()
+            .or_else(|| stream.segments.first().and_then(|x| x.key.as_ref()))
+        {
+            encryption = match method {
+                KeyMethod::Aes128 => Some("aes-128".to_owned()),
+                KeyMethod::Cenc => Some(scheme.to_owned().unwrap_or("cenc".to_owned())),
+                KeyMethod::None => None,
+                KeyMethod::Other(x) => Some(x.to_lowercase()),
+                KeyMethod::SampleAes => Some("sample-aes".to_owned()),
+            };
+
+            if *method == KeyMethod::Cenc {
+                kids = key_ids.iter().map(|x| x.uuid()).collect();
+                kids.sort();
+                kids.dedup();
+
+                if kids.is_empty() {
+                    kids.extend(stream.default_kid());
+                }
+            }
+        }
+
+        Self {
+            index,
+            media_type: stream.media_type.to_string(),
+            width: stream.resolution.map(|x| x.0),
+            height: stream.resolution.map(|x| x.1),
+            bandwidth: stream.bandwidth,
+            codecs: stream.codecs.to_owned(),
+            language: stream.language.to_owned(),
+            channels: stream.channels,
+            segments: stream.segments.len(),
+            duration,
+            size,
+            encryption,
+            kids,
+            uri: stream.uri.to_owned(),
+        }
+    }
+
+    fn row(&self) -> Vec<String> {
+        let or_dash = |x: Option<String>| x.unwrap_or("-".to_owned());
+
+        vec![
+            self.index.to_string(),
+            self.media_type.to_owned(),
+            or_dash(
+                self.width
+                    .zip(self.height)
+                    .map(|(w, h)| format!("{}x{}", w, h)),
+            ),
+            or_dash(self.bandwidth.map(format_bitrate)),
+            or_dash(self.codecs.to_owned()),
+            or_dash(self.language.to_owned()),
+            or_dash(self.channels.map(|x| x.to_string())),
+            self.segments.to_string(),
+            format_duration(self.duration),
+            or_dash(
+                self.size
+                    .map(|x| crate::utils::format_bytes(x as usize, 2).2),
+            ),
+            or_dash(self.encryption.to_owned()),
+            if self.kids.is_empty() {
+                "-".to_owned()
+            } else {
+                self.kids.join(" ")
+            },
+        ]
+    }
+}
+
+impl Info {
+    pub fn execute(self) -> Result<()> {
+        let mut client_builder = Client::builder()
+            .user_agent(self.user_agent)
+            .cookie_store(true);
+
+        if !self.header.is_empty() {
+            let mut headers = HeaderMap::new();
+
+            for i in (0..self.header.len()).step_by(2) {
+                headers.insert(
+                    self.header[i].parse::<HeaderName>()?,
+                    self.header[i + 1].parse::<HeaderValue>()?,
+                );
+            }
+
+            client_builder = client_builder.default_headers(headers);
+        }
+
+        let client = client_builder.build()?;
+        let prompts = Prompts {
+            skip: true,
+            raw: false,
+        };
+        let meta =
+            downloader::fetch_playlist(self.base_url.clone(), &client, &self.input, &prompts)?;
+        let playlist = downloader::parse_all_streams(self.base_url, &client, &meta)?;
+        let streams = stream_infos(playlist, self.i_frames);
+
+        if self.json {
+            serde_json::to_writer(std::io::stdout(), &streams)?;
+            println!();
+            return Ok(());
+        }
+
+        let header = [
+            "#",
+            "TYPE",
+            "RESOLUTION",
+            "BITRATE",
+            "CODECS",
+            "LANG",
+            "CH",
+            "SEGS",
+            "DURATION",
+            "SIZE",
+            "DRM",
+            "KIDS",
+        ];
+        let rows = streams.iter().map(|x| x.row()).collect::<Vec<_>>();
+        let widths = header
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                rows.iter()
+                    .map(|x| x[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(x.len())
+            })
+            .collect::<Vec<_>>();
+
+        println!(
+            "{}",
+            header
+                .iter()
+                .zip(&widths)
+                .map(|(x, width)| format!("{:width$}", x, width = width))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .colorize("bold")
+        );
+
+        for row in rows {
+            println!(
+                "{}",
+                row.iter()
+                    .zip(&widths)
+                    .map(|(x, width)| format!("{:width$}", x, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Streams numbered in the same order as `save` prompt (see `MasterPlaylist::select_streams`),
+/// so that these numbers can be used there.
+fn stream_infos(playlist: MasterPlaylist, i_frames: bool) -> Vec<StreamInfo> {
+    let mut playlist = playlist.sort_streams(&StreamPreferences::default());
+    playlist.retain_i_frames(i_frames);
+    playlist
+        .streams
+        .iter()
+        .enumerate()
+        .map(|(i, x)| StreamInfo::new(i + 1, x))
+        .collect()
+}
+
+fn format_bitrate(bandwidth: u64) -> String {
+    if bandwidth >= 1_000_000 {
+        format!("{:.2} Mbps", bandwidth as f64 / 1_000_000.0)
+    } else if bandwidth >= 1000 {
+        format!("{:.0} kbps", bandwidth as f64 / 1000.0)
+    } else {
+        format!("{} bps", bandwidth)
+    }
+}
+
+fn format_duration(duration: f64) -> String {
+    let duration = duration.round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        duration / 3600,
+        (duration % 3600) / 60,
+        duration % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stream_infos;
+    use serde_json::json;
+
+    const MASTER: &[u8] = br#"#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",LANGUAGE="en",CHANNELS="2",DEFAULT=YES,URI="audio/en.m3u8"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=200000,RESOLUTION=1920x1080,CODECS="avc1.640028",URI="iframes.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,RESOLUTION=1280x720,CODECS="avc1.64001f,mp4a.40.2",AUDIO="aac"
+720p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1920x1080,CODECS="avc1.640028,mp4a.40.2",AUDIO="aac"
+1080p.m3u8
+"#;
+
+    fn json_output(i_frames: bool) -> String {
+        let m3u8 = m3u8_rs::parse_master_playlist_res(MASTER).unwrap();
+        let playlist = crate::hls::parse_as_master(&m3u8, "https://example.com/master.m3u8");
+        serde_json::to_string(&stream_infos(playlist, i_frames)).unwrap()
+    }
+
+    #[test]
+    fn json_streams() {
+        let output = json_output(false);
+        assert!(!output.contains("-0.0"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&output).unwrap(),
+            json!([
+                {
+                    "index": 1,
+                    "type": "video",
+                    "width": 1920,
+                    "height": 1080,
+                    "bandwidth": 3_000_000,
+                    "codecs": "avc1.640028,mp4a.40.2",
+                    "language": null,
+                    "channels": null,
+                    "segments": 0,
+                    "duration": 0.0,
+                    "size": null,
+                    "encryption": null,
+                    "kids": [],
+                    "uri": "1080p.m3u8"
+                },
+                {
+                    "index": 2,
+                    "type": "video",
+                    "width": 1280,
+                    "height": 720,
+                    "bandwidth": 1_000_000,
+                    "codecs": "avc1.64001f,mp4a.40.2",
+                    "language": null,
+                    "channels": null,
+                    "segments": 0,
+                    "duration": 0.0,
+                    "size": null,
+                    "encryption": null,
+                    "kids": [],
+                    "uri": "720p.m3u8"
+                },
+                {
+                    "index": 3,
+                    "type": "audio",
+                    "width": null,
+                    "height": null,
+                    "bandwidth": null,
+                    "codecs": null,
+                    "language": "en",
+                    "channels": 2.0,
+                    "segments": 0,
+                    "duration": 0.0,
+                    "size": null,
+                    "encryption": null,
+                    "kids": [],
+                    "uri": "audio/en.m3u8"
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn json_i_frame_streams() {
+        let streams = serde_json::from_str::<serde_json::Value>(&json_output(true)).unwrap();
+        let streams = streams
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| (x["index"].as_u64().unwrap(), x["uri"].as_str().unwrap()))
+            .collect::<Vec<_>>();
+
+        // Same numbering as `save --i-frames` prompt.
+        assert_eq!(streams, [(1, "iframes.m3u8"), (2, "audio/en.m3u8")]);
+    }
+}
@@ -1,13 +1,17 @@
+mod convert;
 mod extract;
+mod info;
 mod merge;
 mod save;
 
 #[cfg(feature = "browser")]
 mod capture;
 
+pub use convert::Convert;
 pub use extract::Extract;
+pub use info::Info;
 pub use merge::Merge;
-pub use save::{Quality, Save};
+pub use save::{Quality, QualityFallback, Save};
 
 #[cfg(feature = "browser")]
 pub use capture::Capture;
@@ -41,7 +45,9 @@ pub struct Args {
 pub enum Commands {
     #[cfg(feature = "browser")]
     Capture(Capture),
+    Convert(Convert),
     Extract(Extract),
+    Info(Info),
     Merge(Merge),
     Save(Save),
 }
@@ -1,10 +1,13 @@
 use crate::{
+    codec::{CodecFamily, DynamicRange},
     cookie::{CookieJar, CookieParam},
     downloader::{self, Prompts},
+    filter::{StreamFilter, StreamFilters},
+    playlist::StreamPreferences,
     utils,
 };
 use anyhow::Result;
-use clap::Args;
+use clap::{Args, ValueEnum};
 use cookie::Cookie;
 use kdam::term::Colorizer;
 use reqwest::{
@@ -22,7 +25,7 @@ type CookieParams = Vec<CookieParam>;
 /// Download DASH and HLS playlists.
 #[derive(Debug, Clone, Args)]
 pub struct Save {
-    /// http(s):// | .mpd | .xml | .m3u8
+    /// http(s):// | .mpd | .xml | .m3u8 | .ism/Manifest
     #[arg(required = true)]
     pub input: String,
 
@@ -47,30 +50,114 @@ pub struct Save {
     #[arg(long)]
     pub parse: bool,
 
+    /// Add events of downloaded streams (DASH EventStream and emsg boxes) as chapters to the muxed output.
+    /// Ad breaks (SCTE-35) are added as "Ad break" chapters.
+    #[arg(long)]
+    pub chapters: bool,
+
+    /// Save events of downloaded streams (DASH EventStream and emsg boxes) to a json file.
+    #[arg(long, value_name = "PATH")]
+    pub save_events: Option<PathBuf>,
+
     /// Raw style input prompts for old and unsupported terminals.
     #[arg(long)]
     pub raw_prompts: bool,
 
-    /// Preferred language when multiple audio streams with different languages are available.
-    /// Must be in RFC 5646 format (eg. fr or en-AU).
+    /// Preferred languages in order of preference when multiple audio streams with different languages are available.
+    /// Must be in RFC 5646 format (eg. en-US,en,fr). Script and region subtags are matched as fallbacks, so en matches en-AU.
+    /// Use all to select every audio stream.
     /// If a preference is not specified and multiple audio streams are present,
     /// the first one listed in the manifest will be downloaded.
-    #[arg(long, help_heading = "Automation Options")]
-    pub prefer_audio_lang: Option<String>,
+    #[arg(
+        long,
+        help_heading = "Automation Options",
+        alias = "prefer-audio-lang",
+        value_delimiter = ',',
+        value_name = "LANGS"
+    )]
+    pub audio_lang: Vec<String>,
 
-    /// Preferred language when multiple subtitles streams with different languages are available.
-    /// Must be in RFC 5646 format (eg. fr or en-AU).
+    /// Preferred languages in order of preference when multiple subtitles streams with different languages are available.
+    /// Must be in RFC 5646 format (eg. en-US,en,fr). Script and region subtags are matched as fallbacks, so en matches en-AU.
+    /// Use all to select every subtitles stream.
     /// If a preference is not specified and multiple subtitles streams are present,
     /// the first one listed in the manifest will be downloaded.
-    #[arg(long, help_heading = "Automation Options")]
-    pub prefer_subs_lang: Option<String>,
+    #[arg(
+        long,
+        help_heading = "Automation Options",
+        alias = "prefer-subs-lang",
+        value_delimiter = ',',
+        value_name = "LANGS"
+    )]
+    pub subs_lang: Vec<String>,
+
+    /// Preferred video codecs in order of preference, e.g. "hevc,avc".
+    /// Streams of same resolution are ranked by these codecs before bandwidth.
+    #[arg(
+        long,
+        help_heading = "Automation Options",
+        value_delimiter = ',',
+        value_name = "CODECS"
+    )]
+    pub prefer_codec: Vec<CodecFamily>,
+
+    /// Preferred video dynamic ranges in order of preference, e.g. "dv,hdr10".
+    /// Streams of same resolution are ranked by these ranges before codecs.
+    /// By default SDR streams are preferred over HDR (HDR10, HLG and Dolby Vision) streams.
+    #[arg(
+        long,
+        help_heading = "Automation Options",
+        value_delimiter = ',',
+        value_name = "RANGES"
+    )]
+    pub prefer_range: Vec<DynamicRange>,
+
+    /// Preferred video frame rate, streams of same resolution with nearest frame rate are ranked first.
+    /// By default streams with highest frame rate are preferred.
+    #[arg(long, help_heading = "Automation Options", value_name = "FPS")]
+    pub prefer_frame_rate: Option<f32>,
 
     /// Automatic selection of some standard resolution streams with highest bandwidth stream variant from playlist.
     /// If matching resolution of WIDTHxHEIGHT is not found then only resolution HEIGHT would be considered for selection.
+    /// A height range (e.g. 720-1080p) selects highest stream within that range
+    /// and a target bitrate (e.g. 5mbps or 800kbps) selects highest bandwidth stream which doesn't exceed it.
     /// comman values: [lowest, min, 144p, 240p, 360p, 480p, 720p, hd, 1080p, fhd, 2k, 1440p, qhd, 4k, 8k, highest, max]
-    #[arg(short, long, help_heading = "Automation Options", default_value = "highest", value_name = "WIDTHxHEIGHT|HEIGHTp", value_parser = quality_parser)]
+    #[arg(short, long, help_heading = "Automation Options", default_value = "highest", value_name = "WIDTHxHEIGHT|HEIGHTp|MIN-MAXp|BITRATE", value_parser = quality_parser)]
     pub quality: Quality,
 
+    /// Stream to select when no stream matches --quality.
+    /// Width and height are compared together, so 1920x800 is the nearest stream for 1080p.
+    #[arg(long, help_heading = "Automation Options", value_enum, default_value_t = QualityFallback::Nearest)]
+    pub quality_fallback: QualityFallback,
+
+    /// Filter expression for selecting video stream, overrides --quality.
+    /// First matching stream is selected, e.g. "height>=720 & bandwidth<8M & codecs^=hvc1 ; height>=720".
+    /// Comparisons (= != < <= > >= ~ ^=) of bandwidth, codecs, fps, height, width, lang, name, role etc. can be combined with & | ! ( ).
+    /// Alternatives separated by ; are only used when previous ones don't match any stream.
+    #[arg(long, help_heading = "Automation Options", value_name = "EXPR")]
+    pub select_video: Option<StreamFilter>,
+
+    /// Filter expression for selecting audio streams, all matching streams are selected.
+    /// e.g. "lang=en,fr & channels>=6". See --select-video for syntax.
+    #[arg(long, help_heading = "Automation Options", value_name = "EXPR")]
+    pub select_audio: Option<StreamFilter>,
+
+    /// Filter expression for selecting subtitles streams, all matching streams are selected.
+    /// e.g. "!forced" or "none". See --select-video for syntax.
+    #[arg(long, help_heading = "Automation Options", value_name = "EXPR")]
+    pub select_subs: Option<StreamFilter>,
+
+    /// Filter expression for selecting other streams (e.g. metadata tracks), all matching streams are selected.
+    /// These streams are saved separately as they are not muxed. See --select-video for syntax.
+    #[arg(long, help_heading = "Automation Options", value_name = "EXPR")]
+    pub select_other: Option<StreamFilter>,
+
+    /// Select I-frame (trick play) video streams instead of normal video streams.
+    /// These streams only contain key frames and are useful for preview generation.
+    /// If --output has an image extension (.jpg, .png or .webp) then key frames are tiled into a thumbnail strip.
+    #[arg(long, help_heading = "Automation Options")]
+    pub i_frames: bool,
+
     /// Skip user input prompts and proceed with defaults.
     #[arg(long, help_heading = "Automation Options")]
     pub skip_prompts: bool,
@@ -133,6 +220,12 @@ pub struct Save {
     #[arg(long, help_heading = "Decrypt Options")]
     pub no_decrypt: bool,
 
+    /// Keep reloading live playlists and record streams until they end or ctrl+c is pressed.
+    /// Low-latency HLS playlists are recorded at partial segment granularity using blocking playlist reloads.
+    /// By default only segments which are currently available in a live playlist are downloaded.
+    #[arg(long, help_heading = "Download Options")]
+    pub live: bool,
+
     /// Maximum number of retries to download an individual segment.
     #[arg(long, help_heading = "Download Options", default_value_t = 15)]
     pub retry_count: u8,
@@ -142,6 +235,18 @@ pub struct Save {
     #[arg(long, help_heading = "Download Options")]
     pub no_merge: bool,
 
+    /// Skip segments which are marked as a part of an ad break.
+    /// Ad breaks are detected using EXT-X-CUE-OUT/EXT-X-CUE-IN and EXT-X-DATERANGE (SCTE-35) tags,
+    /// and DASH periods which start inside a SCTE-35 event.
+    #[arg(long, help_heading = "Download Options")]
+    pub skip_ads: bool,
+
+    /// Save each period of a multi-period DASH playlist (or each discontinuity of a HLS playlist)
+    /// as a separate file instead of joining them.
+    /// Note that --output flag is ignored if this flag is used.
+    #[arg(long, help_heading = "Download Options")]
+    pub split_periods: bool,
+
     /// Maximum number of threads for parllel downloading of segments.
     /// Number of threads should be in range 1-16 (inclusive).
     #[arg(short, long, help_heading = "Download Options", default_value_t = 5, value_parser = clap::value_parser!(u8).range(1..=16))]
@@ -152,6 +257,8 @@ pub struct Save {
 pub enum Quality {
     Lowest,
     Highest,
+    Bandwidth(u64),
+    HeightRange(u16, u16),
     Resolution(u16, u16),
     Youtube144p,
     Youtube240p,
@@ -165,6 +272,18 @@ pub enum Quality {
     Youtube8k,
 }
 
+#[derive(Debug, Clone, ValueEnum)]
+pub enum QualityFallback {
+    /// Fail with an error.
+    Fail,
+    /// Select nearest higher quality stream, otherwise highest quality stream.
+    Higher,
+    /// Select nearest lower quality stream, otherwise lowest quality stream.
+    Lower,
+    /// Select nearest quality stream.
+    Nearest,
+}
+
 fn quality_parser(s: &str) -> Result<Quality, String> {
     Ok(match s.to_lowercase().as_str() {
         "lowest" | "min" => Quality::Lowest,
@@ -179,6 +298,34 @@ fn quality_parser(s: &str) -> Result<Quality, String> {
         "4k" => Quality::Youtube4k,
         "8k" => Quality::Youtube8k,
         "highest" | "max" => Quality::Highest,
+        x if x.ends_with("bps") => {
+            let x = x.trim_end_matches("bps");
+            let (x, multiplier) = if let Some(x) = x.strip_suffix('k') {
+                (x, 1_000.0)
+            } else if let Some(x) = x.strip_suffix('m') {
+                (x, 1_000_000.0)
+            } else {
+                (x, 1.0)
+            };
+
+            Quality::Bandwidth(
+                (x.parse::<f64>()
+                    .map_err(|_| "could not parse target BITRATE.".to_owned())?
+                    * multiplier) as u64,
+            )
+        }
+        x if x.contains('-') => {
+            let (min, max) = x.split_once('-').unwrap();
+            let min = min
+                .trim_end_matches('p')
+                .parse::<u16>()
+                .map_err(|_| "could not parse minimum HEIGHT of range.".to_owned())?;
+            let max = max
+                .trim_end_matches('p')
+                .parse::<u16>()
+                .map_err(|_| "could not parse maximum HEIGHT of range.".to_owned())?;
+            Quality::HeightRange(min.min(max), min.max(max))
+        }
         x if x.ends_with('p') => Quality::Resolution(
             0,
             x.trim_end_matches('p')
@@ -315,16 +462,58 @@ impl Save {
             let playlist = downloader::parse_all_streams(self.base_url.clone(), &client, &meta)?;
             serde_json::to_writer(std::io::stdout(), &playlist)?;
         } else {
+            // Selecting all languages is same as selecting all streams.
+            let filters = StreamFilters {
+                audio: self.select_audio.or_else(|| {
+                    self.audio_lang
+                        .iter()
+                        .any(|x| x == "all")
+                        .then(StreamFilter::all)
+                }),
+                other: self.select_other,
+                subs: self.select_subs.or_else(|| {
+                    self.subs_lang
+                        .iter()
+                        .any(|x| x == "all")
+                        .then(StreamFilter::all)
+                }),
+                video: self.select_video,
+            };
             let mut selected_playlists = downloader::parse_selected_streams(
                 self.base_url.clone(),
                 &client,
                 &meta,
-                self.prefer_audio_lang,
-                self.prefer_subs_lang,
+                &StreamPreferences {
+                    audio_langs: self.audio_lang,
+                    codecs: self.prefer_codec,
+                    frame_rate: self.prefer_frame_rate,
+                    ranges: self.prefer_range,
+                    subs_langs: self.subs_lang,
+                },
                 &prompts,
                 self.quality,
+                self.quality_fallback,
+                &filters,
+                self.i_frames,
             )?;
 
+            for stream in selected_playlists
+                .0
+                .iter_mut()
+                .chain(selected_playlists.1.iter_mut())
+            {
+                // Recorder starts from preferred start point by itself.
+                if !(stream.live && self.live) {
+                    stream.clip_to_start();
+                }
+
+                stream.skip_gaps();
+
+                if self.skip_ads {
+                    stream.skip_ads();
+                }
+            }
+
             if !self.no_query_pass {
                 if let Some(query) = self.query.as_mut() {
                     if query.starts_with('&') {
@@ -360,14 +549,18 @@ impl Save {
             downloader::download(
                 self.all_keys,
                 self.base_url,
+                self.chapters,
                 client,
                 self.directory,
                 self.key,
+                self.live,
                 self.no_decrypt,
                 self.no_merge,
                 self.output,
                 selected_playlists,
                 self.retry_count,
+                self.save_events,
+                self.split_periods,
                 self.threads,
             )?;
         }
@@ -1,8 +1,11 @@
 mod locator;
 mod playlist;
 mod template;
+mod timeline;
+mod xlink;
 
 use locator::DashUrl;
 use template::Template;
 
 pub(crate) use playlist::{parse_as_master, push_segments};
+pub(crate) use xlink::resolve_xlinks;
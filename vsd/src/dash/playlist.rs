@@ -9,21 +9,74 @@
 
 */
 
-use super::{DashUrl, Template};
-use crate::playlist::{
-    Key, KeyMethod, Map, MasterPlaylist, MediaPlaylist, MediaType, PlaylistType, Range, Segment,
+use super::{timeline, DashUrl, Template};
+use crate::{
+    playlist::{
+        ClosedCaption, Event, InbandEvents, Key, KeyId, KeyMethod, Map, MasterPlaylist,
+        MediaPlaylist, MediaType, PlaylistType, Range, Segment,
+    },
+    utils,
 };
 use anyhow::{anyhow, bail, Result};
-use dash_mpd::MPD;
-use reqwest::Url;
+use dash_mpd::{Accessibility, ContentProtection, EssentialProperty, Period, MPD};
+use kdam::term::Colorizer;
+use reqwest::{blocking::Client, header, StatusCode, Url};
 use std::collections::HashMap;
+use vsd_mp4::{pssh::Pssh, sidx::Sidx};
+
+/// Identity of a stream, used to match its representations across periods.
+struct Track {
+    adaptation_set_id: Option<String>,
+    /// Codec without profile and level (`avc1.640028` -> `avc1`).
+    codec: Option<String>,
+    language: Option<String>,
+    media_type: MediaType,
+    representation_id: Option<String>,
+    resolution: Option<(u64, u64)>,
+    roles: Vec<String>,
+}
+
+impl Track {
+    /// Score of matching a representation of another period with this track.
+    /// Representations with a different language, codec or role never match.
+    fn score(&self, other: &Track) -> Option<u8> {
+        if self.media_type != other.media_type
+            || self.language != other.language
+            || self.codec != other.codec
+            || self.roles != other.roles
+        {
+            return None;
+        }
+
+        let mut score = 0;
+
+        if self.representation_id.is_some() && self.representation_id == other.representation_id {
+            score += 4;
+        }
+
+        if self.adaptation_set_id.is_some() && self.adaptation_set_id == other.adaptation_set_id {
+            score += 2;
+        }
+
+        if self.resolution == other.resolution {
+            score += 1;
+        }
+
+        Some(score)
+    }
+}
 
+/// Streams of all periods are parsed, representations of later periods are matched
+/// with streams of previous periods (by id, language, codec and role) and their locations
+/// are appended to stream's uri (`dash://...;dash://...`).
 pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
-    let mut streams = vec![];
+    let mut streams: Vec<MediaPlaylist> = vec![];
+    let mut tracks: Vec<Track> = vec![];
+
+    for (period_index, period) in mpd.periods.iter().enumerate() {
+        // Tracks which already have a representation in this period.
+        let mut matched = vec![false; tracks.len()];
 
-    if let Some(period) = mpd.periods.get(0) {
-        let period_index = 0;
-        // for (period_index, period) in mpd.periods.iter().enumerate() {
         for (adaptation_index, adaptation_set) in period.adaptations.iter().enumerate() {
             for (representation_index, representation) in
                 adaptation_set.representations.iter().enumerate()
@@ -45,6 +98,7 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
                     match mime_type.as_str() {
                         "application/ttml+xml" | "application/x-sami" => MediaType::Subtitles,
                         x if x.starts_with("audio") => MediaType::Audio,
+                        x if x.starts_with("image") => MediaType::Image,
                         x if x.starts_with("text") => MediaType::Subtitles,
                         x if x.starts_with("video") => MediaType::Video,
                         _ => MediaType::Undefined,
@@ -55,23 +109,67 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
 
                 if media_type == MediaType::Undefined {
                     if let Some(codecs) = &codecs {
-                        media_type = match codecs.as_str() {
-                            "wvtt" | "stpp" => MediaType::Subtitles,
-                            x if x.starts_with("stpp.") => MediaType::Subtitles,
-                            _ => media_type,
-                        };
+                        media_type = codecs_media_type(codecs);
                     }
                 }
 
-                // if let Some(role) = &representation.role {
-                //     if let Some(value) = &role.value {
-                //         if value == "subtitle" {
-                //             media_type = MediaType::Subtitles;
-                //         }
-                //     }
-                // }
+                let roles = adaptation_set
+                    .Role
+                    .iter()
+                    .filter_map(|x| x.value.clone())
+                    .collect::<Vec<_>>();
 
+                if media_type == MediaType::Undefined
+                    && roles.iter().any(|x| x == "subtitle" || x == "caption")
+                {
+                    media_type = MediaType::Subtitles;
+                }
+
+                let location = DashUrl::new(period_index, adaptation_index, representation_index);
+                let track = Track {
+                    adaptation_set_id: adaptation_set.id.clone(),
+                    codec: codecs
+                        .as_ref()
+                        .and_then(|x| x.split(['.', ',']).next())
+                        .map(|x| x.trim().to_owned()),
+                    language: adaptation_set.lang.clone(),
+                    media_type: media_type.clone(),
+                    representation_id: representation.id.clone(),
+                    resolution: representation.width.zip(representation.height),
+                    roles: roles.clone(),
+                };
+
+                let best_match = tracks
+                    .iter()
+                    .enumerate()
+                    .take(matched.len())
+                    .filter(|(i, _)| !matched[*i])
+                    .filter_map(|(i, x)| {
+                        let bandwidth_difference = streams[i]
+                            .bandwidth
+                            .unwrap_or(0)
+                            .abs_diff(representation.bandwidth.unwrap_or(0));
+                        x.score(&track)
+                            .map(|score| (i, (score, std::cmp::Reverse(bandwidth_difference))))
+                    })
+                    .max_by_key(|x| x.1)
+                    .map(|x| x.0);
+
+                if let Some(i) = best_match {
+                    matched[i] = true;
+                    streams[i].uri += &format!(";{}", location.to_string());
+                    continue;
+                }
+
+                tracks.push(track);
                 streams.push(MediaPlaylist {
+                    audio_description: roles.iter().any(|x| x == "description")
+                        || adaptation_set
+                            .Accessibility
+                            .iter()
+                            .any(|x| accessibility_purpose(x) == Some("description")),
+                    audio_group: None,
+                    autoselect: false,
                     bandwidth: representation.bandwidth,
                     channels: representation
                         .AudioChannelConfiguration
@@ -83,10 +181,20 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
                             .get(0)
                             .and_then(|x| x.value.as_ref().map(|y| y.parse::<f32>().ok()))
                             .flatten()),
+                    closed_captions: adaptation_set
+                        .Accessibility
+                        .iter()
+                        .flat_map(parse_closed_captions)
+                        .collect(),
                     codecs,
+                    default: false,
+                    events: vec![],
                     extension: mime_type
                         .as_ref()
                         .and_then(|x| x.split_once('/').map(|x| x.1.to_owned())),
+                    forced: roles
+                        .iter()
+                        .any(|x| x == "forced-subtitle" || x == "forced_subtitle"),
                     frame_rate: if representation.frameRate.is_some() {
                         parse_frame_rate(&representation.frameRate)
                     } else if adaptation_set.frameRate.is_some() {
@@ -94,14 +202,29 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
                     } else {
                         None
                     },
+                    group_id: None,
                     i_frame: false, // Cannot be comment here
+                    inband_events: None,
+                    key: content_protection(
+                        representation
+                            .ContentProtection
+                            .iter()
+                            .chain(adaptation_set.ContentProtection.iter()),
+                    ),
                     language: adaptation_set.lang.clone(),
                     live: if let Some(mpdtype) = &mpd.mpdtype {
                         mpdtype == "dynamic"
                     } else {
                         false
                     },
-                    media_type,
+                    media_type: media_type.clone(),
+                    name: representation
+                        .Label
+                        .first()
+                        .or(adaptation_set.Label.first())
+                        .map(|x| x.content.trim().to_owned()),
+                    pathway_id: None,
+                    pathways: vec![],
                     playlist_type: PlaylistType::Dash,
                     resolution: if let (Some(width), Some(height)) =
                         (representation.width, representation.height)
@@ -110,9 +233,39 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
                     } else {
                         None
                     },
+                    roles,
+                    sdh: media_type == MediaType::Subtitles
+                        && (adaptation_set
+                            .Role
+                            .iter()
+                            .any(|x| x.value.as_deref() == Some("caption"))
+                            || adaptation_set
+                                .Accessibility
+                                .iter()
+                                .any(|x| accessibility_purpose(x) == Some("caption"))),
                     segments: vec![], // Cannot be comment here
-                    uri: DashUrl::new(period_index, adaptation_index, representation_index)
-                        .to_string(),
+                    start_offset: None,
+                    subtitles_group: None,
+                    tiles: representation
+                        .essential_property
+                        .iter()
+                        .chain(adaptation_set.essential_property.iter())
+                        .find_map(parse_thumbnail_tile),
+                    uri: location.to_string(),
+                    variables: HashMap::new(),
+                    video_range: representation
+                        .supplemental_property
+                        .iter()
+                        .chain(adaptation_set.supplemental_property.iter())
+                        .map(|x| (x.schemeIdUri.as_str(), x.value.as_deref()))
+                        .chain(
+                            representation
+                                .essential_property
+                                .iter()
+                                .chain(adaptation_set.essential_property.iter())
+                                .map(|x| (x.schemeIdUri.as_str(), x.value.as_deref())),
+                        )
+                        .find_map(parse_video_range),
                 });
             }
         }
@@ -125,26 +278,32 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
     }
 }
 
-pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &str) -> Result<()> {
-    let location = playlist.uri.parse::<DashUrl>().map_err(|x| anyhow!(x))?;
-
-    for (_period_index, period) in mpd.periods.iter().enumerate() {
+pub(crate) fn push_segments(
+    mpd: &MPD,
+    playlist: &mut MediaPlaylist,
+    base_url: &str,
+    client: &Client,
+) -> Result<()> {
+    let locations = playlist
+        .uri
+        .split(';')
+        .map(|x| x.parse::<DashUrl>().map_err(|x| anyhow!(x)))
+        .collect::<Result<Vec<_>>>()?;
+
+    for (period_index, period) in mpd.periods.iter().enumerate() {
         for (adaptation_index, adaptation_set) in period.adaptations.iter().enumerate() {
             for (representation_index, representation) in
                 adaptation_set.representations.iter().enumerate()
             {
-                if adaptation_index == location.adaptation_set
-                    && representation_index == location.representation
-                {
-                    let mut period_duration_secs = 0.0;
-
-                    if let Some(duration) = &mpd.mediaPresentationDuration {
-                        period_duration_secs = duration.as_secs_f32();
-                    }
-
-                    if let Some(duration) = &period.duration {
-                        period_duration_secs = duration.as_secs_f32();
-                    }
+                // Each period is a separate discontinuity, so that periods can be joined
+                // or saved separately after downloading.
+                if let Some(discontinuity_sequence) = locations.iter().position(|x| {
+                    x.period == period_index
+                        && x.adaptation_set == adaptation_index
+                        && x.representation == representation_index
+                }) {
+                    let period_start = playlist.segments.len();
+                    let period_duration_secs = period_duration(mpd, period_index);
 
                     let mut base_url = base_url.parse::<Url>().unwrap();
 
@@ -291,71 +450,30 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                         // (2) SegmentTemplate+SegmentTimeline (explicit addressing)
                         if let Some(segment_timeline) = &segment_template.SegmentTimeline {
                             if let Some(media) = &segment_template.media {
-                                let media = template.resolve(media);
-                                let timescale = segment_template.timescale.unwrap_or(1) as f32;
-                                let mut segment_time = 0;
-                                let mut number = segment_template.startNumber.unwrap_or(1);
-
-                                for s in &segment_timeline.segments {
-                                    if let Some(t) = s.t {
-                                        segment_time = t;
-                                    }
+                                let timescale = segment_template.timescale.unwrap_or(1);
+                                let presentation_time_offset =
+                                    segment_template.presentationTimeOffset.unwrap_or(0);
+                                let period_end = (period_duration_secs > 0.0).then(|| {
+                                    presentation_time_offset
+                                        + (period_duration_secs as f64 * timescale as f64).round()
+                                            as u64
+                                });
 
-                                    template.insert("Time", segment_time.to_string());
-                                    template.insert("Number", number.to_string());
+                                for segment in timeline::expand(
+                                    segment_timeline,
+                                    segment_template.startNumber.unwrap_or(1),
+                                    presentation_time_offset,
+                                    period_end,
+                                ) {
+                                    template.insert("Time", segment.time.to_string());
+                                    template.insert("Number", segment.number.to_string());
+                                    template.insert("SubNumber", segment.sub_number.to_string());
 
                                     playlist.segments.push(Segment {
-                                        duration: s.d as f32 / timescale,
-                                        uri: base_url.join(&template.resolve(&media))?.to_string(),
+                                        duration: segment.duration as f32 / timescale as f32,
+                                        uri: base_url.join(&template.resolve(media))?.to_string(),
                                         ..Default::default()
                                     });
-
-                                    number += 1;
-
-                                    // let mut repeat_count = s.r.unwrap_or(0);
-                                    // if repeat_count < 0 {
-                                    //     repeat_count = ((period_duration_secs * timescale / s.d as f32) - 1.0) as i64;
-                                    // }
-                                    // for _ in 0..repeat_count {}
-
-                                    if let Some(r) = s.r {
-                                        let mut count = 0;
-                                        // FIXME - Perhaps we also need to account for startTime?
-                                        let end_time = period_duration_secs * timescale;
-
-                                        loop {
-                                            count += 1;
-                                            // Exit from the loop after @r iterations (if @r is
-                                            // positive). A negative value of the @r attribute indicates
-                                            // that the duration indicated in @d attribute repeats until
-                                            // the start of the next S element, the end of the Period or
-                                            // until the next MPD update.
-                                            if r >= 0 {
-                                                if count > r {
-                                                    break;
-                                                }
-                                            } else if segment_time as f32 > end_time {
-                                                break;
-                                            }
-
-                                            segment_time += s.d;
-
-                                            template.insert("Time", segment_time.to_string());
-                                            template.insert("Number", number.to_string());
-
-                                            playlist.segments.push(Segment {
-                                                duration: s.d as f32 / timescale,
-                                                uri: base_url
-                                                    .join(&template.resolve(&media))?
-                                                    .to_string(),
-                                                ..Default::default()
-                                            });
-
-                                            number += 1;
-                                        }
-                                    }
-
-                                    segment_time += s.d;
                                 }
                             } else {
                                 bail!("SegmentTimeline without a media attribute.");
@@ -363,7 +481,6 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                         } else {
                             // (3) SegmentTemplate@duration || (4) SegmentTemplate@index (simple addressing)
                             if let Some(media) = &segment_template.media {
-                                let media = template.resolve(media);
                                 let timescale = segment_template.timescale.unwrap_or(1) as f32;
                                 let mut duration = 0.0;
 
@@ -379,25 +496,23 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                                     bail!("Representation is missing SegmentTemplate @duration attribute.");
                                 }
 
-                                let mut number = segment_template.startNumber.unwrap_or(1) as i64;
+                                let start_number = segment_template.startNumber.unwrap_or(1) as i64;
 
                                 let mut total_number =
-                                    number + (period_duration_secs / duration).ceil() as i64;
+                                    start_number + (period_duration_secs / duration).ceil() as i64;
 
                                 if init_map.is_some() {
                                     total_number -= 1;
                                 }
 
-                                for _ in 1..=total_number {
+                                for (number, _) in (start_number..).zip(0..total_number) {
                                     template.insert("Number", number.to_string());
 
                                     playlist.segments.push(Segment {
                                         duration,
-                                        uri: base_url.join(&template.resolve(&media))?.to_string(),
+                                        uri: base_url.join(&template.resolve(media))?.to_string(),
                                         ..Default::default()
                                     });
-
-                                    number += 1;
                                 }
                             }
                         }
@@ -405,21 +520,16 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                         // (5) SegmentBase@indexRange
                         // The SegmentBase@indexRange attribute points to a byte range in the media
                         // file that contains index information (an sidx box for MPEG files, or a
-                        // Cues entry for a DASH-WebM stream). To be fully compliant, we should
-                        // download and parse these (for example using the sidx crate) then download
-                        // the referenced content segments. In practice, it seems that the
-                        // indexRange information is mostly provided by DASH encoders to allow
-                        // clients to rewind and fast-foward a stream, and is not necessary if we
-                        // download the full content specified by BaseURL.
-                        //
-                        // Our strategy: if there is a SegmentBase > Initialization > SourceURL
-                        // node, download that first, respecting the byte range if it is specified.
-                        // Otherwise, download the full content specified by the BaseURL for this
-                        // segment (ignoring any indexRange attributes).
+                        // Cues entry for a DASH-WebM stream). The sidx box is downloaded and each
+                        // subsegment referenced by it is pushed as a ranged segment. If the index
+                        // couldn't be parsed (e.g. WebM Cues), the full content specified by
+                        // BaseURL is downloaded as a single segment.
                         //
                         // https://github.com/shaka-project/shaka-player/blob/main/lib/dash/segment_base.js
                         // https://github.com/shaka-project/shaka-player/blob/main/lib/media/mp4_segment_index_parser.js
 
+                        let index_range = parse_range(&segment_base.indexRange);
+
                         if let Some(initialization) = &segment_base.initialization {
                             let byte_range = parse_range(&initialization.range);
 
@@ -428,14 +538,100 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                                     range: byte_range,
                                     uri: base_url.join(&template.resolve(source_url))?.to_string(),
                                 });
+                            } else if byte_range.is_some() {
+                                init_map = Some(Map {
+                                    range: byte_range,
+                                    uri: base_url.to_string(),
+                                });
                             }
                         }
 
-                        playlist.segments.push(Segment {
-                            uri: base_url.to_string(),
-                            ..Default::default()
-                        });
-                    } else if playlist.segments.is_empty() && !representation.BaseURL.is_empty() {
+                        let sidx = if let Some(index_range) = &index_range {
+                            let response = client
+                                .get(base_url.clone())
+                                .header(header::RANGE, index_range.as_header_value())
+                                .send()?
+                                .error_for_status()?;
+                            let content_range_start = response
+                                .headers()
+                                .get(header::CONTENT_RANGE)
+                                .and_then(|x| x.to_str().ok())
+                                .and_then(|x| x.strip_prefix("bytes "))
+                                .and_then(|x| x.split_once('-'))
+                                .and_then(|x| x.0.trim().parse::<u64>().ok());
+
+                            if response.status() == StatusCode::PARTIAL_CONTENT
+                                || content_range_start.is_some()
+                            {
+                                match Sidx::new(
+                                    &response.bytes()?,
+                                    content_range_start.unwrap_or(index_range.start),
+                                ) {
+                                    Ok(sidx) => Some(sidx),
+                                    Err(e) => {
+                                        eprintln!(
+                                            "    {} couldn't parse segment index of {} ({}), downloading it as a single segment",
+                                            "Warning".colorize("bold yellow"),
+                                            base_url,
+                                            e
+                                        );
+                                        None
+                                    }
+                                }
+                            } else {
+                                // Servers which ignore range requests respond with the whole file,
+                                // which is left unread here as it is downloaded as a single segment.
+                                eprintln!(
+                                    "    {} {} doesn't support range requests, downloading it as a single segment",
+                                    "Warning".colorize("bold yellow"),
+                                    base_url
+                                );
+                                None
+                            }
+                        } else {
+                            None
+                        };
+
+                        if let (Some(sidx), Some(index_range)) = (sidx, &index_range) {
+                            // Without an Initialization node, initialization data is everything
+                            // before the index.
+                            if init_map.is_none() && index_range.start > 0 {
+                                init_map = Some(Map {
+                                    range: Some(Range {
+                                        start: 0,
+                                        end: index_range.start - 1,
+                                    }),
+                                    uri: base_url.to_string(),
+                                });
+                            }
+
+                            for reference in sidx.references {
+                                playlist.segments.push(Segment {
+                                    duration: reference.duration as f32,
+                                    range: Some(Range {
+                                        start: reference.start,
+                                        end: reference.end,
+                                    }),
+                                    uri: base_url.to_string(),
+                                    ..Default::default()
+                                });
+                            }
+                        } else {
+                            // Whole file already contains initialization data.
+                            if init_map.as_ref().map(|x| x.uri.as_str()) == Some(base_url.as_str())
+                            {
+                                init_map = None;
+                            }
+
+                            playlist.segments.push(Segment {
+                                duration: period_duration_secs,
+                                uri: base_url.to_string(),
+                                ..Default::default()
+                            });
+                        }
+                    } else if playlist.segments.len() == period_start
+                        && !representation.BaseURL.is_empty()
+                    {
                         // (6) Plain BaseURL
                         playlist.segments.push(Segment {
                             duration: period_duration_secs,
@@ -444,55 +640,84 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                         });
                     }
 
-                    if playlist.segments.is_empty() {
+                    if playlist.segments.len() == period_start {
                         bail!("no usable addressing mode identified for representation.");
                     }
 
-                    if let Some(first_segment) = playlist.segments.get_mut(0) {
-                        let mut encryption_type = KeyMethod::None;
-                        let mut default_kid = None;
+                    for segment in &mut playlist.segments[period_start..] {
+                        segment.discontinuity_sequence = discontinuity_sequence as u64;
+                    }
+
+                    let period_stream_start = playlist.segments[..period_start]
+                        .iter()
+                        .map(|x| x.duration as f64)
+                        .sum::<f64>();
+                    playlist
+                        .events
+                        .extend(period_events(period, period_stream_start));
+                    playlist
+                        .events
+                        .sort_by(|x, y| x.start_time.total_cmp(&y.start_time));
+
+                    // Periods which start inside an ad break (SCTE-35) are ads.
+                    if playlist
+                        .events
+                        .iter()
+                        .any(|x| x.is_ad() && x.contains(period_stream_start))
+                    {
+                        for segment in &mut playlist.segments[period_start..] {
+                            segment.ad = true;
+                        }
+                    }
 
-                        for content_protection in &representation.ContentProtection {
-                            if default_kid.is_none() && content_protection.default_KID.is_some() {
-                                default_kid = content_protection.default_KID.clone();
-                            }
+                    let inband_schemes = representation
+                        .InbandEventStream
+                        .iter()
+                        .chain(adaptation_set.InbandEventStream.iter())
+                        .filter_map(|x| x.schemeIdUri.clone())
+                        .collect::<Vec<_>>();
 
-                            // content_protection.value = "cenc" | "cbcs"
-                            if encryption_type == KeyMethod::None
-                                && content_protection.value.is_some()
-                            {
-                                encryption_type = KeyMethod::Cenc;
-                            }
+                    if !inband_schemes.is_empty() {
+                        let presentation_time_offset = representation
+                            .SegmentTemplate
+                            .as_ref()
+                            .or(adaptation_set.SegmentTemplate.as_ref())
+                            .map(|x| (x.presentationTimeOffset, x.timescale))
+                            .or(representation
+                                .SegmentBase
+                                .as_ref()
+                                .map(|x| (x.presentationTimeOffset, x.timescale)))
+                            .map(|(pto, timescale)| {
+                                pto.unwrap_or(0) as f64 / timescale.unwrap_or(1) as f64
+                            })
+                            .unwrap_or(0.0);
+                        let inband_events = playlist
+                            .inband_events
+                            .get_or_insert_with(InbandEvents::default);
+
+                        if inband_events.offsets.len() <= discontinuity_sequence {
+                            inband_events
+                                .offsets
+                                .resize(discontinuity_sequence + 1, 0.0);
                         }
 
-                        if encryption_type == KeyMethod::None || default_kid.is_none() {
-                            for content_protection in &adaptation_set.ContentProtection {
-                                if default_kid.is_none() && content_protection.default_KID.is_some()
-                                {
-                                    default_kid = content_protection.default_KID.clone();
-                                }
+                        inband_events.offsets[discontinuity_sequence] =
+                            period_stream_start - presentation_time_offset;
 
-                                if encryption_type == KeyMethod::None
-                                    && content_protection.value.is_some()
-                                {
-                                    encryption_type = KeyMethod::Cenc;
-                                }
+                        for scheme in inband_schemes {
+                            if !inband_events.schemes.contains(&scheme) {
+                                inband_events.schemes.push(scheme);
                             }
                         }
+                    }
 
-                        default_kid = default_kid.map(|x| x.to_lowercase());
-
-                        first_segment.key = match encryption_type {
-                            KeyMethod::None => None,
-                            x => Some(Key {
-                                default_kid,
-                                iv: None,
-                                key_format: None,
-                                method: x,
-                                uri: None,
-                            }),
-                        };
-
+                    if let Some(first_segment) = playlist.segments.get_mut(period_start) {
+                        first_segment.key = content_protection(
+                            representation
+                                .ContentProtection
+                                .iter()
+                                .chain(adaptation_set.ContentProtection.iter()),
+                        );
                         first_segment.map = init_map;
                     }
                 }
@@ -624,6 +849,266 @@ fn parse_frame_rate(frame_rate: &Option<String>) -> Option<f32> {
     })
 }
 
+/// Parse thumbnail tile descriptor e.g. `<EssentialProperty schemeIdUri="http://dashif.org/thumbnail_tile" value="10x20"/>`.
+fn parse_thumbnail_tile(essential_property: &EssentialProperty) -> Option<(u64, u64)> {
+    if !matches!(
+        essential_property.schemeIdUri.as_str(),
+        "http://dashif.org/thumbnail_tile" | "http://dashif.org/guidelines/thumbnail_tile"
+    ) {
+        return None;
+    }
+
+    let (columns, rows) = essential_property.value.as_ref()?.split_once(['x', 'X'])?;
+    Some((columns.trim().parse().ok()?, rows.trim().parse().ok()?))
+}
+
+/// HLS `VIDEO-RANGE` equivalent of a transfer characteristics descriptor
+/// e.g. `<SupplementalProperty schemeIdUri="urn:mpeg:mpegB:cicp:TransferCharacteristics" value="16"/>`.
+fn parse_video_range((scheme_id_uri, value): (&str, Option<&str>)) -> Option<String> {
+    if scheme_id_uri != "urn:mpeg:mpegB:cicp:TransferCharacteristics" {
+        return None;
+    }
+
+    match value?.trim() {
+        "1" | "6" | "13" | "14" | "15" => Some("SDR".to_owned()),
+        "16" => Some("PQ".to_owned()),
+        "18" => Some("HLG".to_owned()),
+        _ => None,
+    }
+}
+
+/// Purpose of an accessibility descriptor, `description` (for visually impaired)
+/// or `caption` (for hard of hearing).
+fn accessibility_purpose(accessibility: &Accessibility) -> Option<&str> {
+    match (
+        accessibility.schemeIdUri.as_deref(),
+        accessibility.value.as_deref(),
+    ) {
+        (Some("urn:tva:metadata:cs:AudioPurposeCS:2007"), Some("1")) => Some("description"),
+        (Some("urn:tva:metadata:cs:AudioPurposeCS:2007"), Some("2")) => Some("caption"),
+        (Some("urn:mpeg:dash:role:2011"), Some(x @ ("description" | "caption"))) => Some(x),
+        _ => None,
+    }
+}
+
+/// Parse CEA-608/708 accessibility descriptors e.g. `CC1=eng;CC3=deu` or `1=lang:eng;2=lang:deu`.
+fn parse_closed_captions(accessibility: &Accessibility) -> Vec<ClosedCaption> {
+    let (prefix, value) = match (
+        accessibility.schemeIdUri.as_deref(),
+        accessibility.value.as_deref(),
+    ) {
+        (Some("urn:scte:dash:cc:cea-608:2015"), Some(value)) => ("CC", value),
+        (Some("urn:scte:dash:cc:cea-708:2015"), Some(value)) => ("SERVICE", value),
+        _ => return vec![],
+    };
+
+    value
+        .split(';')
+        .filter(|x| !x.trim().is_empty())
+        .enumerate()
+        .map(|(i, x)| {
+            let (channel, language) = match x.split_once('=') {
+                Some((channel, language)) => (
+                    channel.trim().trim_start_matches("CC").to_owned(),
+                    language.trim(),
+                ),
+                // Channel numbers are optional for CEA-608, languages are then listed in
+                // order of CC1, CC3 (field 1 and field 2 of first channel).
+                None if prefix == "CC" => ((i * 2 + 1).to_string(), x.trim()),
+                None => ((i + 1).to_string(), x.trim()),
+            };
+            // CEA-708 languages may contain additional parameters such as `lang:eng,war:1`.
+            let language = language
+                .split(',')
+                .find_map(|x| x.strip_prefix("lang:"))
+                .unwrap_or(language);
+
+            ClosedCaption {
+                channel: format!("{}{}", prefix, channel),
+                language: Some(language.to_owned()),
+                name: None,
+            }
+        })
+        .collect()
+}
+
+const PLAYREADY_SYSTEM_ID: &str = "9a04f07998404286ab92e65be0885f95";
+const WIDEVINE_SYSTEM_ID: &str = "edef8ba979d64acea3c827dcd51d21ed";
+
+/// System ids of DRM systems, which are signalled as `urn:uuid:<system id>` schemes.
+const DRM_SYSTEM_IDS: [&str; 8] = [
+    "1077efec-c0b2-4d02-ace3-3c1e52e2fb4b", // W3C Common PSSH box
+    "3d5e6d35-9b9a-41e8-b843-dd3c6e72c42c", // ChinaDRM
+    "5e629af5-38da-4063-8977-97ffbd9902d4", // Marlin
+    "94ce86fb-07ff-4f43-adb8-93d2fa968ca2", // FairPlay
+    "9a04f079-9840-4286-ab92-e65be0885f95", // PlayReady
+    "e2719d58-a985-b3c9-781a-b030af78d30e", // ClearKey
+    "edef8ba9-79d6-4ace-a3c8-27dcd51d21ed", // Widevine
+    "f239e769-efa3-4850-9c16-a903c6932efb", // Adobe Primetime
+];
+
+/// Encryption of a representation, declared by its (and its adaptation set's)
+/// `ContentProtection` elements. Elements of other schemes (e.g. watermarking or
+/// output protection descriptors) don't make a representation encrypted.
+fn content_protection<'a>(
+    content_protections: impl Iterator<Item = &'a ContentProtection>,
+) -> Option<Key> {
+    let mut default_kid = None;
+    let mut key_ids: Vec<KeyId> = vec![];
+    let mut protected = false;
+    let mut psshs_data = vec![];
+    let mut scheme = None;
+
+    for content_protection in content_protections {
+        let scheme_id_uri = content_protection
+            .schemeIdUri
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+
+        if scheme_id_uri == "urn:mpeg:dash:mp4protection:2011"
+            || scheme_id_uri
+                .strip_prefix("urn:uuid:")
+                .is_some_and(|x| DRM_SYSTEM_IDS.contains(&x))
+        {
+            protected = true;
+        } else {
+            continue;
+        }
+
+        if default_kid.is_none() {
+            default_kid = content_protection
+                .default_KID
+                .as_ref()
+                .map(|x| x.to_lowercase());
+        }
+
+        // <ContentProtection schemeIdUri="urn:mpeg:dash:mp4protection:2011" value="cenc" />
+        if scheme.is_none() && scheme_id_uri == "urn:mpeg:dash:mp4protection:2011" {
+            scheme = content_protection.value.as_ref().map(|x| x.to_lowercase());
+        }
+
+        let mut psshs = vec![];
+
+        for data in content_protection
+            .cenc_pssh
+            .iter()
+            .filter_map(|x| x.content.as_ref())
+            .filter_map(|x| utils::decode_base64(x.trim()).ok())
+        {
+            let Ok(pssh) = Pssh::new(&data) else {
+                continue;
+            };
+
+            match pssh.system_ids.first().map(|x| x.as_str()) {
+                Some(WIDEVINE_SYSTEM_ID) => push_pssh(&mut psshs_data, "widevine", data.clone()),
+                Some(PLAYREADY_SYSTEM_ID) => {
+                    if let Some(object) = pssh_data(&data) {
+                        push_pssh(&mut psshs_data, "playready", object.to_vec());
+                    }
+                }
+                _ => (),
+            }
+
+            psshs.push(pssh);
+        }
+
+        if let Some(object) = content_protection
+            .msprpro
+            .as_ref()
+            .and_then(|x| x.content.as_ref())
+            .and_then(|x| utils::decode_base64(x.trim()).ok())
+        {
+            if let Ok(pssh) = Pssh::from_playready_object(&object) {
+                push_pssh(&mut psshs_data, "playready", object);
+                psshs.push(pssh);
+            }
+        }
+
+        for key_id in psshs.into_iter().flat_map(|x| x.key_ids) {
+            if !key_ids.iter().any(|x| x.value == key_id.value) {
+                key_ids.push(KeyId {
+                    system: key_id.system_type.to_string(),
+                    value: key_id.value,
+                });
+            }
+        }
+    }
+
+    if !protected {
+        return None;
+    }
+
+    Some(Key {
+        default_kid,
+        iv: None,
+        key_format: None,
+        key_ids,
+        method: KeyMethod::Cenc,
+        psshs: psshs_data,
+        scheme,
+        uri: None,
+    })
+}
+
+fn push_pssh(psshs: &mut Vec<(String, Vec<u8>)>, system: &str, data: Vec<u8>) {
+    if !psshs.iter().any(|x| x.1 == data) {
+        psshs.push((system.to_owned(), data));
+    }
+}
+
+/// Data of a `pssh` box (playready object for playready system id).
+fn pssh_data(pssh: &[u8]) -> Option<&[u8]> {
+    let u32_at = |offset: usize| {
+        pssh.get(offset..(offset + 4))
+            .map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]]) as usize)
+    };
+
+    let mut offset = 28;
+
+    // Version 1 boxes have key ids before data.
+    if *pssh.get(8)? > 0 {
+        offset += 4 + 16 * u32_at(offset)?;
+    }
+
+    let size = u32_at(offset)?;
+    pssh.get((offset + 4)..(offset + 4 + size))
+}
+
+/// Events of period's event streams, timed relative to the start of stream.
+fn period_events(period: &Period, period_stream_start: f64) -> Vec<Event> {
+    let mut events = vec![];
+
+    for event_stream in &period.event_streams {
+        let Some(scheme_id_uri) = &event_stream.schemeIdUri else {
+            continue;
+        };
+
+        let timescale = event_stream.timescale.unwrap_or(1).max(1) as f64;
+        let presentation_time_offset = event_stream.presentationTimeOffset.unwrap_or(0) as f64;
+
+        for event in &event_stream.event {
+            events.push(Event {
+                duration: event.duration.map(|x| x as f64 / timescale),
+                id: event.id.clone(),
+                message_data: event
+                    .messageData
+                    .clone()
+                    .or(event.content.as_ref().map(|x| x.trim().to_owned()))
+                    .filter(|x| !x.is_empty()),
+                scheme_id_uri: scheme_id_uri.to_owned(),
+                start_time: period_stream_start
+                    + (event.presentationTime.unwrap_or(0) as f64 - presentation_time_offset)
+                        / timescale,
+                value: event_stream.value.clone(),
+            });
+        }
+    }
+
+    events
+}
+
 fn parse_range(range: &Option<String>) -> Option<Range> {
     range.as_ref().map(|range| {
         if let Some((Some(start), Some(end))) = range
@@ -637,28 +1122,359 @@ fn parse_range(range: &Option<String>) -> Option<Range> {
     })
 }
 
-// // Sec-Fetch-Mode: navigate
-// // Upgrade-Insecure-Requests: 1
-// /*
-// A manifest may use a data URL (RFC 2397) to embed media content such as the
-// initialization segment directly in the manifest (recommended by YouTube for live
-// streaming, but uncommon in practice).
-//  */
-// if url.scheme() == "data" {
-//     let us = &url.to_string();
-//     let du = DataUrl::process(us)
-//         .map_err(|_| DashMpdError::Parsing(String::from("parsing data URL")))?;
-//     if du.mime_type().type_ != "audio" {
-//         return Err(DashMpdError::UnhandledMediaStream(
-//             String::from("expecting audio content in data URL")));
-//     }
-//     let (body, _fragment) = du.decode_to_vec()
-//         .map_err(|_| DashMpdError::Parsing(String::from("decoding data URL")))?;
-//     if downloader.verbosity > 2 {
-//         println!("Audio segment data URL -> {} octets", body.len());
-//     }
-//     if let Err(e) = tmpfile_audio.write_all(&body) {
-//         log::error!("Unable to write DASH audio data: {e:?}");
-//         return Err(DashMpdError::Io(e, String::from("writing DASH audio data")));
-//     }
-//     have_audio = true;
+/// Start of a period in seconds, a period without `@start` begins where previous period ends
+/// (ISO/IEC 23009-1 Section 5.3.2.1).
+fn period_start(mpd: &MPD, index: usize) -> Option<f32> {
+    if let Some(start) = &mpd.periods[index].start {
+        return Some(start.as_secs_f32());
+    }
+
+    if index == 0 {
+        return Some(0.0);
+    }
+
+    Some(period_start(mpd, index - 1)? + mpd.periods[index - 1].duration?.as_secs_f32())
+}
+
+/// Duration of a period in seconds, it lasts until the start of next period or until the end of
+/// presentation for the last period.
+fn period_duration(mpd: &MPD, index: usize) -> f32 {
+    if let Some(duration) = &mpd.periods[index].duration {
+        return duration.as_secs_f32();
+    }
+
+    let start = period_start(mpd, index);
+    let end = if index + 1 < mpd.periods.len() {
+        period_start(mpd, index + 1)
+    } else {
+        mpd.mediaPresentationDuration.map(|x| x.as_secs_f32())
+    };
+
+    match (start, end) {
+        (Some(start), Some(end)) => (end - start).max(0.0),
+        _ => 0.0,
+    }
+}
+
+fn codecs_media_type(codecs: &str) -> MediaType {
+    // https://dashif.org/codecs/introduction
+    let codec = codecs.split(',').next().unwrap_or_default().trim();
+
+    match codec.split('.').next().unwrap_or_default() {
+        "stpp" | "wvtt" => MediaType::Subtitles,
+        "av01" | "avc1" | "avc3" | "dva1" | "dvav" | "dvh1" | "dvhe" | "hev1" | "hvc1" | "vp08"
+        | "vp09" => MediaType::Video,
+        "ac-3" | "ac-4" | "dtsc" | "dtse" | "dtsh" | "dtsl" | "ec-3" | "flac" | "mha1" | "mhm1"
+        | "mp4a" | "opus" => MediaType::Audio,
+        _ => MediaType::Undefined,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        codecs_media_type, content_protection, parse_as_master, period_duration, period_events,
+        push_segments,
+    };
+    use crate::playlist::{MediaPlaylist, MediaType};
+    use reqwest::blocking::Client;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Serve a response (extra headers and body) once.
+    fn serve(status: &'static str, headers: &'static str, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                headers,
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        });
+
+        url
+    }
+
+    fn segment_base(base_url: &str) -> MediaPlaylist {
+        let mpd = dash_mpd::parse(
+            r#"<MPD mediaPresentationDuration="PT6S"><Period><AdaptationSet mimeType="video/mp4">
+<Representation id="v" bandwidth="1000"><BaseURL>video.mp4</BaseURL><SegmentBase indexRange="100-155"/></Representation>
+</AdaptationSet></Period></MPD>"#,
+        )
+        .unwrap();
+        let mut playlist = MediaPlaylist {
+            uri: "dash://period.0.adaptation-set.0.representation.0".to_owned(),
+            ..Default::default()
+        };
+        push_segments(
+            &mpd,
+            &mut playlist,
+            base_url,
+            &Client::builder().no_proxy().build().unwrap(),
+        )
+        .unwrap();
+        playlist
+    }
+
+    fn key(content_protections: &str) -> Option<super::Key> {
+        let mpd = dash_mpd::parse(&format!(
+            r#"<MPD xmlns:cenc="urn:mpeg:cenc:2013"><Period><AdaptationSet>{}</AdaptationSet></Period></MPD>"#,
+            content_protections
+        ))
+        .unwrap();
+        content_protection(mpd.periods[0].adaptations[0].ContentProtection.iter())
+    }
+
+    #[test]
+    fn drm_schemes() {
+        let cenc = key(
+            r#"<ContentProtection schemeIdUri="urn:mpeg:dash:mp4protection:2011" value="cenc" cenc:default_KID="9EB4050D-E44B-4802-932E-27D75083E266"/>
+<ContentProtection schemeIdUri="urn:uuid:EDEF8BA9-79D6-4ACE-A3C8-27DCD51D21ED"/>"#,
+        )
+        .unwrap();
+        assert_eq!(cenc.scheme.as_deref(), Some("cenc"));
+        assert_eq!(
+            cenc.default_kid.as_deref(),
+            Some("9eb4050d-e44b-4802-932e-27d75083e266")
+        );
+
+        assert!(key(
+            r#"<ContentProtection schemeIdUri="urn:uuid:9a04f079-9840-4286-ab92-e65be0885f95"/>"#
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn other_schemes() {
+        assert!(key("").is_none());
+        assert!(key(
+            r#"<ContentProtection schemeIdUri="urn:example:watermarking:2024" cenc:default_KID="9eb4050d-e44b-4802-932e-27d75083e266"/>"#
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn media_type_from_codecs() {
+        assert!(codecs_media_type("avc1.64001f") == MediaType::Video);
+        assert!(codecs_media_type("hvc1.1.6.L93.B0") == MediaType::Video);
+        assert!(codecs_media_type("av01.0.04M.08") == MediaType::Video);
+        assert!(codecs_media_type("vp09.00.10.08") == MediaType::Video);
+        assert!(codecs_media_type("mp4a.40.2") == MediaType::Audio);
+        assert!(codecs_media_type("ec-3") == MediaType::Audio);
+        assert!(codecs_media_type("stpp.ttml.im1t") == MediaType::Subtitles);
+        assert!(codecs_media_type("wvtt") == MediaType::Subtitles);
+        assert!(codecs_media_type("avc1.4d401f,mp4a.40.2") == MediaType::Video);
+        assert!(codecs_media_type("evte") == MediaType::Undefined);
+    }
+
+    #[test]
+    fn implicit_period_starts() {
+        let mpd = dash_mpd::parse(
+            r#"<MPD mediaPresentationDuration="PT45S">
+<Period duration="PT10S"/>
+<Period duration="PT20S"/>
+<Period/>
+</MPD>"#,
+        )
+        .unwrap();
+        assert_eq!(period_duration(&mpd, 0), 10.0);
+        assert_eq!(period_duration(&mpd, 1), 20.0);
+        assert_eq!(period_duration(&mpd, 2), 15.0);
+
+        let mpd = dash_mpd::parse(
+            r#"<MPD mediaPresentationDuration="PT60S">
+<Period start="PT0S"/>
+<Period start="PT25S" duration="PT5S"/>
+<Period/>
+</MPD>"#,
+        )
+        .unwrap();
+        assert_eq!(period_duration(&mpd, 0), 25.0);
+        assert_eq!(period_duration(&mpd, 1), 5.0);
+        assert_eq!(period_duration(&mpd, 2), 30.0);
+    }
+
+    #[test]
+    fn segment_base_index() {
+        // v0 sidx box (56 bytes) with references of 100 and 200 bytes lasting 4s and 2s.
+        let sidx = hex::decode("00000038736964780000000000000001000003e80000000000000000000000020000006400000fa090000000000000c8000007d090000000").unwrap();
+        let url = serve(
+            "206 Partial Content",
+            "Content-Range: bytes 100-155/1000\r\n",
+            sidx,
+        );
+        let playlist = segment_base(&url);
+
+        let ranges = playlist
+            .segments
+            .iter()
+            .map(|x| {
+                let range = x.range.as_ref().unwrap();
+                (range.start, range.end, x.duration)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(156, 255, 4.0), (256, 455, 2.0)]);
+        assert_eq!(playlist.segments[0].uri, format!("{}video.mp4", url));
+
+        // Initialization data is everything before the index.
+        let map = playlist.segments[0].map.as_ref().unwrap();
+        let range = map.range.as_ref().unwrap();
+        assert_eq!((range.start, range.end), (0, 99));
+    }
+
+    #[test]
+    fn segment_base_without_range_requests() {
+        let url = serve("200 OK", "", vec![0; 1000]);
+        let playlist = segment_base(&url);
+
+        assert_eq!(playlist.segments.len(), 1);
+        assert!(playlist.segments[0].range.is_none());
+        assert!(playlist.segments[0].map.is_none());
+        assert_eq!(playlist.segments[0].duration, 6.0);
+    }
+
+    /// Locations (`period.adaptation-set.representation`) of each stream.
+    fn locations(mpd: &str) -> Vec<String> {
+        parse_as_master(
+            &dash_mpd::parse(mpd).unwrap(),
+            "https://example.com/manifest.mpd",
+        )
+        .streams
+        .iter()
+        .map(|x| {
+            x.uri
+                .split(';')
+                .map(|x| {
+                    x.replace("dash://period.", "")
+                        .replace("adaptation-set.", "")
+                        .replace("representation.", "")
+                })
+                .collect::<Vec<_>>()
+                .join(";")
+        })
+        .collect()
+    }
+
+    #[test]
+    fn match_periods_by_id() {
+        let period = r#"<Period>
+<AdaptationSet id="1" mimeType="video/mp4" codecs="avc1.64001f">
+<Representation id="720p" bandwidth="3000000" width="1280" height="720"/>
+<Representation id="1080p" bandwidth="6000000" width="1920" height="1080"/>
+</AdaptationSet>
+<AdaptationSet id="2" mimeType="audio/mp4" codecs="mp4a.40.2" lang="en">
+<Representation id="audio" bandwidth="128000"/>
+</AdaptationSet>
+</Period>"#;
+
+        assert_eq!(
+            locations(&format!("<MPD>{}{}</MPD>", period, period)),
+            ["0.0.0;1.0.0", "0.0.1;1.0.1", "0.1.0;1.1.0"]
+        );
+    }
+
+    #[test]
+    fn match_periods_by_attributes() {
+        // Second period has different ids, a different order and other bandwidths.
+        assert_eq!(
+            locations(
+                r#"<MPD>
+<Period>
+<AdaptationSet mimeType="video/mp4" codecs="avc1.64001f">
+<Representation id="v1" bandwidth="3000000" width="1280" height="720"/>
+<Representation id="v2" bandwidth="6000000" width="1920" height="1080"/>
+</AdaptationSet>
+<AdaptationSet mimeType="audio/mp4" codecs="mp4a.40.2" lang="en">
+<Representation id="a1" bandwidth="128000"/>
+</AdaptationSet>
+<AdaptationSet mimeType="audio/mp4" codecs="mp4a.40.2" lang="fr">
+<Representation id="a2" bandwidth="128000"/>
+</AdaptationSet>
+</Period>
+<Period>
+<AdaptationSet mimeType="audio/mp4" codecs="mp4a.40.5" lang="fr">
+<Representation id="ad-a2" bandwidth="96000"/>
+</AdaptationSet>
+<AdaptationSet mimeType="audio/mp4" codecs="mp4a.40.2" lang="en">
+<Representation id="ad-a1" bandwidth="96000"/>
+</AdaptationSet>
+<AdaptationSet mimeType="video/mp4" codecs="avc1.4d401f">
+<Representation id="ad-v2" bandwidth="5000000" width="1920" height="1080"/>
+<Representation id="ad-v1" bandwidth="2000000" width="1280" height="720"/>
+</AdaptationSet>
+</Period>
+</MPD>"#
+            ),
+            ["0.0.0;1.2.1", "0.0.1;1.2.0", "0.1.0;1.1.0", "0.2.0;1.0.0"]
+        );
+    }
+
+    #[test]
+    fn track_missing_in_period() {
+        let video = r#"<AdaptationSet id="1" mimeType="video/mp4" codecs="avc1.64001f">
+<Representation id="720p" bandwidth="3000000" width="1280" height="720"/>
+</AdaptationSet>"#;
+        let audio = r#"<AdaptationSet id="2" mimeType="audio/mp4" codecs="ec-3" lang="en">
+<Representation id="surround" bandwidth="384000"/>
+</AdaptationSet>"#;
+
+        // Surround audio is not available in the second period, and a new track of a
+        // different codec is not matched with it.
+        assert_eq!(
+            locations(&format!(
+                r#"<MPD><Period>{}{}</Period><Period>{}<AdaptationSet mimeType="audio/mp4" codecs="mp4a.40.2" lang="en">
+<Representation id="stereo" bandwidth="128000"/>
+</AdaptationSet></Period><Period>{}{}</Period></MPD>"#,
+                video, audio, video, video, audio
+            )),
+            ["0.0.0;1.0.0;2.0.0", "0.1.0;2.1.0", "1.1.0"]
+        );
+    }
+
+    #[test]
+    fn event_streams() {
+        let mpd = dash_mpd::parse(
+            r#"<MPD><Period>
+<EventStream schemeIdUri="urn:scte:scte35:2014:xml+bin" timescale="90000" presentationTimeOffset="900000">
+<Event presentationTime="1350000" duration="2700000" id="1"><Signal><Binary>/DAgAAA=</Binary></Signal></Event>
+</EventStream>
+<EventStream schemeIdUri="urn:example:chapters" value="chapter">
+<Event presentationTime="20" id="2" messageData="Scene 2"/>
+</EventStream>
+</Period></MPD>"#,
+        )
+        .unwrap();
+        let events = period_events(&mpd.periods[0], 60.0);
+
+        assert_eq!(events.len(), 2);
+        assert!(events[0].is_ad());
+        assert_eq!(events[0].start_time, 65.0);
+        assert_eq!(events[0].duration, Some(30.0));
+        assert_eq!(events[0].id.as_deref(), Some("1"));
+        assert_eq!(events[1].start_time, 80.0);
+        assert_eq!(events[1].duration, None);
+        assert_eq!(events[1].message_data.as_deref(), Some("Scene 2"));
+        assert_eq!(events[1].title(), "chapter");
+    }
+}
@@ -2,66 +2,208 @@
     REFERENCES
     ----------
 
-    1. https://github.com/emarsden/dash-mpd-rs/blob/6ebdfb4759adbda8233b5b3520804e23ff86e7de/src/fetch.rs#L435-L466
+    1. ISO/IEC 23009-1 Section 5.3.9.4.4 (Template-based Segment URL construction)
+    2. https://github.com/shaka-project/shaka-player/blob/main/lib/dash/mpd_utils.js (fillUriTemplate)
+
+    Identifiers are written as `$<Identifier>$` or `$<Identifier>%0<Width><Format>$`,
+    where `<Format>` is one of `d`, `i`, `u`, `o`, `x` or `X` and `$$` is an escaped `$`.
+    `$SubNumber$` is the number of a segment within a segment sequence (`S@k`), starting at 1.
 
 */
 
-use regex::Regex;
 use std::collections::HashMap;
 
+const IDENTIFIERS: [&str; 5] = [
+    "Bandwidth",
+    "Number",
+    "RepresentationID",
+    "SubNumber",
+    "Time",
+];
+
 pub(super) struct Template {
-    re_representation_id: Regex,
-    re_number: Regex,
-    re_time: Regex,
-    re_bandwidth: Regex,
     vars: HashMap<String, String>,
 }
 
 impl Template {
     pub(super) fn new(vars: HashMap<String, String>) -> Self {
-        Self {
-            re_representation_id: Regex::new("\\$RepresentationID%0([\\d])d\\$").unwrap(),
-            re_number: Regex::new("\\$Number%0([\\d])d\\$").unwrap(),
-            re_time: Regex::new("\\$Time%0([\\d])d\\$").unwrap(),
-            re_bandwidth: Regex::new("\\$Bandwidth%0([\\d])d\\$").unwrap(),
-            vars,
-        }
+        Self { vars }
     }
 
     pub(super) fn insert(&mut self, var: &str, val: String) {
         self.vars.insert(var.to_owned(), val);
     }
 
+    /// Substitute identifiers of a template. Unknown identifiers, identifiers without
+    /// a value and malformed identifiers are kept as they are.
     pub(super) fn resolve(&self, template: &str) -> String {
-        let mut template = template.to_owned();
-
-        for (var, ident_re) in [
-            ("RepresentationID", &self.re_representation_id),
-            ("Number", &self.re_number),
-            ("Time", &self.re_time),
-            ("Bandwidth", &self.re_bandwidth),
-        ] {
-            let ident = format!("${}$", var);
-
-            if template.contains(&ident) {
-                if let Some(value) = self.vars.get(var) {
-                    template = template.replace(&ident, value);
-                }
-            }
+        let mut resolved = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('$') {
+            resolved.push_str(&rest[..start]);
+            rest = &rest[(start + 1)..];
+
+            let Some(end) = rest.find('$') else {
+                resolved.push('$');
+                break;
+            };
 
-            if let Some(cap) = ident_re.captures(&template) {
-                if let Some(value) = self.vars.get(var) {
-                    let count = format!(
-                        "{:0>width$}",
-                        value,
-                        width = cap[1].parse::<usize>().unwrap()
-                    );
-                    let m = ident_re.find(&template).unwrap();
-                    template = template[..m.start()].to_owned() + &count + &template[m.end()..];
-                }
+            let identifier = &rest[..end];
+            rest = &rest[(end + 1)..];
+
+            if identifier.is_empty() {
+                resolved.push('$');
+            } else if let Some(value) = self.substitute(identifier) {
+                resolved.push_str(&value);
+            } else {
+                resolved.push('$');
+                resolved.push_str(identifier);
+                resolved.push('$');
             }
         }
 
-        template
+        resolved.push_str(rest);
+        resolved
+    }
+
+    /// Value of a single identifier (text between two `$`).
+    fn substitute(&self, identifier: &str) -> Option<String> {
+        let (name, format_tag) = match identifier.split_once('%') {
+            Some((name, format_tag)) => (name, Some(format_tag)),
+            None => (identifier, None),
+        };
+
+        if !IDENTIFIERS.contains(&name) {
+            return None;
+        }
+
+        let value = self.vars.get(name)?;
+
+        let Some(format_tag) = format_tag else {
+            return Some(value.to_owned());
+        };
+
+        // Format tag is %0<Width><Format>, width is optional.
+        let format = format_tag.chars().last()?;
+        let width = &format_tag[..(format_tag.len() - format.len_utf8())];
+
+        if !width.is_empty() && !width.starts_with('0') {
+            return None;
+        }
+
+        let width = if width.is_empty() {
+            0
+        } else {
+            width.parse::<usize>().ok()?
+        };
+
+        // RepresentationID is not a number, format tag only pads it.
+        let value = match (format, value.parse::<u64>()) {
+            ('d' | 'i' | 'u', Ok(x)) => x.to_string(),
+            ('o', Ok(x)) => format!("{:o}", x),
+            ('x', Ok(x)) => format!("{:x}", x),
+            ('X', Ok(x)) => format!("{:X}", x),
+            ('d' | 'i' | 'u' | 'o' | 'x' | 'X', Err(_)) => value.to_owned(),
+            _ => return None,
+        };
+
+        Some(format!("{:0>width$}", value, width = width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+
+    fn resolve(template: &str, vars: &[(&str, &str)]) -> String {
+        Template::new(
+            vars.iter()
+                .map(|(x, y)| (x.to_string(), y.to_string()))
+                .collect(),
+        )
+        .resolve(template)
+    }
+
+    #[test]
+    fn representation_id() {
+        assert_eq!(
+            resolve("$RepresentationID$.mp4", &[("RepresentationID", "v1")]),
+            "v1.mp4"
+        );
+    }
+
+    #[test]
+    fn padded_number() {
+        assert_eq!(
+            resolve("$Number%05d$.m4s", &[("Number", "42")]),
+            "00042.m4s"
+        );
+    }
+
+    #[test]
+    fn multi_digit_width() {
+        assert_eq!(
+            resolve("$Number%010d$.m4s", &[("Number", "42")]),
+            "0000000042.m4s"
+        );
+    }
+
+    #[test]
+    fn hexadecimal() {
+        assert_eq!(
+            resolve("$Time%x$-$Time%08X$", &[("Time", "255")]),
+            "ff-000000FF"
+        );
+    }
+
+    #[test]
+    fn octal() {
+        assert_eq!(resolve("$Number%o$", &[("Number", "8")]), "10");
+    }
+
+    #[test]
+    fn sub_number() {
+        assert_eq!(
+            resolve(
+                "$Number$_$SubNumber%02d$",
+                &[("Number", "3"), ("SubNumber", "1")]
+            ),
+            "3_01"
+        );
+    }
+
+    #[test]
+    fn repeated_identifier() {
+        assert_eq!(
+            resolve("$Number%03d$/$Number%03d$", &[("Number", "7")]),
+            "007/007"
+        );
+    }
+
+    #[test]
+    fn escaped_dollar() {
+        assert_eq!(
+            resolve("cost$$$Number$.m4s", &[("Number", "1")]),
+            "cost$1.m4s"
+        );
+    }
+
+    #[test]
+    fn missing_value() {
+        assert_eq!(resolve("$Time$.m4s", &[]), "$Time$.m4s");
+    }
+
+    #[test]
+    fn unknown_identifier() {
+        assert_eq!(
+            resolve("$Unknown$.m4s", &[("Unknown", "1")]),
+            "$Unknown$.m4s"
+        );
+    }
+
+    #[test]
+    fn unterminated_identifier() {
+        assert_eq!(resolve("$Number.m4s", &[("Number", "1")]), "$Number.m4s");
     }
 }
@@ -0,0 +1,236 @@
+/*
+    REFERENCES
+    ----------
+
+    1. ISO/IEC 23009-1 Section 5.3.9.6 (Segment timeline)
+    2. https://github.com/shaka-project/shaka-player/blob/main/lib/dash/mpd_utils.js (createTimeline)
+
+    Times are in timescale units on the media timeline. A period covers `presentationTimeOffset`
+    (PTO) to PTO + period duration of it, segments outside of it are not a part of the period.
+    `$Number$` still counts segments which are left out.
+
+*/
+
+use dash_mpd::SegmentTimeline;
+
+pub(super) struct TimelineSegment {
+    /// Start of segment on the media timeline, used as `$Time$`.
+    pub(super) time: u64,
+    pub(super) duration: u64,
+    pub(super) number: u64,
+    /// Number of segment within its segment sequence (`S@k`), starting at 1, used as `$SubNumber$`.
+    pub(super) sub_number: u64,
+}
+
+/// Expand `S` elements of a segment timeline into the segments which overlap the period.
+/// `period_end` is the end of period on the media timeline (PTO + period duration), if it is known.
+pub(super) fn expand(
+    segment_timeline: &SegmentTimeline,
+    start_number: u64,
+    presentation_time_offset: u64,
+    period_end: Option<u64>,
+) -> Vec<TimelineSegment> {
+    let mut segments = vec![];
+    let mut time = 0;
+    let mut number = start_number;
+
+    for (i, s) in segment_timeline.segments.iter().enumerate() {
+        if let Some(t) = s.t {
+            time = t;
+        }
+
+        if let Some(n) = s.n {
+            number = n;
+        }
+
+        if s.d == 0 {
+            continue;
+        }
+
+        let repeat = match s.r.unwrap_or(0) {
+            r if r >= 0 => r as u64,
+            // A negative value of the @r attribute indicates that the duration indicated in @d
+            // attribute repeats until the start of the next S element, the end of the Period or
+            // until the next MPD update.
+            _ => segment_timeline
+                .segments
+                .get(i + 1)
+                .and_then(|x| x.t)
+                .or(period_end)
+                .map(|end| end.saturating_sub(time).div_ceil(s.d).saturating_sub(1))
+                .unwrap_or(0),
+        };
+
+        for _ in 0..=repeat {
+            if period_end.is_some_and(|end| time >= end) {
+                return segments;
+            }
+
+            if time + s.d > presentation_time_offset {
+                // Segments of a segment sequence share $Time$ and $Number$,
+                // their exact durations are unknown so @d is divided equally.
+                let k = s.k.unwrap_or(1).max(1);
+
+                for sub_number in 1..=k {
+                    segments.push(TimelineSegment {
+                        time,
+                        duration: s.d / k,
+                        number,
+                        sub_number,
+                    });
+                }
+            }
+
+            time += s.d;
+            number += 1;
+        }
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand;
+    use dash_mpd::{SegmentTimeline, S};
+
+    fn s(t: Option<u64>, d: u64, r: Option<i64>) -> S {
+        S {
+            t,
+            d,
+            r,
+            ..Default::default()
+        }
+    }
+
+    /// Segments as (`$Time$`, @d, `$Number$`).
+    fn segments(
+        timeline: Vec<S>,
+        start_number: u64,
+        presentation_time_offset: u64,
+        period_end: Option<u64>,
+    ) -> Vec<(u64, u64, u64)> {
+        expand(
+            &SegmentTimeline { segments: timeline },
+            start_number,
+            presentation_time_offset,
+            period_end,
+        )
+        .into_iter()
+        .map(|x| (x.time, x.duration, x.number))
+        .collect()
+    }
+
+    #[test]
+    fn repeat() {
+        assert_eq!(
+            segments(vec![s(Some(0), 2, Some(2))], 1, 0, Some(6)),
+            [(0, 2, 1), (2, 2, 2), (4, 2, 3)]
+        );
+    }
+
+    #[test]
+    fn implicit_start() {
+        assert_eq!(
+            segments(vec![s(None, 2, None), s(None, 3, None)], 1, 0, Some(5)),
+            [(0, 2, 1), (2, 3, 2)]
+        );
+    }
+
+    #[test]
+    fn gap() {
+        assert_eq!(
+            segments(
+                vec![s(Some(0), 2, None), s(Some(5), 2, None)],
+                1,
+                0,
+                Some(7)
+            ),
+            [(0, 2, 1), (5, 2, 2)]
+        );
+    }
+
+    #[test]
+    fn negative_repeat_until_next_s() {
+        assert_eq!(
+            segments(
+                vec![s(Some(10), 2, Some(-1)), s(Some(16), 1, None)],
+                1,
+                10,
+                Some(17)
+            ),
+            [(10, 2, 1), (12, 2, 2), (14, 2, 3), (16, 1, 4)]
+        );
+    }
+
+    #[test]
+    fn negative_repeat_until_period_end() {
+        assert_eq!(
+            segments(vec![s(Some(10), 2, Some(-1))], 1, 10, Some(15)),
+            [(10, 2, 1), (12, 2, 2), (14, 2, 3)]
+        );
+    }
+
+    #[test]
+    fn negative_repeat_without_period_end() {
+        assert_eq!(
+            segments(vec![s(Some(10), 2, Some(-1))], 1, 10, None),
+            [(10, 2, 1)]
+        );
+    }
+
+    #[test]
+    fn presentation_time_offset() {
+        assert_eq!(
+            segments(vec![s(Some(0), 2, Some(4))], 1, 4, Some(8)),
+            [(4, 2, 3), (6, 2, 4)]
+        );
+    }
+
+    #[test]
+    fn period_end() {
+        assert_eq!(
+            segments(vec![s(Some(0), 2, Some(9))], 1, 0, Some(4)),
+            [(0, 2, 1), (2, 2, 2)]
+        );
+    }
+
+    #[test]
+    fn start_number() {
+        assert_eq!(
+            segments(vec![s(Some(0), 2, Some(1))], 5, 0, Some(4)),
+            [(0, 2, 5), (2, 2, 6)]
+        );
+    }
+
+    #[test]
+    fn explicit_number() {
+        let timeline = vec![S {
+            n: Some(7),
+            ..s(Some(100), 2, Some(1))
+        }];
+
+        assert_eq!(
+            segments(timeline, 1, 100, Some(104)),
+            [(100, 2, 7), (102, 2, 8)]
+        );
+    }
+
+    #[test]
+    fn segment_sequence() {
+        let timeline = SegmentTimeline {
+            segments: vec![S {
+                k: Some(2),
+                ..s(Some(0), 4, Some(1))
+            }],
+        };
+
+        assert_eq!(
+            expand(&timeline, 1, 0, Some(8))
+                .into_iter()
+                .map(|x| (x.time, x.duration, x.number, x.sub_number))
+                .collect::<Vec<_>>(),
+            [(0, 2, 1, 1), (0, 2, 1, 2), (4, 2, 2, 1), (4, 2, 2, 2)]
+        );
+    }
+}
@@ -0,0 +1,303 @@
+/*
+    REFERENCES
+    ----------
+
+    1. ISO/IEC 23009-1 Section 5.5 (Remote elements)
+    2. https://dashif.org/docs/DASH-IF-IOP-v4.3.pdf (4.5.3 Remote elements)
+
+*/
+
+use anyhow::{anyhow, bail, Result};
+use dash_mpd::{AdaptationSet, BaseURL, Period, SegmentList, MPD};
+use kdam::term::Colorizer;
+use reqwest::{blocking::Client, Url};
+
+const RESOLVE_TO_ZERO: &str = "urn:mpeg:dash:resolve-to-zero:2013";
+/// Remote elements can reference more remote elements, they are resolved up to this depth.
+const MAX_DEPTH: u8 = 5;
+
+/// Fetch remote `Period` and `SegmentList` elements (`xlink:href`) and splice them into playlist.
+/// Elements with `xlink:actuate="onLoad"` are always resolved, whereas `onRequest` (default)
+/// elements are only resolved when they don't have any content of their own.
+/// Elements which couldn't be fetched or parsed are removed, as if they resolved to zero.
+pub(crate) fn resolve_xlinks(mpd: &mut MPD, url: &Url, client: &Client) -> Result<()> {
+    mpd.periods = resolve_periods(std::mem::take(&mut mpd.periods), url, client, 0)?;
+    Ok(())
+}
+
+fn resolve_periods(
+    periods: Vec<Period>,
+    url: &Url,
+    client: &Client,
+    depth: u8,
+) -> Result<Vec<Period>> {
+    let mut resolved_periods = vec![];
+
+    for mut period in periods {
+        let Some(href) = remote_href(
+            &period.href,
+            &period.actuate,
+            !period.adaptations.is_empty(),
+        ) else {
+            resolve_adaptation_sets(&mut period.adaptations, url, client, depth)?;
+            resolved_periods.push(period);
+            continue;
+        };
+
+        if href == RESOLVE_TO_ZERO {
+            continue;
+        }
+
+        // A remote period can resolve to zero, one or more periods.
+        let remote = fetch(href, url, client, depth).and_then(|(remote_url, text)| {
+            let remote = parse_fragment(&text, &remote_url, ("", ""))?;
+            Ok((remote_url, remote))
+        });
+
+        let (remote_url, mut remote) = match remote {
+            Ok(x) => x,
+            Err(e) => {
+                warn_dropped("Period", &e);
+                continue;
+            }
+        };
+
+        for period in &mut remote.periods {
+            rebase_period(period, &remote_url);
+        }
+
+        resolved_periods.extend(resolve_periods(
+            remote.periods,
+            &remote_url,
+            client,
+            depth + 1,
+        )?);
+    }
+
+    Ok(resolved_periods)
+}
+
+fn resolve_adaptation_sets(
+    adaptation_sets: &mut [AdaptationSet],
+    url: &Url,
+    client: &Client,
+    depth: u8,
+) -> Result<()> {
+    for adaptation_set in adaptation_sets {
+        resolve_segment_list(&mut adaptation_set.SegmentList, url, client, depth)?;
+
+        for representation in &mut adaptation_set.representations {
+            resolve_segment_list(&mut representation.SegmentList, url, client, depth)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_segment_list(
+    segment_list: &mut Option<SegmentList>,
+    url: &Url,
+    client: &Client,
+    depth: u8,
+) -> Result<()> {
+    let Some(href) = segment_list.as_ref().and_then(|x| {
+        remote_href(&x.href, &x.actuate, !x.segment_urls.is_empty()).map(|x| x.to_owned())
+    }) else {
+        return Ok(());
+    };
+
+    if href == RESOLVE_TO_ZERO {
+        *segment_list = None;
+        return Ok(());
+    }
+
+    let remote = fetch(&href, url, client, depth).and_then(|(remote_url, text)| {
+        let remote = parse_fragment(
+            &text,
+            &remote_url,
+            ("<Period><AdaptationSet>", "</AdaptationSet></Period>"),
+        )?;
+        Ok((remote_url, remote))
+    });
+
+    let (remote_url, remote) = match remote {
+        Ok(x) => x,
+        Err(e) => {
+            warn_dropped("SegmentList", &e);
+            *segment_list = None;
+            return Ok(());
+        }
+    };
+
+    *segment_list = remote
+        .periods
+        .into_iter()
+        .next()
+        .and_then(|x| x.adaptations.into_iter().next())
+        .and_then(|x| x.SegmentList);
+
+    resolve_segment_list(segment_list, &remote_url, client, depth + 1)
+}
+
+fn warn_dropped(element: &str, error: &anyhow::Error) {
+    eprintln!(
+        "    {} {}, removing remote {} element",
+        "Warning".colorize("bold yellow"),
+        error.to_string().trim_end_matches('.'),
+        element
+    );
+}
+
+/// Relative urls of a remote period are resolved against the remote element's own url
+/// instead of the playlist url. Only the outermost `BaseURL` elements need to be rebased,
+/// nested ones are resolved against them.
+fn rebase_period(period: &mut Period, remote_url: &Url) {
+    if rebase(&mut period.BaseURL, remote_url) {
+        return;
+    }
+
+    for adaptation_set in &mut period.adaptations {
+        if !rebase(&mut adaptation_set.BaseURL, remote_url) {
+            for representation in &mut adaptation_set.representations {
+                rebase(&mut representation.BaseURL, remote_url);
+            }
+        }
+    }
+}
+
+fn rebase(base_urls: &mut [BaseURL], remote_url: &Url) -> bool {
+    for base_url in base_urls.iter_mut() {
+        if let Ok(url) = remote_url.join(base_url.base.trim()) {
+            base_url.base = url.to_string();
+        }
+    }
+
+    !base_urls.is_empty()
+}
+
+/// `xlink:href` of an element, if it should be resolved now.
+fn remote_href<'a>(
+    href: &'a Option<String>,
+    actuate: &Option<String>,
+    has_content: bool,
+) -> Option<&'a str> {
+    let href = href.as_deref()?.trim();
+
+    if href.is_empty() {
+        return None;
+    }
+
+    (href == RESOLVE_TO_ZERO || actuate.as_deref() == Some("onLoad") || !has_content)
+        .then_some(href)
+}
+
+fn fetch(href: &str, url: &Url, client: &Client, depth: u8) -> Result<(Url, String)> {
+    let remote_url = url.join(href)?;
+
+    if depth >= MAX_DEPTH {
+        bail!(
+            "couldn't resolve remote element {} (more than {} nested xlinks).",
+            remote_url,
+            MAX_DEPTH
+        );
+    }
+
+    let text = client
+        .get(remote_url.clone())
+        .send()
+        .and_then(|x| x.error_for_status())
+        .and_then(|x| x.text())
+        .map_err(|x| anyhow!("couldn't fetch remote element {} ({}).", remote_url, x))?;
+
+    Ok((remote_url, text))
+}
+
+/// Remote elements are xml fragments (possibly without a single root element),
+/// hence they are wrapped in an `MPD` (and their expected parents) before parsing.
+fn parse_fragment(text: &str, url: &Url, (open, close): (&str, &str)) -> Result<MPD> {
+    let mut text = text.trim_start_matches('\u{feff}').trim();
+
+    if text.starts_with("<?xml") {
+        text = text.split_once("?>").map(|x| x.1).unwrap_or(text);
+    }
+
+    dash_mpd::parse(&format!("<MPD>{}{}{}</MPD>", open, text, close)).map_err(|x| {
+        anyhow!(
+            "couldn't parse remote element {} (failed with {}).\n\n{}",
+            url,
+            x,
+            text
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_fragment, rebase_period, resolve_xlinks};
+    use reqwest::{blocking::Client, Url};
+    use std::net::TcpListener;
+
+    #[test]
+    fn rebase_remote_periods() {
+        let remote_url = "https://ads.example.com/breaks/1/period.xml"
+            .parse::<Url>()
+            .unwrap();
+        let mut remote = parse_fragment(
+            r#"<Period id="ad-1"><BaseURL>media/</BaseURL></Period>
+<Period id="ad-2">
+  <AdaptationSet><BaseURL>https://cdn.example.com/ad/</BaseURL></AdaptationSet>
+  <AdaptationSet><Representation id="1" bandwidth="1"><BaseURL>audio/</BaseURL></Representation></AdaptationSet>
+</Period>"#,
+            &remote_url,
+            ("", ""),
+        )
+        .unwrap();
+
+        for period in &mut remote.periods {
+            rebase_period(period, &remote_url);
+        }
+
+        assert_eq!(
+            remote.periods[0].BaseURL[0].base,
+            "https://ads.example.com/breaks/1/media/"
+        );
+        assert_eq!(
+            remote.periods[1].adaptations[0].BaseURL[0].base,
+            "https://cdn.example.com/ad/"
+        );
+        assert_eq!(
+            remote.periods[1].adaptations[1].representations[0].BaseURL[0].base,
+            "https://ads.example.com/breaks/1/audio/"
+        );
+    }
+
+    #[test]
+    fn drop_unavailable_elements() {
+        // Nothing listens on a port once its listener is dropped.
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let url = format!("http://{}/manifest.mpd", address)
+            .parse::<Url>()
+            .unwrap();
+        let mut mpd = dash_mpd::parse(
+            r#"<MPD xmlns:xlink="http://www.w3.org/1999/xlink">
+  <Period id="main"><AdaptationSet><SegmentList xlink:href="list.xml"/></AdaptationSet></Period>
+  <Period id="ad" xlink:href="ad.xml" xlink:actuate="onLoad"/>
+</MPD>"#,
+        )
+        .unwrap();
+
+        resolve_xlinks(
+            &mut mpd,
+            &url,
+            &Client::builder().no_proxy().build().unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(mpd.periods.len(), 1);
+        assert_eq!(mpd.periods[0].id.as_deref(), Some("main"));
+        assert!(mpd.periods[0].adaptations[0].SegmentList.is_none());
+    }
+}
@@ -0,0 +1,152 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/rfc2397
+    2. https://fetch.spec.whatwg.org/#data-urls
+
+*/
+
+use crate::{playlist::Range, utils};
+use anyhow::{bail, Result};
+use reqwest::{blocking::Client, header, Url};
+
+/// Decode payload of a `data:` url, `None` is returned for any other url.
+/// Byte range (if any) is applied to the decoded payload.
+pub(super) fn decode(url: &Url, range: Option<&Range>) -> Result<Option<Vec<u8>>> {
+    if url.scheme() != "data" {
+        return Ok(None);
+    }
+
+    // Everything after the scheme and before the fragment.
+    let text = &url.as_str()["data:".len()..];
+    let text = text.split_once('#').map(|x| x.0).unwrap_or(text);
+
+    let Some((media_type, payload)) = text.split_once(',') else {
+        bail!("malformed data url {} (missing comma).", text);
+    };
+
+    let payload = percent_decode(payload);
+    let is_base64 = media_type
+        .rsplit_once(';')
+        .is_some_and(|x| x.1.trim().eq_ignore_ascii_case("base64"));
+
+    let mut data = if is_base64 {
+        // Forgiving base64 decode, whitespace and missing padding are allowed.
+        let mut payload = payload
+            .into_iter()
+            .filter(|x| !x.is_ascii_whitespace())
+            .collect::<Vec<_>>();
+
+        while payload.len() % 4 != 0 {
+            payload.push(b'=');
+        }
+
+        utils::decode_base64(payload)?
+    } else {
+        payload
+    };
+
+    if let Some(range) = range {
+        let start = (range.start as usize).min(data.len());
+        let end = range.end.saturating_add(1).min(data.len() as u64) as usize;
+        data = data[start..end.max(start)].to_vec();
+    }
+
+    Ok(Some(data))
+}
+
+/// Fetch a resource, `data:` urls are decoded instead of being requested.
+pub(super) fn fetch(client: &Client, url: Url, range: Option<&Range>) -> Result<Vec<u8>> {
+    if let Some(data) = decode(&url, range)? {
+        return Ok(data);
+    }
+
+    let mut request = client.get(url);
+
+    if let Some(range) = range {
+        request = request.header(header::RANGE, range.as_header_value());
+    }
+
+    Ok(request.send()?.error_for_status()?.bytes()?.to_vec())
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = bytes
+                .get((i + 1)..(i + 3))
+                .filter(|x| x.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|x| std::str::from_utf8(x).ok())
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+            {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+    use crate::playlist::Range;
+
+    fn data(url: &str, range: Option<&Range>) -> Option<Vec<u8>> {
+        decode(&url.parse().unwrap(), range).unwrap()
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(
+            data("data:application/octet-stream;base64,SGVsbG8=", None).unwrap(),
+            b"Hello"
+        );
+        // Whitespace (percent encoded) and missing padding are allowed.
+        assert_eq!(data("data:;BASE64,SGVs%20bG8", None).unwrap(), b"Hello");
+        assert_eq!(
+            data("data:;base64,SGVsbG8=", Some(&Range { start: 1, end: 3 })).unwrap(),
+            b"ell"
+        );
+    }
+
+    #[test]
+    fn percent_encoded() {
+        assert_eq!(
+            data("data:text/plain;charset=utf-8,%23EXTM3U%0A%zz", None).unwrap(),
+            b"#EXTM3U\n%zz"
+        );
+        // Without `;base64` payload is taken as is.
+        assert_eq!(data("data:text/plain,SGVsbG8=", None).unwrap(), b"SGVsbG8=");
+        assert_eq!(data("data:text/plain;base64x,abc", None).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn empty_payload() {
+        assert!(data("data:,", None).unwrap().is_empty());
+        assert!(data("data:;base64,", None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(decode(&"data:text/plain;base64".parse().unwrap(), None).is_err());
+        assert!(decode(&"data:;base64,SGVsbG8@".parse().unwrap(), None).is_err());
+        assert!(decode(&"data:;base64,SGVsb".parse().unwrap(), None).is_err());
+    }
+
+    #[test]
+    fn other_urls() {
+        assert!(data("https://example.com/data:,abc", None).is_none());
+        assert!(data("file:///tmp/data", None).is_none());
+    }
+}
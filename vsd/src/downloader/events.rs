@@ -0,0 +1,286 @@
+/*
+    REFERENCES
+    ----------
+
+    1. ISO/IEC 23009-1 Section 5.10 (Media presentation timed events)
+    2. https://ffmpeg.org/ffmpeg-formats.html#Metadata-2
+
+*/
+
+use crate::{playlist::Event, utils};
+use anyhow::Result;
+use std::{
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use vsd_mp4::emsg::Emsg;
+
+/// Parser of in-band events (`emsg` boxes) of a single segment.
+pub(super) struct InbandEventParser {
+    pub(super) events: Arc<Mutex<Vec<Event>>>,
+    /// Offset from media timeline to stream timeline, used by version 1 boxes.
+    pub(super) offset: f64,
+    pub(super) schemes: Arc<Vec<String>>,
+    /// Start of segment on stream timeline, used by version 0 boxes.
+    pub(super) segment_start: f64,
+}
+
+impl InbandEventParser {
+    pub(super) fn parse(&self, segment: &[u8]) {
+        // Segments which cannot be parsed simply don't have any events.
+        let Ok(emsg) = Emsg::new(segment) else {
+            return;
+        };
+
+        let mut events = self.events.lock().unwrap();
+
+        for message in emsg.messages {
+            if !self.schemes.contains(&message.scheme_id_uri) {
+                continue;
+            }
+
+            let timescale = message.timescale as f64;
+            let start_time = if let Some(presentation_time) = message.presentation_time {
+                self.offset + presentation_time as f64 / timescale
+            } else {
+                self.segment_start + message.presentation_time_delta.unwrap_or(0) as f64 / timescale
+            };
+
+            let event = Event {
+                duration: (message.event_duration != u32::MAX)
+                    .then(|| message.event_duration as f64 / timescale),
+                id: Some(message.id.to_string()),
+                message_data: (!message.message_data.is_empty())
+                    .then(|| utils::encode_base64(&message.message_data)),
+                scheme_id_uri: message.scheme_id_uri,
+                start_time,
+                value: Some(message.value).filter(|x| !x.is_empty()),
+            };
+
+            // Events are repeated in every segment until they occur.
+            if !events.iter().any(|x| x.is_same(&event)) {
+                events.push(event);
+            }
+        }
+    }
+}
+
+/// Add events which are not already present, and keep them sorted by their start time.
+pub(super) fn merge_events(events: &mut Vec<Event>, other: impl IntoIterator<Item = Event>) {
+    for event in other {
+        if !events.iter().any(|x| x.is_same(&event)) {
+            events.push(event);
+        }
+    }
+
+    events.sort_by(|x, y| x.start_time.total_cmp(&y.start_time));
+}
+
+/// Write events as chapters in ffmpeg metadata format.
+pub(super) fn write_chapters(events: &[Event], duration: f64, path: &Path) -> Result<()> {
+    File::create(path)?.write_all(chapters_metadata(events, duration).as_bytes())?;
+    Ok(())
+}
+
+/// Chapters of events in ffmpeg metadata format.
+/// Time which isn't covered by any event is kept in untitled chapters.
+fn chapters_metadata(events: &[Event], duration: f64) -> String {
+    let mut chapters = vec![];
+    let mut time = 0.0;
+
+    for (i, event) in events.iter().enumerate() {
+        if event.start_time < time || event.start_time >= duration {
+            continue;
+        }
+
+        if event.start_time > time {
+            chapters.push((time, event.start_time, None));
+        }
+
+        let end = event
+            .duration
+            .map(|x| event.start_time + x)
+            .or(events.get(i + 1).map(|x| x.start_time))
+            .unwrap_or(duration)
+            .min(duration);
+
+        if end > event.start_time {
+            chapters.push((event.start_time, end, Some(event.title())));
+            time = end;
+        }
+    }
+
+    if time < duration {
+        chapters.push((time, duration, None));
+    }
+
+    let mut metadata = ";FFMETADATA1\n".to_owned();
+
+    for (i, (start, end, title)) in chapters.into_iter().enumerate() {
+        metadata += &format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (start * 1000.0).round() as u64,
+            (end * 1000.0).round() as u64,
+            escape_metadata(&title.unwrap_or(format!("Chapter {}", i + 1)))
+        );
+    }
+
+    metadata
+}
+
+/// Write events as json.
+pub(super) fn write_events(events: &[Event], path: &Path) -> Result<()> {
+    serde_json::to_writer_pretty(File::create(path)?, events)?;
+    Ok(())
+}
+
+fn escape_metadata(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chapters_metadata, InbandEventParser};
+    use crate::playlist::Event;
+    use std::sync::{Arc, Mutex};
+
+    fn full_box(name: &[u8; 4], version: u8, payload: &[u8]) -> Vec<u8> {
+        [
+            &(12 + payload.len() as u32).to_be_bytes()[..],
+            name,
+            &[version, 0, 0, 0],
+            payload,
+        ]
+        .concat()
+    }
+
+    fn event(start_time: f64, duration: Option<f64>, value: &str) -> Event {
+        Event {
+            duration,
+            id: None,
+            message_data: None,
+            scheme_id_uri: "urn:example".to_owned(),
+            start_time,
+            value: Some(value.to_owned()),
+        }
+    }
+
+    #[test]
+    fn inband_events() {
+        let mut v0 = b"urn:scte:scte35:2013:bin\0\0".to_vec();
+        v0.extend(90000_u32.to_be_bytes());
+        v0.extend(45000_u32.to_be_bytes()); // 0.5s after segment start
+        v0.extend(2700000_u32.to_be_bytes()); // 30s
+        v0.extend(1_u32.to_be_bytes());
+        v0.extend([0xfc]);
+
+        let mut v1 = 1000_u32.to_be_bytes().to_vec();
+        v1.extend(100_000_u64.to_be_bytes()); // 100s on media timeline
+        v1.extend(u32::MAX.to_be_bytes());
+        v1.extend(2_u32.to_be_bytes());
+        v1.extend(b"urn:example\0chapter\0");
+
+        let mut other = 1000_u32.to_be_bytes().to_vec();
+        other.extend([0; 20]);
+        other.extend(b"urn:ignored\0\0");
+
+        let segment = [
+            full_box(b"emsg", 0, &v0),
+            full_box(b"emsg", 1, &v1),
+            full_box(b"emsg", 1, &other),
+        ]
+        .concat();
+        let parser = InbandEventParser {
+            events: Arc::new(Mutex::new(vec![])),
+            offset: -90.0,
+            schemes: Arc::new(vec![
+                "urn:scte:scte35:2013:bin".to_owned(),
+                "urn:example".to_owned(),
+            ]),
+            segment_start: 8.0,
+        };
+        parser.parse(&segment);
+        // Events repeated in later segments are ignored.
+        parser.parse(&segment);
+
+        let events = parser.events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+
+        assert_eq!(events[0].start_time, 8.5);
+        assert_eq!(events[0].duration, Some(30.0));
+        assert_eq!(events[0].id.as_deref(), Some("1"));
+        assert_eq!(events[0].message_data.as_deref(), Some("/A=="));
+        assert_eq!(events[0].value, None);
+
+        assert_eq!(events[1].start_time, 10.0);
+        assert_eq!(events[1].duration, None);
+        assert_eq!(events[1].value.as_deref(), Some("chapter"));
+    }
+
+    #[test]
+    fn chapters() {
+        let mut ad = event(20.0, Some(10.0), "");
+        ad.scheme_id_uri = "urn:scte:scte35:2014:xml+bin".to_owned();
+
+        let events = [
+            event(5.0, None, "Intro"),
+            event(12.0, Some(3.0), "Title=1; #2"),
+            ad,
+            event(70.0, None, "After end"),
+        ];
+
+        assert_eq!(
+            chapters_metadata(&events, 60.0),
+            ";FFMETADATA1
+
+[CHAPTER]
+TIMEBASE=1/1000
+START=0
+END=5000
+title=Chapter 1
+
+[CHAPTER]
+TIMEBASE=1/1000
+START=5000
+END=12000
+title=Intro
+
+[CHAPTER]
+TIMEBASE=1/1000
+START=12000
+END=15000
+title=Title\\=1\\; \\#2
+
+[CHAPTER]
+TIMEBASE=1/1000
+START=15000
+END=20000
+title=Chapter 4
+
+[CHAPTER]
+TIMEBASE=1/1000
+START=20000
+END=30000
+title=Ad break
+
+[CHAPTER]
+TIMEBASE=1/1000
+START=30000
+END=60000
+title=Chapter 6
+"
+        );
+    }
+}
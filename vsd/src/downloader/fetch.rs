@@ -24,6 +24,7 @@ impl InputMetadata {
                 b"application/x-mpegurl" | b"application/vnd.apple.mpegurl" => {
                     self.pl_type = Some(PlaylistType::Hls)
                 }
+                b"application/vnd.ms-sstr+xml" => self.pl_type = Some(PlaylistType::Smooth),
                 _ => (),
             }
         }
@@ -39,6 +40,8 @@ impl InputMetadata {
                 self.pl_type = Some(PlaylistType::Dash);
             } else if self.text.contains("#EXTM3U") {
                 self.pl_type = Some(PlaylistType::Hls);
+            } else if self.text.contains("<SmoothStreamingMedia") {
+                self.pl_type = Some(PlaylistType::Smooth);
             }
         }
     }
@@ -160,8 +163,10 @@ fn fetch_from_website(client: &Client, meta: &mut InputMetadata, prompts: &Promp
 }
 
 fn scrape_playlist_links(text: &str) -> Vec<String> {
-    let re =
-        Regex::new(r#"([\"\'])(https?:\/\/[^\"\']*\.(m3u8|m3u|mpd)[^\"\']*)([\"\'])"#).unwrap();
+    let re = Regex::new(
+        r#"([\"\'])(https?:\/\/[^\"\']*\.(m3u8|m3u|mpd|isml?\/[Mm]anifest)[^\"\']*)([\"\'])"#,
+    )
+    .unwrap();
     let links = re
         .captures_iter(text)
         .map(|caps| caps.get(2).unwrap().as_str().to_string())
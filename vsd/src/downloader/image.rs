@@ -0,0 +1,297 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://dashif.org/docs/DASH-IF-IOP-v4.3.pdf (6.2.6 Tiles of thumbnail images)
+    2. https://www.w3.org/TR/media-frags/#naming-space
+
+*/
+
+use super::data_url;
+use crate::{playlist::MediaPlaylist, utils};
+use anyhow::{bail, Result};
+use kdam::{term::Colorizer, BarExt, Column, RichProgress};
+use reqwest::{blocking::Client, Url};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+pub fn download_image_stream(
+    base_url: Option<Url>,
+    client: &Client,
+    directory: &Option<PathBuf>,
+    stream: &MediaPlaylist,
+    pb: &mut RichProgress,
+) -> Result<()> {
+    pb.write(format!(
+        " {} {} stream {}",
+        "Processing".colorize("bold green"),
+        stream.media_type,
+        stream.display_stream().colorize("cyan"),
+    ))?;
+
+    let length = stream.segments.len();
+
+    if length == 0 {
+        pb.write(format!(
+            "    {} skipping stream (no segments)",
+            "Warning".colorize("bold yellow"),
+        ))?;
+        return Ok(());
+    }
+
+    pb.pb.total = length;
+
+    let ext = stream.extension();
+    let tiles_directory = stream.file_path(directory, &ext).with_extension("");
+    std::fs::create_dir_all(&tiles_directory)?;
+
+    pb.write(format!(
+        "{} stream to {}",
+        "Downloading".colorize("bold green"),
+        tiles_directory.to_string_lossy().colorize("cyan")
+    ))?;
+
+    let stream_base_url = base_url
+        .clone()
+        .unwrap_or(stream.uri.parse::<Url>().unwrap());
+    let (columns, rows) = stream.tiles.unwrap_or((1, 1));
+    let (vtt, thumbnail_times) = sprite_index(stream, &ext);
+    let ffmpeg = (columns * rows > 1 && utils::find_ffmpeg().is_some())
+        .then(|| tiles_directory.join("thumbnails"));
+    let mut downloaded_bytes = 0;
+
+    if let Some(thumbnails_directory) = &ffmpeg {
+        std::fs::create_dir_all(thumbnails_directory)?;
+    }
+
+    for (i, (segment, thumbnail_times)) in stream.segments.iter().zip(&thumbnail_times).enumerate()
+    {
+        let url = stream_base_url.join(&segment.uri)?;
+        let bytes = data_url::fetch(client, url, segment.range.as_ref())?;
+        let tile_path = tiles_directory.join(tile_name(i, &ext));
+        File::create(&tile_path)?.write_all(&bytes)?;
+        downloaded_bytes += bytes.len();
+
+        if let Some(thumbnails_directory) = &ffmpeg {
+            slice_tile(
+                &tile_path,
+                thumbnails_directory,
+                (columns, rows),
+                thumbnail_times,
+                &ext,
+            )?;
+        }
+
+        pb.replace(
+            0,
+            Column::Text(format!(
+                "[bold blue]{}",
+                utils::format_bytes(downloaded_bytes, 2).2
+            )),
+        );
+        pb.update(1)?;
+    }
+
+    File::create(tiles_directory.join("thumbnails.vtt"))?.write_all(vtt.as_bytes())?;
+
+    pb.write(format!(
+        " {} stream successfully",
+        "Downloaded".colorize("bold green"),
+    ))?;
+    eprintln!();
+    pb.reset(Some(0));
+    Ok(())
+}
+
+pub fn download_image_streams(
+    base_url: Option<Url>,
+    client: &Client,
+    directory: &Option<PathBuf>,
+    image_streams: &Vec<MediaPlaylist>,
+    pb: &mut RichProgress,
+) -> Result<()> {
+    for stream in image_streams {
+        download_image_stream(base_url.clone(), client, directory, stream, pb)?;
+    }
+
+    Ok(())
+}
+
+fn tile_name(index: usize, ext: &str) -> String {
+    format!("tile_{:05}.{}", index + 1, ext)
+}
+
+/// WebVTT index of thumbnails in tiles (`tile.jpg#xywh=x,y,w,h`) and start times of
+/// thumbnails of each tile.
+fn sprite_index(stream: &MediaPlaylist, ext: &str) -> (String, Vec<Vec<f64>>) {
+    let (columns, rows) = stream.tiles.unwrap_or((1, 1));
+    let thumbnails = columns * rows;
+    // Each tile covers the nominal segment duration, which is evenly split between its thumbnails.
+    let thumbnail_duration = stream
+        .segments
+        .iter()
+        .map(|x| x.duration as f64)
+        .fold(0.0, f64::max)
+        / thumbnails as f64;
+    let mut start_time = 0.0;
+    let mut vtt = "WEBVTT\n".to_owned();
+    let mut all_thumbnail_times = vec![];
+
+    for (i, segment) in stream.segments.iter().enumerate() {
+        let mut thumbnail_times = vec![];
+
+        for j in 0..thumbnails {
+            let start = start_time + (j as f64 * thumbnail_duration);
+
+            // Last tile can be partially filled.
+            if start >= start_time + segment.duration as f64 {
+                break;
+            }
+
+            let end = start + thumbnail_duration;
+            thumbnail_times.push(start);
+
+            vtt += &format!(
+                "\n{} --> {}\n{}",
+                vtt_timestamp(start),
+                vtt_timestamp(end),
+                tile_name(i, ext)
+            );
+
+            if let (Some((width, height)), true) = (stream.resolution, thumbnails > 1) {
+                let (width, height) = (width / columns, height / rows);
+                vtt += &format!(
+                    "#xywh={},{},{},{}",
+                    (j % columns) * width,
+                    (j / columns) * height,
+                    width,
+                    height
+                );
+            }
+
+            vtt += "\n";
+        }
+
+        all_thumbnail_times.push(thumbnail_times);
+        start_time += segment.duration as f64;
+    }
+
+    (vtt, all_thumbnail_times)
+}
+
+/// Slice a tile into individual thumbnails named after their presentation time.
+fn slice_tile(
+    tile_path: &Path,
+    thumbnails_directory: &Path,
+    (columns, rows): (u64, u64),
+    thumbnail_times: &[f64],
+    ext: &str,
+) -> Result<()> {
+    let pattern = thumbnails_directory.join(format!("slice_%03d.{}", ext));
+    let code = Command::new("ffmpeg")
+        .args([
+            "-hide_banner".to_owned(),
+            "-y".to_owned(),
+            "-i".to_owned(),
+            tile_path.to_string_lossy().to_string(),
+            "-vf".to_owned(),
+            format!("untile={}x{}", columns, rows),
+            "-start_number".to_owned(),
+            "0".to_owned(),
+            pattern.to_string_lossy().to_string(),
+        ])
+        .stderr(Stdio::null())
+        .spawn()?
+        .wait()?;
+
+    if !code.success() {
+        bail!("ffmpeg exited with code {}", code.code().unwrap_or(1))
+    }
+
+    for (j, start) in thumbnail_times.iter().enumerate() {
+        let slice = thumbnails_directory.join(format!("slice_{:03}.{}", j, ext));
+
+        if slice.exists() {
+            std::fs::rename(
+                slice,
+                thumbnails_directory.join(format!(
+                    "{}.{}",
+                    vtt_timestamp(*start).replace(':', "-"),
+                    ext
+                )),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn vtt_timestamp(seconds: f64) -> String {
+    let milliseconds = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        milliseconds / 3_600_000,
+        (milliseconds / 60_000) % 60,
+        (milliseconds / 1000) % 60,
+        milliseconds % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sprite_index;
+    use crate::playlist::{MediaPlaylist, Segment};
+
+    #[test]
+    fn sprite_cues() {
+        let stream = MediaPlaylist {
+            resolution: Some((640, 360)),
+            segments: [8.0, 4.0]
+                .into_iter()
+                .map(|duration| Segment {
+                    duration,
+                    ..Default::default()
+                })
+                .collect(),
+            tiles: Some((2, 2)),
+            ..Default::default()
+        };
+        let (vtt, thumbnail_times) = sprite_index(&stream, "jpg");
+
+        assert_eq!(
+            vtt,
+            "WEBVTT\n\
+             \n00:00:00.000 --> 00:00:02.000\ntile_00001.jpg#xywh=0,0,320,180\n\
+             \n00:00:02.000 --> 00:00:04.000\ntile_00001.jpg#xywh=320,0,320,180\n\
+             \n00:00:04.000 --> 00:00:06.000\ntile_00001.jpg#xywh=0,180,320,180\n\
+             \n00:00:06.000 --> 00:00:08.000\ntile_00001.jpg#xywh=320,180,320,180\n\
+             \n00:00:08.000 --> 00:00:10.000\ntile_00002.jpg#xywh=0,0,320,180\n\
+             \n00:00:10.000 --> 00:00:12.000\ntile_00002.jpg#xywh=320,0,320,180\n"
+        );
+        assert_eq!(
+            thumbnail_times,
+            vec![vec![0.0, 2.0, 4.0, 6.0], vec![8.0, 10.0]]
+        );
+    }
+
+    #[test]
+    fn single_thumbnail_tiles() {
+        let stream = MediaPlaylist {
+            resolution: Some((320, 180)),
+            segments: vec![Segment {
+                duration: 5.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            sprite_index(&stream, "png").0,
+            "WEBVTT\n\n00:00:00.000 --> 00:00:05.000\ntile_00001.png\n"
+        );
+    }
+}
@@ -0,0 +1,586 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-6.2.5.2
+    2. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-6.3.3
+
+*/
+
+use super::{check_reqwest_error, data_url, Keys, Stream};
+use crate::{
+    hls::{self, LowLatency},
+    playlist::{Key, KeyMethod, Map, MediaPlaylist, Range, Segment},
+    utils,
+};
+use anyhow::{anyhow, bail, Result};
+use kdam::{term::Colorizer, tqdm, BarExt, Column, RichProgress};
+use reqwest::{blocking::Client, header, Url};
+use std::{
+    fs::File,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+pub(super) struct Recorder {
+    pub(super) all_keys: bool,
+    pub(super) base_url: Option<Url>,
+    pub(super) client: Client,
+    pub(super) keys: Vec<(Option<String>, String)>,
+    pub(super) no_decrypt: bool,
+    pub(super) retry_count: u8,
+}
+
+struct Context {
+    recorder: Recorder,
+    pb: Mutex<RichProgress>,
+    recorded_bytes: AtomicUsize,
+    stop: AtomicBool,
+}
+
+/// State of a stream which is being recorded.
+#[derive(Default)]
+struct State {
+    file: Option<File>,
+    key: Option<(Key, Option<Keys>)>,
+    map: Option<(Map, Vec<u8>)>,
+    map_written: bool,
+    /// Media sequence number of the last recorded segment and its bytes recorded so far.
+    recorded: (u64, usize),
+}
+
+impl Recorder {
+    /// Record live streams (each one in a separate thread) until their playlists end or ctrl+c is pressed.
+    pub(super) fn record(
+        self,
+        streams: Vec<(MediaPlaylist, String)>,
+        temp_files: &mut Vec<Stream>,
+    ) -> Result<()> {
+        let context = Arc::new(Context {
+            recorder: self,
+            pb: Mutex::new(RichProgress::new(
+                tqdm!(unit = " SEG".to_owned(), dynamic_ncols = true),
+                vec![
+                    Column::Text("[bold blue]?".to_owned()),
+                    Column::Text("•".to_owned()),
+                    Column::Count,
+                    Column::Text("•".to_owned()),
+                    Column::ElapsedTime,
+                    Column::Text("•".to_owned()),
+                    Column::Rate,
+                ],
+            )),
+            recorded_bytes: AtomicUsize::new(0),
+            stop: AtomicBool::new(false),
+        });
+
+        let stop_context = context.clone();
+        ctrlc::set_handler(move || stop_context.stop.store(true, Ordering::SeqCst))?;
+
+        context.pb.lock().unwrap().write(format!(
+            "   {} live streams (press ctrl+c to stop recording)",
+            "Recording".colorize("bold green"),
+        ))?;
+
+        let mut handles = vec![];
+
+        for (stream, temp_file) in streams {
+            context.pb.lock().unwrap().write(format!(
+                "{} {} stream {} to {}",
+                "Recording".colorize("bold green"),
+                stream.media_type,
+                stream.display_stream().colorize("cyan"),
+                temp_file.colorize("cyan"),
+            ))?;
+
+            temp_files.push(Stream {
+                dispositions: stream.dispositions(),
+                file_path: temp_file.clone(),
+                language: stream.language.clone(),
+                media_type: stream.media_type.clone(),
+                title: stream.name.clone(),
+            });
+
+            let context = context.clone();
+            handles.push(std::thread::spawn(move || {
+                let result = context.record_stream(&stream, &temp_file);
+
+                if result.is_err() {
+                    // Stop other streams too, they cannot be muxed anyway.
+                    context.stop.store(true, Ordering::SeqCst);
+                }
+
+                result
+            }));
+        }
+
+        for handle in handles {
+            handle
+                .join()
+                .map_err(|_| anyhow!("live recording thread panicked."))??;
+        }
+
+        Ok(())
+    }
+}
+
+impl Context {
+    fn record_stream(&self, stream: &MediaPlaylist, temp_file: &str) -> Result<()> {
+        let playlist_url = stream.uri.parse::<Url>()?;
+        let base_url = self
+            .recorder
+            .base_url
+            .clone()
+            .unwrap_or(playlist_url.clone());
+        let mut state = State {
+            file: Some(File::create(temp_file)?),
+            ..Default::default()
+        };
+        let mut position = None;
+        let mut blocking_reload: Option<(u64, Option<u64>)> = None;
+
+        while !self.stop.load(Ordering::SeqCst) {
+            let mut url = playlist_url.clone();
+
+            if let Some((msn, part)) = blocking_reload {
+                let mut query = url.query_pairs_mut();
+                query.append_pair("_HLS_msn", &msn.to_string());
+
+                if let Some(part) = part {
+                    query.append_pair("_HLS_part", &format!("{}", part));
+                }
+            }
+
+            let text = String::from_utf8(self.fetch(url, None)?)?;
+            let (text, _) = hls::substitute_variables(&text, &playlist_url, &stream.variables)?;
+            let m3u8 = m3u8_rs::parse_media_playlist_res(text.as_bytes()).map_err(|x| {
+                anyhow!(
+                    "couldn't parse response as hls playlist (failed with {}).\n\n{}\n\n{}",
+                    x,
+                    stream.uri,
+                    text
+                )
+            })?;
+            let low_latency = LowLatency::parse(&text);
+            let mut playlist = MediaPlaylist::default();
+            hls::push_segments(&m3u8, &mut playlist);
+
+            let first_msn = m3u8.media_sequence;
+            // Media sequence number of the segment which is being currently produced.
+            let edge_msn = first_msn + playlist.segments.len() as u64;
+
+            if stream.start_offset.is_some() && playlist.start_offset.is_none() {
+                playlist.start_offset = stream.start_offset;
+            }
+
+            let (mut msn, mut part) = position.unwrap_or_else(|| {
+                if m3u8.end_list {
+                    (first_msn, 0)
+                } else if let Some(index) = playlist.start_index() {
+                    (first_msn + index as u64, 0)
+                } else if low_latency.parts_of(edge_msn).next().is_some() {
+                    // Start from the latest independent part of segment being currently produced.
+                    (
+                        edge_msn,
+                        low_latency
+                            .parts_of(edge_msn)
+                            .filter(|x| x.independent)
+                            .last()
+                            .map(|x| x.index)
+                            .unwrap_or(0),
+                    )
+                } else {
+                    // Hold back three target durations from live edge.
+                    (edge_msn.saturating_sub(3).max(first_msn), 0)
+                }
+            });
+
+            if msn < first_msn {
+                self.pb.lock().unwrap().write(format!(
+                    "    {} {} segments were removed from playlist before they could be recorded",
+                    "Warning".colorize("bold yellow"),
+                    first_msn - msn,
+                ))?;
+                msn = first_msn;
+                part = 0;
+            }
+
+            while msn < edge_msn && !self.stop.load(Ordering::SeqCst) {
+                let index = (msn - first_msn) as usize;
+                self.update_state(&mut state, &playlist.segments[..=index], &base_url)?;
+                let segment = &playlist.segments[index];
+
+                if segment.gap {
+                    // Unavailable segments are left out of the recording.
+                } else if part == 0 {
+                    let data = self.fetch(base_url.join(&segment.uri)?, segment.range.as_ref())?;
+                    self.write(&mut state, data, msn)?;
+                } else {
+                    let mut parts = low_latency.parts_of(msn).peekable();
+
+                    if parts.peek().is_none() {
+                        // Remaining partial segments were removed from playlist, a segment is
+                        // the concatenation of its parts, so rest of it is taken from the whole
+                        // segment.
+                        let data =
+                            self.fetch(base_url.join(&segment.uri)?, segment.range.as_ref())?;
+                        let recorded = if state.recorded.0 == msn {
+                            state.recorded.1
+                        } else {
+                            0
+                        };
+
+                        if data.len() > recorded {
+                            self.write(&mut state, data[recorded..].to_vec(), msn)?;
+                        }
+                    }
+
+                    for x in parts.filter(|x| x.index >= part && !x.gap) {
+                        let data = self.fetch(base_url.join(&x.uri)?, x.range.as_ref())?;
+                        self.write(&mut state, data, msn)?;
+                    }
+                }
+
+                msn += 1;
+                part = 0;
+            }
+
+            // Partial segments cannot be decrypted independently when whole segment is encrypted.
+            let parts_usable = !matches!(
+                state.key,
+                Some((
+                    _,
+                    Some(Keys {
+                        method: KeyMethod::Aes128,
+                        ..
+                    })
+                ))
+            );
+
+            if parts_usable && !m3u8.end_list {
+                self.update_state(&mut state, &playlist.segments, &base_url)?;
+
+                let parts = low_latency
+                    .parts_of(msn)
+                    .filter(|x| x.index >= part)
+                    .collect::<Vec<_>>();
+
+                for x in parts {
+                    if self.stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    if !x.gap {
+                        let data = self.fetch(base_url.join(&x.uri)?, x.range.as_ref())?;
+                        self.write(&mut state, data, msn)?;
+                    }
+
+                    part = x.index + 1;
+                }
+
+                // Server holds the response of preload hint until the part is available,
+                // which saves a playlist reload round trip.
+                if let Some(hint) = &low_latency.preload_hint {
+                    if hint.msn == msn && hint.index == part && !self.stop.load(Ordering::SeqCst) {
+                        let data = self.fetch(base_url.join(&hint.uri)?, hint.range.as_ref())?;
+                        self.write(&mut state, data, msn)?;
+                        part += 1;
+                    }
+                }
+            }
+
+            position = Some((msn, part));
+
+            if m3u8.end_list {
+                break;
+            }
+
+            if low_latency.can_block_reload {
+                blocking_reload = Some((
+                    msn,
+                    if parts_usable && low_latency.part_target.is_some() {
+                        Some(part)
+                    } else {
+                        None
+                    },
+                ));
+            } else {
+                let wait = if parts_usable {
+                    low_latency.part_target
+                } else {
+                    None
+                }
+                .unwrap_or(m3u8.target_duration as f32);
+                std::thread::sleep(Duration::from_secs_f32(wait.max(0.5)));
+            }
+        }
+
+        if let Some(mut file) = state.file.take() {
+            file.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Update map and keys using the latest `EXT-X-MAP` and `EXT-X-KEY` tags found in segments.
+    fn update_state(&self, state: &mut State, segments: &[Segment], base_url: &Url) -> Result<()> {
+        if let Some(map) = segments.iter().rev().find_map(|x| x.map.as_ref()) {
+            if state.map.as_ref().map(|x| is_same_map(&x.0, map)) != Some(true) {
+                let data = self.fetch(base_url.join(&map.uri)?, map.range.as_ref())?;
+                state.map = Some((map.clone(), data));
+                state.map_written = false;
+            }
+        }
+
+        if self.recorder.no_decrypt {
+            return Ok(());
+        }
+
+        if let Some(key) = segments.iter().rev().find_map(|x| x.key.as_ref()) {
+            if state.key.as_ref().map(|x| is_same_key(&x.0, key)) != Some(true) {
+                let keys = Keys::resolve(
+                    key,
+                    key.default_kid
+                        .as_ref()
+                        .map(|x| x.replace('-', "").to_lowercase()),
+                    self.recorder.all_keys,
+                    &self.recorder.keys,
+                    &self.recorder.client,
+                    base_url,
+                )?;
+                state.key = Some((key.clone(), keys));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, state: &mut State, mut data: Vec<u8>, msn: u64) -> Result<()> {
+        if state.recorded.0 != msn {
+            state.recorded = (msn, 0);
+        }
+
+        state.recorded.1 += data.len();
+        let file = state.file.as_mut().unwrap();
+
+        match &state.key {
+            Some((key, Some(keys))) => {
+                if keys.method == KeyMethod::Cenc {
+                    if let Some((_, map)) = &state.map {
+                        let mut segment = map.clone();
+                        segment.append(&mut data);
+                        data = segment;
+                    }
+                }
+
+                let mut keys = keys.clone();
+
+                // Media sequence number is used as iv when it is not specified.
+                if keys.method == KeyMethod::Aes128 && key.iv.is_none() {
+                    keys.iv = Some(format!("{:032x}", msn));
+                }
+
+                data = keys.decrypt(data)?;
+            }
+            _ => {
+                if let (Some((_, map)), false) = (&state.map, state.map_written) {
+                    file.write_all(map)?;
+                    state.map_written = true;
+                }
+            }
+        }
+
+        file.write_all(&data)?;
+
+        let recorded_bytes =
+            self.recorded_bytes.fetch_add(data.len(), Ordering::SeqCst) + data.len();
+        let mut pb = self.pb.lock().unwrap();
+        pb.replace(
+            0,
+            Column::Text(format!(
+                "[bold blue]{}",
+                utils::format_bytes(recorded_bytes, 2).2
+            )),
+        );
+        pb.update(1)?;
+        Ok(())
+    }
+
+    fn fetch(&self, url: Url, range: Option<&Range>) -> Result<Vec<u8>> {
+        if let Some(data) = data_url::decode(&url, range)? {
+            return Ok(data);
+        }
+
+        for _ in 0..self.recorder.retry_count {
+            let mut request = self.recorder.client.get(url.clone());
+
+            if let Some(range) = range {
+                request = request.header(header::RANGE, range.as_header_value());
+            }
+
+            let response = match request.send() {
+                Ok(response) => response,
+                Err(error) => {
+                    self.pb
+                        .lock()
+                        .unwrap()
+                        .write(check_reqwest_error(&error)?)?;
+                    continue;
+                }
+            };
+
+            let status = response.status();
+
+            if status.is_client_error() || status.is_server_error() {
+                bail!("failed to fetch {} (HTTP {})", url, status);
+            }
+
+            return Ok(response.bytes()?.to_vec());
+        }
+
+        bail!("reached maximum number of retries to fetch {}", url);
+    }
+}
+
+fn is_same_map(x: &Map, y: &Map) -> bool {
+    x.uri == y.uri
+        && x.range.as_ref().map(|x| (x.start, x.end)) == y.range.as_ref().map(|y| (y.start, y.end))
+}
+
+fn is_same_key(x: &Key, y: &Key) -> bool {
+    x.method == y.method && x.uri == y.uri && x.iv == y.iv && x.key_format == y.key_format
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Context, Recorder};
+    use crate::playlist::MediaPlaylist;
+    use kdam::{tqdm, Column, RichProgress};
+    use reqwest::blocking::Client;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize},
+            Mutex,
+        },
+        thread,
+    };
+
+    /// Serve `files` by path, consecutive requests of a path get its next body.
+    fn serve(files: Vec<(&'static str, Vec<&'static str>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let mut files = files;
+
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+
+                let path = request_line.split(' ').nth(1).unwrap();
+                let path = path.split('?').next().unwrap().trim_start_matches('/');
+                let bodies = &mut files.iter_mut().find(|x| x.0 == path).unwrap().1;
+                let body = if bodies.len() > 1 {
+                    bodies.remove(0)
+                } else {
+                    bodies[0]
+                };
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn parts_removed_from_playlist() {
+        let url = serve(vec![
+            (
+                "live.m3u8",
+                vec![
+                    "#EXTM3U
+#EXT-X-TARGETDURATION:4
+#EXT-X-PART-INF:PART-TARGET=1.0
+#EXT-X-MEDIA-SEQUENCE:0
+#EXTINF:4.0,
+seg0.ts
+#EXT-X-PART:DURATION=1.0,URI=\"part1.0.ts\",INDEPENDENT=YES
+#EXT-X-PART:DURATION=1.0,URI=\"part1.1.ts\"
+",
+                    "#EXTM3U
+#EXT-X-TARGETDURATION:4
+#EXT-X-MEDIA-SEQUENCE:0
+#EXTINF:4.0,
+seg0.ts
+#EXTINF:4.0,
+seg1.ts
+#EXTINF:4.0,
+seg2.ts
+#EXT-X-ENDLIST
+",
+                ],
+            ),
+            ("seg0.ts", vec!["0000"]),
+            ("part1.0.ts", vec!["1111"]),
+            ("part1.1.ts", vec!["2222"]),
+            ("seg1.ts", vec!["111122223333"]),
+            ("seg2.ts", vec!["4444"]),
+        ]);
+        let context = Context {
+            recorder: Recorder {
+                all_keys: false,
+                base_url: None,
+                client: Client::builder().no_proxy().build().unwrap(),
+                keys: vec![],
+                no_decrypt: true,
+                retry_count: 1,
+            },
+            pb: Mutex::new(RichProgress::new(
+                tqdm!(disable = true),
+                vec![Column::Count],
+            )),
+            recorded_bytes: AtomicUsize::new(0),
+            stop: AtomicBool::new(false),
+        };
+        let temp_file = std::env::temp_dir().join(format!("vsd-live-{}.ts", std::process::id()));
+        let stream = MediaPlaylist {
+            uri: format!("{}live.m3u8", url),
+            ..Default::default()
+        };
+
+        context
+            .record_stream(&stream, temp_file.to_str().unwrap())
+            .unwrap();
+
+        // Recording starts from latest independent part of segment being produced.
+        assert_eq!(
+            std::fs::read_to_string(&temp_file).unwrap(),
+            "1111222233334444"
+        );
+        std::fs::remove_file(temp_file).unwrap();
+    }
+}
@@ -1,27 +1,36 @@
+mod data_url;
+mod events;
 mod fetch;
+mod image;
+mod live;
 mod parse;
 mod subtitle;
 
 pub use fetch::{fetch_playlist, InputMetadata};
+pub use image::download_image_streams;
 pub use parse::{parse_all_streams, parse_selected_streams};
-pub use subtitle::download_subtitle_streams;
+pub use subtitle::{download_subtitle_streams, extract_closed_captions};
 
 use crate::{
     merger::Merger,
-    playlist::{KeyMethod, MediaPlaylist, MediaType, Range, Segment},
+    playlist::{Key, KeyMethod, MediaPlaylist, MediaType, PlaylistType, Range, Segment},
     utils,
 };
 use anyhow::{anyhow, bail, Result};
+use events::InbandEventParser;
 use kdam::{term::Colorizer, tqdm, BarExt, Column, RichProgress};
 use reqwest::{
-    blocking::{Client, RequestBuilder},
+    blocking::{Client, RequestBuilder, Response},
     header, StatusCode, Url,
 };
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     path::{Path, PathBuf},
     process::{Command, Stdio},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::Instant,
 };
 use vsd_mp4::pssh::Pssh;
@@ -34,29 +43,53 @@ pub struct Prompts {
 }
 
 pub struct Stream {
+    /// Track dispositions used by ffmpeg (`forced`, `hearing_impaired`, ...).
+    pub dispositions: Vec<String>,
     pub file_path: String,
     pub language: Option<String>,
     pub media_type: MediaType,
+    pub title: Option<String>,
 }
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn download(
     all_keys: bool,
     base_url: Option<Url>,
+    chapters: bool,
     client: Client,
     directory: Option<PathBuf>,
     keys: Vec<(Option<String>, String)>,
+    live: bool,
     no_decrypt: bool,
     no_merge: bool,
     output: Option<String>,
     selected_playlists: SelectedPlaylists,
     retry_count: u8,
+    save_events: Option<PathBuf>,
+    split_periods: bool,
     threads: u8,
 ) -> Result<()> {
-    let (mut video_audio_streams, subtitle_streams) = selected_playlists;
+    let (video_audio_streams, subtitle_streams) = selected_playlists;
+    let (image_streams, mut video_audio_streams): (Vec<_>, Vec<_>) = video_audio_streams
+        .into_iter()
+        .partition(|x| x.media_type == MediaType::Image);
 
-    let one_stream = (video_audio_streams.len() == 1) && subtitle_streams.is_empty();
-    let mut should_mux = !no_decrypt && !no_merge;
+    // Events of playlist, in-band events are added to them while downloading.
+    let mut all_events = vec![];
+
+    for stream in video_audio_streams.iter().chain(subtitle_streams.iter()) {
+        events::merge_events(&mut all_events, stream.events.clone());
+    }
+
+    // Chapters can only be added while muxing, so a single stream isn't written to output directly.
+    let one_stream = (video_audio_streams.len() == 1) && subtitle_streams.is_empty() && !chapters;
+    let mut should_mux = !no_decrypt
+        && !no_merge
+        && !split_periods
+        && (video_audio_streams
+            .iter()
+            .any(|x| x.media_type != MediaType::Undefined)
+            || !subtitle_streams.is_empty());
 
     if let Some(output) = &output {
         if one_stream
@@ -78,6 +111,11 @@ pub(crate) fn download(
         should_mux = false;
     }
 
+    let i_frames = video_audio_streams
+        .iter()
+        .find(|x| x.i_frame)
+        .map(|x| x.segments.len());
+
     if should_mux && utils::find_ffmpeg().is_none() {
         bail!("ffmpeg couldn't be found, it is required to continue further.");
     }
@@ -113,38 +151,58 @@ pub(crate) fn download(
     let mut kids = HashSet::new();
 
     for stream in &video_audio_streams {
-        let stream_base_url = base_url
-            .clone()
-            .unwrap_or(stream.uri.parse::<Url>().unwrap());
-
-        if let Some(segment) = stream.segments.get(0) {
-            if let Some(map) = &segment.map {
-                let url = stream_base_url.join(&map.uri)?;
-                let mut request = client.get(url);
-
-                if let Some(range) = &map.range {
-                    request = request.header(header::RANGE, range.as_header_value());
+        // Key ids declared by playlist are preferred, so that no media request is made.
+        let mut key_ids = stream
+            .segments
+            .first()
+            .and_then(|x| x.key.as_ref())
+            .map(|x| {
+                x.key_ids
+                    .iter()
+                    .map(|x| (x.uuid(), x.system.to_owned(), x.value.to_owned()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        if key_ids.is_empty() {
+            let stream_base_url = base_url
+                .clone()
+                .unwrap_or(stream.uri.parse::<Url>().unwrap());
+
+            if let Some(segment) = stream.segments.first() {
+                if let Some(map) = &segment.map {
+                    let url = stream_base_url.join(&map.uri)?;
+                    let bytes = data_url::fetch(&client, url, map.range.as_ref())?;
+                    let pssh = match Pssh::new(&bytes) {
+                        Ok(x) => x,
+                        // Undefined streams aren't necessarily mp4 files.
+                        Err(_) if stream.media_type == MediaType::Undefined => continue,
+                        Err(x) => bail!(x),
+                    };
+
+                    key_ids.extend(
+                        pssh.key_ids
+                            .into_iter()
+                            .map(|x| (x.uuid(), x.system_type.to_string(), x.value)),
+                    );
                 }
+            }
+        }
 
-                let response = request.send()?;
-                let pssh = Pssh::new(&response.bytes()?).map_err(|x| anyhow!(x))?;
-
-                for key_id in pssh.key_ids {
-                    if !kids.contains(&key_id.value) {
-                        kids.insert(key_id.value.clone());
-                        println!(
-                            "      {} {} {} ({})",
-                            "KeyId".colorize("bold green"),
-                            if default_kids.contains(&key_id.value) {
-                                "*"
-                            } else {
-                                " "
-                            },
-                            key_id.uuid(),
-                            key_id.system_type,
-                        );
-                    }
-                }
+        for (uuid, system, value) in key_ids {
+            if !kids.contains(&value) {
+                kids.insert(value.clone());
+                println!(
+                    "      {} {} {} ({})",
+                    "KeyId".colorize("bold green"),
+                    if default_kids.contains(&value) {
+                        "*"
+                    } else {
+                        " "
+                    },
+                    uuid,
+                    system,
+                );
             }
         }
     }
@@ -235,6 +293,13 @@ pub(crate) fn download(
                 "Warning".colorize("bold yellow")
             );
         }
+
+        if split_periods {
+            println!(
+                "    {} --output is ignored when --split-periods is used",
+                "Warning".colorize("bold yellow")
+            );
+        }
     }
 
     if !subtitle_streams.is_empty() && no_merge {
@@ -259,6 +324,58 @@ pub(crate) fn download(
         &mut temp_files,
     )?;
 
+    // -----------------------------------------------------------------------------------------
+    // Download Thumbnail Streams
+    // -----------------------------------------------------------------------------------------
+
+    download_image_streams(
+        base_url.clone(),
+        &client,
+        &directory,
+        &image_streams,
+        &mut pb,
+    )?;
+
+    // -----------------------------------------------------------------------------------------
+    // Record Live Streams
+    // -----------------------------------------------------------------------------------------
+
+    if live && video_audio_streams.iter().any(|x| x.live) {
+        if video_audio_streams.iter().all(|x| x.is_hls()) {
+            let mut streams = vec![];
+
+            for stream in std::mem::take(&mut video_audio_streams) {
+                let mut temp_file = stream
+                    .file_path(&directory, &stream.extension())
+                    .to_string_lossy()
+                    .to_string();
+
+                if let Some(output) = &output {
+                    if one_stream && output.ends_with(&format!(".{}", stream.extension())) {
+                        temp_file = output.to_owned();
+                    }
+                }
+
+                streams.push((stream, temp_file));
+            }
+
+            live::Recorder {
+                all_keys,
+                base_url: base_url.clone(),
+                client: client.clone(),
+                keys: keys.clone(),
+                no_decrypt,
+                retry_count,
+            }
+            .record(streams, &mut temp_files)?;
+        } else {
+            println!(
+                "    {} --live is only supported for HLS streams",
+                "Warning".colorize("bold yellow")
+            );
+        }
+    }
+
     // -----------------------------------------------------------------------------------------
     // Estimation
     // -----------------------------------------------------------------------------------------
@@ -279,7 +396,10 @@ pub(crate) fn download(
             let url = stream_base_url.join(&segment.uri)?;
             let mut request = client.head(url.clone());
 
-            if total_segments == 1 {
+            if let Some(data) = data_url::decode(&url, segment.range.as_ref())? {
+                // Embedded segments aren't requested, so they don't need to be split.
+                relative_sizes.push_back(total_segments * data.len());
+            } else if total_segments == 1 {
                 let response = request.send()?;
                 let content_length = response
                     .headers()
@@ -355,6 +475,10 @@ pub(crate) fn download(
     // -----------------------------------------------------------------------------------------
 
     let pool = threadpool::ThreadPool::new(threads as usize);
+    let duration = video_audio_streams
+        .iter()
+        .map(|x| x.segments.iter().map(|x| x.duration as f64).sum::<f64>())
+        .fold(0.0, f64::max);
 
     for stream in video_audio_streams {
         pb.lock().unwrap().write(format!(
@@ -385,109 +509,103 @@ pub(crate) fn download(
             }
         }
 
-        temp_files.push(Stream {
-            file_path: temp_file.clone(),
-            language: stream.language.clone(),
-            media_type: stream.media_type.clone(),
-        });
-        pb.lock().unwrap().write(format!(
-            "{} stream to {}",
-            "Downloading".colorize("bold green"),
-            temp_file.colorize("cyan"),
-        ))?;
+        let discontinuities = stream.discontinuities();
 
-        let merger = Arc::new(Mutex::new(if no_merge {
-            Merger::with_directory(stream.segments.len(), &temp_file)?
-        } else {
-            Merger::new(stream.segments.len(), &temp_file)?
-        }));
-        let timer = Arc::new(Instant::now());
+        if discontinuities.len() > 1 {
+            pb.lock().unwrap().write(format!(
+                "    {} stream has {} discontinuities, each one is downloaded separately",
+                "Warning".colorize("bold yellow"),
+                discontinuities.len() - 1,
+            ))?;
+        }
 
-        let _ = relative_sizes.pop_front();
-        let relative_size = relative_sizes.iter().sum();
+        let stream_size = relative_sizes.pop_front().unwrap_or(0);
+        let relative_size = relative_sizes.iter().sum::<usize>();
         let mut previous_map = None;
         let mut previous_key = None;
+        let mut latest_map = None;
+        let mut part_files = vec![];
 
         let stream_base_url = base_url
             .clone()
             .unwrap_or(stream.uri.parse::<Url>().unwrap());
+        // Index of the pathway which served the last segment successfully.
+        let pathway = Arc::new(AtomicUsize::new(0));
+        let stream_events = Arc::new(Mutex::new(vec![]));
+        let inband_schemes = stream
+            .inband_events
+            .as_ref()
+            .map(|x| Arc::new(x.schemes.clone()));
+        let segment_starts = stream
+            .segments
+            .iter()
+            .scan(0.0, |time, x| {
+                let start = *time + x.gap_before as f64;
+                *time = start + x.duration as f64;
+                Some(start)
+            })
+            .collect::<Vec<_>>();
 
-        for (i, segment) in stream.segments.iter().enumerate() {
-            if let Some(map) = &segment.map {
-                let url = stream_base_url.join(&map.uri)?;
-                let mut request = client.get(url);
+        for (part, segments_range) in discontinuities.iter().enumerate() {
+            let part_file = if discontinuities.len() == 1 {
+                temp_file.clone()
+            } else {
+                part_file_path(&temp_file, part)
+            };
 
-                if let Some(range) = &map.range {
-                    request = request.header(header::RANGE, range.as_header_value());
-                }
+            pb.lock().unwrap().write(format!(
+                "{} stream to {}",
+                "Downloading".colorize("bold green"),
+                part_file.colorize("cyan"),
+            ))?;
 
-                let response = request.send()?;
-                let bytes = response.bytes()?;
-                previous_map = Some(bytes.to_vec())
+            let segments = &stream.segments[segments_range.clone()];
+            let merger = Arc::new(Mutex::new(if no_merge {
+                Merger::with_directory(segments.len(), &part_file)?
+            } else {
+                Merger::new(segments.len(), &part_file)?
+            }));
+            let timer = Arc::new(Instant::now());
+            let relative_size =
+                relative_size + ((stream_size * (length - segments_range.end)) / length);
+
+            // A discontinuity without a new map still needs the previous one.
+            if previous_map.is_none() {
+                previous_map = latest_map.clone();
             }
 
-            if !no_decrypt {
-                if let Some(key) = &segment.key {
-                    match key.method {
-                        KeyMethod::Aes128 => {
-                            if !keys.is_empty() {
-                                bail!("custom keys with AES-128 encryption is not supported");
-                            }
-
-                            if let Some(uri) = &key.uri {
-                                previous_key = Some(Keys {
-                                    bytes: if key.key_format.is_none() {
-                                        let url = stream_base_url.join(uri)?;
-                                        let request = client.get(url);
-                                        let response = request.send()?;
-                                        response.bytes()?.to_vec()
-                                    } else {
-                                        vec![]
-                                    },
-                                    iv: key.iv.clone(),
-                                    method: key.method.clone(),
-                                });
-                            } else {
-                                bail!("uri cannot be none when key method is AES-128");
-                            }
-                        }
-                        KeyMethod::Cenc => {
-                            let default_kid = stream.default_kid();
-                            let mut decryption_keys = HashMap::new();
-
-                            if all_keys {
-                                for key in &keys {
-                                    if let Some(kid) = &key.0 {
-                                        decryption_keys.insert(kid.to_owned(), key.1.to_owned());
-                                    } else if let Some(default_kid) = &default_kid {
-                                        decryption_keys
-                                            .insert(default_kid.to_owned(), key.1.to_owned());
-                                    }
-                                }
-                            } else {
-                                for key in &keys {
-                                    if let Some(default_kid) = &default_kid {
-                                        if let Some(kid) = &key.0 {
-                                            if default_kid == kid {
-                                                decryption_keys
-                                                    .insert(kid.to_owned(), key.1.to_owned());
-                                            }
-                                        } else {
-                                            decryption_keys
-                                                .insert(default_kid.to_owned(), key.1.to_owned());
-                                        }
-                                    }
-                                }
-                            }
-
-                            if decryption_keys.is_empty() {
-                                bail!(
-                                    "cannot determine keys to use, bypass this error using {} flag.",
-                                    "--all-keys".colorize("bold green")
-                                );
-                            }
+            for (i, segment) in segments.iter().enumerate() {
+                if let Some(map) = &segment.map {
+                    let url = stream_base_url.join(&map.uri)?;
+                    let bytes = match data_url::decode(&url, map.range.as_ref())? {
+                        Some(data) => data,
+                        None => send_any(pathway_requests(&client, &stream, url, &map.range))?
+                            .bytes()?
+                            .to_vec(),
+                    };
+                    previous_map = Some(bytes);
+                    latest_map = previous_map.clone();
+                }
 
-                            for key in &decryption_keys {
+                if !no_decrypt {
+                    if let Some(key) = &segment.key {
+                        previous_key = Keys::resolve(
+                            key,
+                            stream.default_kid(),
+                            all_keys,
+                            &keys,
+                            &client,
+                            &stream_base_url,
+                        )?;
+
+                        if let Some(
+                            previous_key @ Keys {
+                                method: KeyMethod::Cenc,
+                                ..
+                            },
+                        ) = &previous_key
+                        {
+                            for key in previous_key.as_hex_keys() {
                                 pb.lock().unwrap().write(format!(
                                     "        {} {}:{}",
                                     "Key".colorize("bold green"),
@@ -495,60 +613,132 @@ pub(crate) fn download(
                                     key.1
                                 ))?;
                             }
-
-                            previous_key = Some(Keys::from_hex_keys(decryption_keys));
                         }
-                        _ => previous_key = None,
                     }
                 }
-            }
 
-            let url = stream_base_url.join(&segment.uri)?;
-            let mut request = client.get(url);
+                let url = stream_base_url.join(&segment.uri)?;
+
+                let thread_data = ThreadData {
+                    data: data_url::decode(&url, segment.range.as_ref())?,
+                    downloaded_bytes,
+                    inband_events: inband_schemes.as_ref().map(|schemes| InbandEventParser {
+                        events: stream_events.clone(),
+                        offset: stream
+                            .inband_events
+                            .as_ref()
+                            .and_then(|x| x.offsets.get(segment.discontinuity_sequence as usize))
+                            .copied()
+                            .unwrap_or(0.0),
+                        schemes: schemes.clone(),
+                        segment_start: segment_starts[segments_range.start + i],
+                    }),
+                    index: i,
+                    keys: previous_key.clone(),
+                    map: previous_map.clone(),
+                    merger: merger.clone(),
+                    pathway: pathway.clone(),
+                    pb: pb.clone(),
+                    relative_size,
+                    requests: pathway_requests(&client, &stream, url, &segment.range),
+                    timer: timer.clone(),
+                    total_retries: retry_count,
+                    track_id: matches!(stream.playlist_type, PlaylistType::Smooth)
+                        .then_some(crate::smooth::TRACK_ID),
+                };
 
-            if let Some(range) = &segment.range {
-                request = request.header(header::RANGE, range.as_header_value());
+                if previous_key.is_none() {
+                    previous_map = None;
+                }
+
+                pool.execute(move || {
+                    if let Err(e) = thread_data.execute() {
+                        let _lock = thread_data.pb.lock().unwrap();
+                        println!("\n{}: {}", "error".colorize("bold red"), e);
+                        std::process::exit(1);
+                    }
+                });
             }
 
-            let thread_data = ThreadData {
-                downloaded_bytes,
-                index: i,
-                keys: previous_key.clone(),
-                map: previous_map.clone(),
-                merger: merger.clone(),
-                pb: pb.clone(),
-                relative_size,
-                request,
-                timer: timer.clone(),
-                total_retries: retry_count,
-            };
+            pool.join();
+            let mut merger = merger.lock().unwrap();
+            merger.flush()?;
 
-            if previous_key.is_none() {
-                previous_map = None;
+            if !merger.buffered() {
+                bail!(
+                    "failed to download {} stream to {}",
+                    stream.display_stream().colorize("cyan"),
+                    part_file
+                );
             }
 
-            pool.execute(move || {
-                if let Err(e) = thread_data.execute() {
-                    let _lock = thread_data.pb.lock().unwrap();
-                    println!("\n{}: {}", "error".colorize("bold red"), e);
-                    std::process::exit(1);
-                }
-            });
+            downloaded_bytes += merger.stored();
+            part_files.push(part_file);
         }
 
-        pool.join();
-        let mut merger = merger.lock().unwrap();
-        merger.flush()?;
+        if part_files.len() > 1 && !no_merge && !split_periods {
+            if utils::find_ffmpeg().is_some() {
+                // Parts followed by a gap are padded to the start of next part.
+                let durations = discontinuities
+                    .iter()
+                    .map(|x| {
+                        stream
+                            .segments
+                            .get(x.end)
+                            .filter(|y| y.gap_before > 0.0)
+                            .map(|y| {
+                                stream.segments[x.clone()]
+                                    .iter()
+                                    .map(|z| z.duration as f64)
+                                    .sum::<f64>()
+                                    + y.gap_before as f64
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                concat_parts(&part_files, &durations, &temp_file)?;
+                part_files = vec![temp_file];
+            } else {
+                pb.lock().unwrap().write(format!(
+                    "    {} ffmpeg couldn't be found, discontinuities are saved as separate files",
+                    "Warning".colorize("bold yellow"),
+                ))?;
+            }
+        }
 
-        if !merger.buffered() {
-            bail!(
-                "failed to download {} stream to {}",
-                stream.display_stream().colorize("cyan"),
-                temp_file
-            );
+        events::merge_events(&mut all_events, stream_events.lock().unwrap().drain(..));
+
+        if !stream.closed_captions.is_empty() && !no_merge {
+            if part_files.len() == 1 {
+                if let Err(e) = extract_closed_captions(
+                    &stream,
+                    &part_files[0],
+                    &mut pb.lock().unwrap(),
+                    &mut temp_files,
+                ) {
+                    pb.lock().unwrap().write(format!(
+                        "    {} couldn't extract closed captions ({})",
+                        "Warning".colorize("bold yellow"),
+                        e,
+                    ))?;
+                }
+            } else {
+                pb.lock().unwrap().write(format!(
+                    "    {} closed captions aren't extracted from streams saved as {} separate parts",
+                    "Warning".colorize("bold yellow"),
+                    part_files.len(),
+                ))?;
+            }
         }
 
-        downloaded_bytes += merger.stored();
+        for part_file in part_files {
+            temp_files.push(Stream {
+                dispositions: stream.dispositions(),
+                file_path: part_file,
+                language: stream.language.clone(),
+                media_type: stream.media_type.clone(),
+                title: stream.name.clone(),
+            });
+        }
 
         pb.lock().unwrap().write(format!(
             " {} stream successfully",
@@ -558,13 +748,91 @@ pub(crate) fn download(
 
     eprintln!();
 
+    // -----------------------------------------------------------------------------------------
+    // Save Events
+    // -----------------------------------------------------------------------------------------
+
+    if let Some(save_events) = &save_events {
+        events::write_events(&all_events, save_events)?;
+        println!(
+            "      {} {} events to {}",
+            "Saved".colorize("bold green"),
+            all_events.len(),
+            save_events.to_string_lossy()
+        );
+    }
+
+    // -----------------------------------------------------------------------------------------
+    // Tile I-Frames Into Thumbnail Strip
+    // -----------------------------------------------------------------------------------------
+
+    if let (true, Some(output), Some(frames)) = (should_mux, &output, i_frames) {
+        let is_image = [".jpg", ".jpeg", ".png", ".webp"]
+            .iter()
+            .any(|x| output.to_lowercase().ends_with(x));
+
+        if let (true, Some(temp_file)) = (
+            is_image,
+            temp_files.iter().find(|x| x.media_type == MediaType::Video),
+        ) {
+            let (step, columns, rows) = thumbnail_grid(frames, output);
+            let args = [
+                "-hide_banner".to_owned(),
+                "-y".to_owned(),
+                "-i".to_owned(),
+                temp_file.file_path.clone(),
+                "-vsync".to_owned(),
+                "passthrough".to_owned(),
+                "-vf".to_owned(),
+                format!(
+                    "select=not(mod(n\\,{})),scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:-1:-1,tile={}x{}",
+                    step,
+                    columns,
+                    rows,
+                    w = THUMBNAIL_WIDTH,
+                    h = THUMBNAIL_HEIGHT,
+                ),
+                "-frames:v".to_owned(),
+                "1".to_owned(),
+                output.to_owned(),
+            ];
+
+            println!(
+                "  {} ffmpeg {}",
+                "Executing".colorize("bold cyan"),
+                args.join(" ")
+            );
+
+            let code = Command::new("ffmpeg")
+                .args(args)
+                .stderr(Stdio::null())
+                .spawn()?
+                .wait()?;
+
+            if !code.success() {
+                bail!("ffmpeg exited with code {}", code.code().unwrap_or(1))
+            }
+
+            for temp_file in &temp_files {
+                println!(
+                    "   {} {}",
+                    "Deleting".colorize("bold red"),
+                    temp_file.file_path
+                );
+                std::fs::remove_file(&temp_file.file_path)?;
+            }
+
+            return Ok(());
+        }
+    }
+
     // -----------------------------------------------------------------------------------------
     // Mux Downloaded Streams
     // -----------------------------------------------------------------------------------------
 
     let video_temp_files = temp_files
         .iter()
-        .filter(|x| (x.media_type == MediaType::Video) || (x.media_type == MediaType::Undefined))
+        .filter(|x| x.media_type == MediaType::Video)
         .collect::<Vec<_>>();
     let video_streams_count = video_temp_files.len();
     let audio_streams_count = temp_files
@@ -582,9 +850,7 @@ pub(crate) fn download(
         if let Some(output) = &output {
             let all_temp_files = temp_files
                 .iter()
-                .filter(|x| {
-                    (x.media_type == MediaType::Video) || (x.media_type == MediaType::Undefined)
-                })
+                .filter(|x| x.media_type == MediaType::Video)
                 .chain(
                     temp_files
                         .iter()
@@ -597,16 +863,41 @@ pub(crate) fn download(
                 )
                 .collect::<Vec<_>>();
 
+            for temp_file in temp_files
+                .iter()
+                .filter(|x| x.media_type == MediaType::Undefined)
+            {
+                println!(
+                    "    {} {} is not muxed, it is saved separately",
+                    "Warning".colorize("bold yellow"),
+                    temp_file.file_path
+                );
+            }
+
             let mut args = vec![];
 
             for temp_file in &all_temp_files {
                 args.extend_from_slice(&["-i".to_owned(), temp_file.file_path.clone()]);
             }
 
-            if (video_streams_count == 1)
-                || (audio_streams_count == 1)
-                || (subtitle_streams_count == 1)
-            {
+            let chapters_file = (chapters && !all_events.is_empty()).then(|| {
+                directory
+                    .as_ref()
+                    .map(|x| x.join("vsd_chapters.txt"))
+                    .unwrap_or(PathBuf::from("vsd_chapters.txt"))
+            });
+
+            if let Some(chapters_file) = &chapters_file {
+                events::write_chapters(&all_events, duration, chapters_file)?;
+                args.extend_from_slice(&[
+                    "-i".to_owned(),
+                    chapters_file.to_string_lossy().to_string(),
+                    "-map_chapters".to_owned(),
+                    all_temp_files.len().to_string(),
+                ]);
+            }
+
+            if all_temp_files.len() == 1 {
                 // TODO - Re-consider this copy
                 args.extend_from_slice(&["-c".to_owned(), "copy".to_owned()]);
             } else {
@@ -620,38 +911,7 @@ pub(crate) fn download(
                     args.extend_from_slice(&["-map".to_owned(), i.to_string()]);
                 }
 
-                let mut audio_index = 0;
-                let mut subtitle_index = 0;
-
-                for temp_file in &all_temp_files {
-                    match temp_file.media_type {
-                        MediaType::Audio => {
-                            if let Some(language) = &temp_file.language {
-                                args.extend_from_slice(&[
-                                    format!("-metadata:s:a:{}", audio_index),
-                                    format!("language={}", language),
-                                ]);
-                            }
-
-                            audio_index += 1;
-                        }
-                        MediaType::Subtitles => {
-                            if let Some(language) = &temp_file.language {
-                                args.extend_from_slice(&[
-                                    format!("-metadata:s:s:{}", subtitle_index),
-                                    format!("language={}", language),
-                                ]);
-                            }
-
-                            subtitle_index += 1;
-                        }
-                        _ => (),
-                    }
-                }
-
-                if subtitle_streams_count > 0 {
-                    args.extend_from_slice(&["-disposition:s:0".to_owned(), "default".to_owned()]);
-                }
+                args.extend(stream_metadata_args(&all_temp_files));
             }
 
             args.push(output.to_owned());
@@ -693,6 +953,15 @@ pub(crate) fn download(
                 std::fs::remove_file(&temp_file.file_path)?;
             }
 
+            if let Some(chapters_file) = &chapters_file {
+                println!(
+                    "   {} {}",
+                    "Deleting".colorize("bold red"),
+                    chapters_file.to_string_lossy()
+                );
+                std::fs::remove_file(chapters_file)?;
+            }
+
             if let Some(directory) = &directory {
                 if std::fs::read_dir(directory)?.next().is_none() {
                     println!(
@@ -709,6 +978,147 @@ pub(crate) fn download(
     Ok(())
 }
 
+const THUMBNAIL_WIDTH: usize = 320;
+const THUMBNAIL_HEIGHT: usize = 180;
+
+/// Every nth frame, columns and rows of a thumbnail strip made from `frames` key frames.
+///
+/// Frames are boxed into fixed size tiles and sampled evenly, so that the strip stays
+/// within the height which the image format can encode (16383px for WebP and 65535px otherwise).
+fn thumbnail_grid(frames: usize, output: &str) -> (usize, usize, usize) {
+    let max_height = if output.to_lowercase().ends_with(".webp") {
+        16383
+    } else {
+        65535
+    };
+    let columns = frames.clamp(1, 10);
+    let max_frames = (max_height / THUMBNAIL_HEIGHT) * columns;
+    let step = frames.div_ceil(max_frames).max(1);
+    let rows = frames.div_ceil(step).div_ceil(columns).max(1);
+    (step, columns, rows)
+}
+
+fn part_file_path(temp_file: &str, part: usize) -> String {
+    let path = Path::new(temp_file);
+
+    path.with_file_name(format!(
+        "{}_part{}.{}",
+        path.file_stem().unwrap().to_string_lossy(),
+        part + 1,
+        path.extension()
+            .map(|x| x.to_string_lossy())
+            .unwrap_or_default()
+    ))
+    .to_string_lossy()
+    .to_string()
+}
+
+/// Join files downloaded for each discontinuity into a single file.
+/// Unlike binary merge, ffmpeg's concat demuxer also rebases timestamps of every part.
+/// Next part starts after the duration (in seconds) of previous part, when it is known.
+/// Language, title and disposition arguments of audio and subtitle streams for ffmpeg.
+fn stream_metadata_args(temp_files: &[&Stream]) -> Vec<String> {
+    let mut args = vec![];
+    let mut audio_index = 0;
+    let mut subtitle_index = 0;
+
+    for temp_file in temp_files {
+        let (specifier, index) = match temp_file.media_type {
+            MediaType::Audio => ("a", &mut audio_index),
+            MediaType::Subtitles => ("s", &mut subtitle_index),
+            _ => continue,
+        };
+
+        if let Some(language) = &temp_file.language {
+            args.extend_from_slice(&[
+                format!("-metadata:s:{}:{}", specifier, index),
+                format!("language={}", language),
+            ]);
+        }
+
+        if let Some(title) = &temp_file.title {
+            args.extend_from_slice(&[
+                format!("-metadata:s:{}:{}", specifier, index),
+                format!("title={}", title),
+            ]);
+        }
+
+        let mut dispositions = temp_file.dispositions.clone();
+
+        if specifier == "s" && *index == 0 {
+            dispositions.insert(0, "default".to_owned());
+        }
+
+        if !dispositions.is_empty() {
+            args.extend_from_slice(&[
+                format!("-disposition:{}:{}", specifier, index),
+                dispositions.join("+"),
+            ]);
+        }
+
+        *index += 1;
+    }
+
+    args
+}
+
+fn concat_parts(part_files: &[String], durations: &[Option<f64>], output: &str) -> Result<()> {
+    let concat_file = format!("{}.txt", output);
+    let mut concat = String::new();
+
+    for (i, part_file) in part_files.iter().enumerate() {
+        let part_file = std::fs::canonicalize(part_file)?;
+        concat += &format!(
+            "file '{}'\n",
+            part_file.to_string_lossy().replace('\'', "'\\''")
+        );
+
+        if let Some(duration) = durations.get(i).copied().flatten() {
+            concat += &format!("duration {:.6}\n", duration);
+        }
+    }
+
+    std::fs::write(&concat_file, concat)?;
+
+    println!(
+        "  {} ffmpeg -f concat -safe 0 -i {} -c copy {}",
+        "Executing".colorize("bold cyan"),
+        concat_file,
+        output
+    );
+
+    let code = Command::new("ffmpeg")
+        .args([
+            "-hide_banner",
+            "-y",
+            "-f",
+            "concat",
+            "-safe",
+            "0",
+            "-i",
+            &concat_file,
+            "-c",
+            "copy",
+            output,
+        ])
+        .stderr(Stdio::null())
+        .spawn()?
+        .wait()?;
+
+    if !code.success() {
+        bail!("ffmpeg exited with code {}", code.code().unwrap_or(1))
+    }
+
+    std::fs::remove_file(&concat_file)?;
+
+    for part_file in part_files {
+        println!("   {} {}", "Deleting".colorize("bold red"), part_file);
+        std::fs::remove_file(part_file)?;
+    }
+
+    Ok(())
+}
+
 #[derive(Clone)]
 struct Keys {
     bytes: Vec<u8>,
@@ -717,6 +1127,73 @@ struct Keys {
 }
 
 impl Keys {
+    /// Keys to be used for decrypting segments encrypted with `key`.
+    fn resolve(
+        key: &Key,
+        default_kid: Option<String>,
+        all_keys: bool,
+        keys: &[(Option<String>, String)],
+        client: &Client,
+        base_url: &Url,
+    ) -> Result<Option<Self>> {
+        Ok(match key.method {
+            KeyMethod::Aes128 => {
+                if !keys.is_empty() {
+                    bail!("custom keys with AES-128 encryption is not supported");
+                }
+
+                if let Some(uri) = &key.uri {
+                    Some(Self {
+                        bytes: if key.key_format.is_none() {
+                            data_url::fetch(client, base_url.join(uri)?, None)?
+                        } else {
+                            vec![]
+                        },
+                        iv: key.iv.clone(),
+                        method: key.method.clone(),
+                    })
+                } else {
+                    bail!("uri cannot be none when key method is AES-128");
+                }
+            }
+            KeyMethod::Cenc => {
+                let mut decryption_keys = HashMap::new();
+
+                if all_keys {
+                    for key in keys {
+                        if let Some(kid) = &key.0 {
+                            decryption_keys.insert(kid.to_owned(), key.1.to_owned());
+                        } else if let Some(default_kid) = &default_kid {
+                            decryption_keys.insert(default_kid.to_owned(), key.1.to_owned());
+                        }
+                    }
+                } else {
+                    for key in keys {
+                        if let Some(default_kid) = &default_kid {
+                            if let Some(kid) = &key.0 {
+                                if default_kid == kid {
+                                    decryption_keys.insert(kid.to_owned(), key.1.to_owned());
+                                }
+                            } else {
+                                decryption_keys.insert(default_kid.to_owned(), key.1.to_owned());
+                            }
+                        }
+                    }
+                }
+
+                if decryption_keys.is_empty() {
+                    bail!(
+                        "cannot determine keys to use, bypass this error using {} flag.",
+                        "--all-keys".colorize("bold green")
+                    );
+                }
+
+                Some(Self::from_hex_keys(decryption_keys))
+            }
+            _ => None,
+        })
+    }
+
     fn from_hex_keys(keys: HashMap<String, String>) -> Self {
         let mut bytes = String::new();
 
@@ -786,22 +1263,39 @@ impl Iterator for PartialRangeIter {
     }
 }
 struct ThreadData {
+    /// Payload of the segment, when it is embedded in playlist as a `data:` url.
+    data: Option<Vec<u8>>,
     downloaded_bytes: usize,
+    inband_events: Option<InbandEventParser>,
     index: usize,
     keys: Option<Keys>,
     map: Option<Vec<u8>>,
     merger: Arc<Mutex<Merger>>,
+    pathway: Arc<AtomicUsize>,
     pb: Arc<Mutex<RichProgress>>,
     relative_size: usize,
-    request: RequestBuilder,
+    /// Requests of the segment on all pathways.
+    requests: Vec<RequestBuilder>,
     timer: Arc<Instant>,
     total_retries: u8,
+    /// Track id of synthesized init segment, which `tfhd` boxes of Smooth Streaming fragments are set to.
+    track_id: Option<u32>,
 }
 
 impl ThreadData {
     fn execute(&self) -> Result<()> {
+        let mut data = self.download_segment()?;
+
+        if let Some(inband_events) = &self.inband_events {
+            inband_events.parse(&data);
+        }
+
+        if let Some(track_id) = self.track_id {
+            crate::smooth::patch_track_id(&mut data, track_id);
+        }
+
         let mut segment = self.map.clone().unwrap_or(vec![]);
-        segment.append(&mut self.download_segment()?);
+        segment.append(&mut data);
 
         if let Some(keys) = &self.keys {
             segment = keys.decrypt(segment)?;
@@ -816,8 +1310,41 @@ impl ThreadData {
     }
 
     fn download_segment(&self) -> Result<Vec<u8>> {
+        if let Some(data) = &self.data {
+            return Ok(data.clone());
+        }
+
+        let start = self.pathway.load(Ordering::Relaxed);
+        let mut error = anyhow!("segment {} has no pathways to download from", self.index);
+
+        for i in 0..self.requests.len() {
+            let pathway = (start + i) % self.requests.len();
+
+            match self.download_segment_from(&self.requests[pathway]) {
+                Ok(data) => {
+                    self.pathway.store(pathway, Ordering::Relaxed);
+                    return Ok(data);
+                }
+                Err(e) => {
+                    if i + 1 < self.requests.len() {
+                        self.pb.lock().unwrap().write(format!(
+                            "    {} {}, switching to next pathway",
+                            "Warning".colorize("bold yellow"),
+                            e
+                        ))?;
+                    }
+
+                    error = e;
+                }
+            }
+        }
+
+        Err(error)
+    }
+
+    fn download_segment_from(&self, request: &RequestBuilder) -> Result<Vec<u8>> {
         for _ in 0..self.total_retries {
-            let response = match self.request.try_clone().unwrap().send() {
+            let response = match request.try_clone().unwrap().send() {
                 Ok(response) => response,
                 Err(error) => {
                     self.pb
@@ -831,7 +1358,11 @@ impl ThreadData {
             let status = response.status();
 
             if status.is_client_error() || status.is_server_error() {
-                bail!("failed to fetch segments");
+                bail!(
+                    "failed to fetch segments {} (HTTP {})",
+                    response.url(),
+                    status
+                );
             }
 
             let data = response.bytes()?.to_vec();
@@ -871,6 +1402,42 @@ impl ThreadData {
     }
 }
 
+/// Requests of a resource of a stream on all of its pathways, in order of preference.
+fn pathway_requests(
+    client: &Client,
+    stream: &MediaPlaylist,
+    url: Url,
+    range: &Option<Range>,
+) -> Vec<RequestBuilder> {
+    let mut urls = stream.pathway_urls(&url);
+    urls.insert(0, url);
+    urls.into_iter()
+        .map(|url| {
+            let mut request = client.get(url);
+
+            if let Some(range) = range {
+                request = request.header(header::RANGE, range.as_header_value());
+            }
+
+            request
+        })
+        .collect()
+}
+
+/// Send requests one by one until one of them succeeds.
+fn send_any(requests: Vec<RequestBuilder>) -> Result<Response> {
+    let mut error = None;
+
+    for request in requests {
+        match request.send().and_then(|x| x.error_for_status()) {
+            Ok(response) => return Ok(response),
+            Err(e) => error = Some(e),
+        }
+    }
+
+    Err(error.unwrap().into())
+}
+
 fn check_reqwest_error(error: &reqwest::Error) -> Result<String> {
     let request = "Request".colorize("bold yellow");
     let url = error.url().unwrap();
@@ -897,3 +1464,149 @@ fn check_reqwest_error(error: &reqwest::Error) -> Result<String> {
         bail!("download failed {}", url)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{stream_metadata_args, thumbnail_grid, Stream};
+    use crate::playlist::{MasterPlaylist, MediaType, StreamPreferences};
+
+    /// Metadata arguments of audio and subtitle streams in their sorted order.
+    fn metadata_args(playlist: MasterPlaylist) -> Vec<String> {
+        let temp_files = playlist
+            .sort_streams(&StreamPreferences::default())
+            .streams
+            .iter()
+            .filter(|x| matches!(x.media_type, MediaType::Audio | MediaType::Subtitles))
+            .map(|x| Stream {
+                dispositions: x.dispositions(),
+                file_path: x.uri.clone(),
+                language: x.language.clone(),
+                media_type: x.media_type.clone(),
+                title: x.name.clone(),
+            })
+            .collect::<Vec<_>>();
+        stream_metadata_args(&temp_files.iter().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn dash_dispositions() {
+        let mpd = dash_mpd::parse(
+            r#"<MPD><Period>
+<AdaptationSet mimeType="audio/mp4" lang="en"><Role schemeIdUri="urn:mpeg:dash:role:2011" value="main"/>
+<Representation id="main" bandwidth="128000"/></AdaptationSet>
+<AdaptationSet mimeType="audio/mp4" lang="en"><Role schemeIdUri="urn:mpeg:dash:role:2011" value="commentary"/><Label>Director</Label>
+<Representation id="commentary" bandwidth="128000"/></AdaptationSet>
+<AdaptationSet mimeType="audio/mp4" lang="en"><Accessibility schemeIdUri="urn:tva:metadata:cs:AudioPurposeCS:2007" value="1"/>
+<Representation id="description" bandwidth="128000"/></AdaptationSet>
+<AdaptationSet mimeType="text/vtt" lang="en"><Role schemeIdUri="urn:mpeg:dash:role:2011" value="caption"/>
+<Representation id="sdh" bandwidth="1000"/></AdaptationSet>
+<AdaptationSet mimeType="text/vtt" lang="en"><Role schemeIdUri="urn:mpeg:dash:role:2011" value="forced-subtitle"/>
+<Representation id="forced" bandwidth="1000"/></AdaptationSet>
+<AdaptationSet mimeType="text/vtt" lang="en"><Role schemeIdUri="urn:mpeg:dash:role:2011" value="subtitle"/>
+<Representation id="subtitle" bandwidth="1000"/></AdaptationSet>
+</Period></MPD>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata_args(crate::dash::parse_as_master(
+                &mpd,
+                "https://example.com/manifest.mpd"
+            )),
+            [
+                "-metadata:s:a:0",
+                "language=en",
+                "-metadata:s:a:1",
+                "language=en",
+                "-metadata:s:a:1",
+                "title=Director",
+                "-disposition:a:1",
+                "comment",
+                "-metadata:s:a:2",
+                "language=en",
+                "-disposition:a:2",
+                "visual_impaired+descriptions",
+                // Main subtitles are sorted before sdh and forced ones.
+                "-metadata:s:s:0",
+                "language=en",
+                "-disposition:s:0",
+                "default",
+                "-metadata:s:s:1",
+                "language=en",
+                "-disposition:s:1",
+                "hearing_impaired",
+                "-metadata:s:s:2",
+                "language=en",
+                "-disposition:s:2",
+                "forced",
+            ]
+        );
+    }
+
+    #[test]
+    fn hls_dispositions() {
+        let m3u8 = m3u8_rs::parse_master_playlist_res(
+            br#"#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",LANGUAGE="en",DEFAULT=YES,AUTOSELECT=YES,URI="audio/en.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English (AD)",LANGUAGE="en",CHARACTERISTICS="public.accessibility.describes-video",URI="audio/ad.m3u8"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID="subs",NAME="English (SDH)",LANGUAGE="en",CHARACTERISTICS="public.accessibility.transcribes-spoken-dialog,public.accessibility.describes-music-and-sound",URI="subs/sdh.m3u8"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID="subs",NAME="English",LANGUAGE="en",DEFAULT=YES,AUTOSELECT=YES,URI="subs/en.m3u8"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID="subs",NAME="English (Forced)",LANGUAGE="en",FORCED=YES,AUTOSELECT=YES,URI="subs/forced.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,AUDIO="aac",SUBTITLES="subs"
+video.m3u8
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata_args(crate::hls::parse_as_master(
+                &m3u8,
+                "https://example.com/master.m3u8"
+            )),
+            [
+                "-metadata:s:a:0",
+                "language=en",
+                "-metadata:s:a:0",
+                "title=English",
+                "-metadata:s:a:1",
+                "language=en",
+                "-metadata:s:a:1",
+                "title=English (AD)",
+                "-disposition:a:1",
+                "visual_impaired+descriptions",
+                // DEFAULT=YES renditions are sorted first.
+                "-metadata:s:s:0",
+                "language=en",
+                "-metadata:s:s:0",
+                "title=English",
+                "-disposition:s:0",
+                "default",
+                "-metadata:s:s:1",
+                "language=en",
+                "-metadata:s:s:1",
+                "title=English (Forced)",
+                "-disposition:s:1",
+                "forced",
+                "-metadata:s:s:2",
+                "language=en",
+                "-metadata:s:s:2",
+                "title=English (SDH)",
+                "-disposition:s:2",
+                "hearing_impaired",
+            ]
+        );
+    }
+
+    #[test]
+    fn thumbnail_grid_height() {
+        assert_eq!(thumbnail_grid(0, "a.jpg"), (1, 1, 1));
+        assert_eq!(thumbnail_grid(7, "a.jpg"), (1, 7, 1));
+        assert_eq!(thumbnail_grid(25, "a.png"), (1, 10, 3));
+        // 91 rows of 180px tiles fit in 16383px.
+        assert_eq!(thumbnail_grid(910, "a.webp"), (1, 10, 91));
+        assert_eq!(thumbnail_grid(911, "a.WEBP"), (2, 10, 46));
+        assert_eq!(thumbnail_grid(10000, "a.webp"), (11, 10, 91));
+        // 364 rows fit in 65535px.
+        assert_eq!(thumbnail_grid(10000, "a.jpg"), (3, 10, 334));
+    }
+}
@@ -1,11 +1,15 @@
 use crate::{
-    commands::Quality,
-    downloader::{InputMetadata, Prompts, SelectedPlaylists},
-    playlist::{MasterPlaylist, MediaPlaylist, PlaylistType},
-    utils
+    commands::{Quality, QualityFallback},
+    downloader::{data_url, InputMetadata, Prompts, SelectedPlaylists},
+    filter::StreamFilters,
+    playlist::{
+        MasterPlaylist, MediaPlaylist, MediaType, Pathway, PlaylistType, StreamPreferences,
+    },
 };
 use anyhow::{anyhow, bail, Result};
+use kdam::term::Colorizer;
 use reqwest::{blocking::Client, Url};
+use std::collections::HashMap;
 
 pub fn parse_all_streams(
     base_url: Option<Url>,
@@ -14,13 +18,14 @@ pub fn parse_all_streams(
 ) -> Result<MasterPlaylist> {
     match meta.pl_type {
         Some(PlaylistType::Dash) => {
-            let mpd = dash_mpd::parse(&meta.text).map_err(|x| {
+            let mut mpd = dash_mpd::parse(&meta.text).map_err(|x| {
                 anyhow!(
                     "couldn't parse response as dash playlist (failed with {}).\n\n{}",
                     x,
                     meta.text
                 )
             })?;
+            crate::dash::resolve_xlinks(&mut mpd, &meta.url, client)?;
             let mut playlist = crate::dash::parse_as_master(&mpd, meta.url.as_ref());
 
             for stream in playlist.streams.iter_mut() {
@@ -28,33 +33,31 @@ pub fn parse_all_streams(
                     &mpd,
                     stream,
                     base_url.as_ref().unwrap_or(&meta.url).as_str(),
+                    client,
                 )?;
                 stream.uri = meta.url.as_ref().to_owned();
             }
 
             Ok(playlist)
         }
-        Some(PlaylistType::Hls) => match m3u8_rs::parse_playlist_res(meta.text.as_bytes()) {
-            Ok(m3u8_rs::Playlist::MasterPlaylist(m3u8)) => {
-                let mut playlist = crate::hls::parse_as_master(&m3u8, meta.url.as_ref());
-
-                for stream in playlist.streams.iter_mut() {
-                    stream.uri = base_url
-                        .as_ref()
-                        .unwrap_or(&meta.url)
-                        .join(&stream.uri)?
-                        .to_string();
-
-                    let text;
-                    if let Some(bs) = stream.uri.strip_prefix("data:application/x-mpegurl;base64,") {
-                        let decoded = utils::decode_base64(bs)?;
-                        text = String::from_utf8(decoded)?;
-                    } else {
-                        let response = client.get(&stream.uri).send()?;
-                        text = response.text()?;
-                    }
+        Some(PlaylistType::Hls) => {
+            let (text, variables) =
+                crate::hls::substitute_variables(&meta.text, &meta.url, &HashMap::new())?;
+
+            match m3u8_rs::parse_playlist_res(text.as_bytes()) {
+                Ok(m3u8_rs::Playlist::MasterPlaylist(mut m3u8)) => {
+                    steer(&mut m3u8, &meta.url, client);
+                    let mut playlist = crate::hls::parse_as_master(&m3u8, meta.url.as_ref());
 
-                    let media_playlist = m3u8_rs::parse_media_playlist_res(text.as_bytes())
+                    for stream in playlist.streams.iter_mut() {
+                        stream.variables = variables.clone();
+                        let text = fetch_media_playlist(
+                            base_url.as_ref().unwrap_or(&meta.url),
+                            client,
+                            stream,
+                        )?;
+
+                        let media_playlist = m3u8_rs::parse_media_playlist_res(text.as_bytes())
                         .map_err(|x| {
                             anyhow!(
                                 "couldn't parse response as hls playlist (failed with {}).\n\n{}\n\n{}",
@@ -63,56 +66,70 @@ pub fn parse_all_streams(
                                 text
                             )
                         })?;
-                    crate::hls::push_segments(&media_playlist, stream);
-                }
+                        crate::hls::push_segments(&media_playlist, stream);
+                    }
 
-                Ok(playlist)
-            }
-            Ok(m3u8_rs::Playlist::MediaPlaylist(m3u8)) => {
-                let mut media_playlist = crate::playlist::MediaPlaylist {
-                    uri: meta.url.as_ref().to_owned(),
-                    ..Default::default()
-                };
-                crate::hls::push_segments(&m3u8, &mut media_playlist);
-                Ok(MasterPlaylist {
-                    playlist_type: PlaylistType::Hls,
-                    streams: vec![media_playlist],
-                    uri: meta.url.as_ref().to_owned(),
-                })
+                    Ok(playlist)
+                }
+                Ok(m3u8_rs::Playlist::MediaPlaylist(m3u8)) => {
+                    let mut media_playlist = crate::playlist::MediaPlaylist {
+                        media_type: MediaType::Video,
+                        uri: meta.url.as_ref().to_owned(),
+                        ..Default::default()
+                    };
+                    crate::hls::push_segments(&m3u8, &mut media_playlist);
+                    Ok(MasterPlaylist {
+                        playlist_type: PlaylistType::Hls,
+                        streams: vec![media_playlist],
+                        uri: meta.url.as_ref().to_owned(),
+                    })
+                }
+                Err(x) => bail!(
+                    "couldn't parse response as hls playlist (failed with {}).\n\n{}\n\n{}",
+                    x,
+                    meta.url,
+                    meta.text
+                ),
             }
-            Err(x) => bail!(
-                "couldn't parse response as hls playlist (failed with {}).\n\n{}\n\n{}",
-                x,
-                meta.url,
-                meta.text
-            ),
-        },
-        _ => bail!("couldn't determine playlist type, only DASH and HLS playlists are supported."),
+        }
+        Some(PlaylistType::Smooth) => {
+            let manifest = crate::smooth::parse(&meta.text)?;
+            crate::smooth::parse_as_master(
+                &manifest,
+                base_url.as_ref().unwrap_or(&meta.url),
+                meta.url.as_ref(),
+            )
+        }
+        _ => bail!("couldn't determine playlist type, only DASH, HLS and Smooth Streaming playlists are supported."),
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn parse_selected_streams(
     base_url: Option<Url>,
     client: &Client,
     meta: &InputMetadata,
-    prefer_audio_lang: Option<String>,
-    prefer_subs_lang: Option<String>,
+    preferences: &StreamPreferences,
     prompts: &Prompts,
     quality: Quality,
+    quality_fallback: QualityFallback,
+    filters: &StreamFilters,
+    i_frames: bool,
 ) -> Result<SelectedPlaylists> {
     match meta.pl_type {
         Some(PlaylistType::Dash) => {
-            let mpd = dash_mpd::parse(&meta.text).map_err(|x| {
+            let mut mpd = dash_mpd::parse(&meta.text).map_err(|x| {
                 anyhow!(
                     "couldn't parse response as dash playlist (failed with {}).\n\n{}",
                     x,
                     meta.text
                 )
             })?;
+            crate::dash::resolve_xlinks(&mut mpd, &meta.url, client)?;
             let (mut video_audio_streams, mut subtitle_streams) =
                 crate::dash::parse_as_master(&mpd, meta.url.as_ref())
-                    .sort_streams(prefer_audio_lang, prefer_subs_lang)
-                    .select_streams(quality, prompts.skip, prompts.raw)?;
+                    .sort_streams(preferences)
+                    .select_streams(quality, quality_fallback, filters, i_frames, prompts.skip, prompts.raw)?;
 
             for stream in video_audio_streams
                 .iter_mut()
@@ -122,39 +139,37 @@ pub fn parse_selected_streams(
                     &mpd,
                     stream,
                     base_url.as_ref().unwrap_or(&meta.url).as_str(),
+                    client,
                 )?;
                 stream.uri = meta.url.as_ref().to_owned();
             }
 
             Ok((video_audio_streams, subtitle_streams))
         }
-        Some(PlaylistType::Hls) => match m3u8_rs::parse_playlist_res(meta.text.as_bytes()) {
-            Ok(m3u8_rs::Playlist::MasterPlaylist(m3u8)) => {
-                let (mut video_audio_streams, mut subtitle_streams) =
-                    crate::hls::parse_as_master(&m3u8, meta.url.as_str())
-                        .sort_streams(prefer_audio_lang, prefer_subs_lang)
-                        .select_streams(quality, prompts.skip, prompts.raw)?;
-
-                for stream in video_audio_streams
-                    .iter_mut()
-                    .chain(subtitle_streams.iter_mut())
-                {
-                    stream.uri = base_url
-                        .as_ref()
-                        .unwrap_or(&meta.url)
-                        .join(&stream.uri)?
-                        .to_string();
-
-                    let text;
-                    if let Some(bs) = stream.uri.strip_prefix("data:application/x-mpegurl;base64,") {
-                        let decoded = utils::decode_base64(bs)?;
-                        text = String::from_utf8(decoded)?;
-                    } else {
-                        let response = client.get(&stream.uri).send()?;
-                        text = response.text()?;
-                    }
+        Some(PlaylistType::Hls) => {
+            let (text, variables) =
+                crate::hls::substitute_variables(&meta.text, &meta.url, &HashMap::new())?;
+
+            match m3u8_rs::parse_playlist_res(text.as_bytes()) {
+                Ok(m3u8_rs::Playlist::MasterPlaylist(mut m3u8)) => {
+                    steer(&mut m3u8, &meta.url, client);
+                    let (mut video_audio_streams, mut subtitle_streams) =
+                        crate::hls::parse_as_master(&m3u8, meta.url.as_str())
+                            .sort_streams(preferences)
+                            .select_streams(quality, quality_fallback, filters, i_frames, prompts.skip, prompts.raw)?;
+
+                    for stream in video_audio_streams
+                        .iter_mut()
+                        .chain(subtitle_streams.iter_mut())
+                    {
+                        stream.variables = variables.clone();
+                        let text = fetch_media_playlist(
+                            base_url.as_ref().unwrap_or(&meta.url),
+                            client,
+                            stream,
+                        )?;
 
-                    let media_playlist = m3u8_rs::parse_media_playlist_res(text.as_bytes())
+                        let media_playlist = m3u8_rs::parse_media_playlist_res(text.as_bytes())
                         .map_err(|x| {
                             anyhow!(
                                 "couldn't parse response as hls playlist (failed with {}).\n\n{}\n\n{}",
@@ -163,26 +178,187 @@ pub fn parse_selected_streams(
                                 text
                             )
                         })?;
-                    crate::hls::push_segments(&media_playlist, stream);
+                        crate::hls::push_segments(&media_playlist, stream);
+                    }
+
+                    Ok((video_audio_streams, subtitle_streams))
                 }
+                Ok(m3u8_rs::Playlist::MediaPlaylist(m3u8)) => {
+                    let mut media_playlist = MediaPlaylist {
+                        media_type: MediaType::Video,
+                        uri: meta.url.as_ref().to_owned(),
+                        ..Default::default()
+                    };
+                    crate::hls::push_segments(&m3u8, &mut media_playlist);
+                    Ok((vec![media_playlist], vec![]))
+                }
+                Err(x) => bail!(
+                    "couldn't parse response as hls playlist (failed with {}).\n\n{}\n\n{}",
+                    x,
+                    meta.url,
+                    meta.text
+                ),
+            }
+        }
+        Some(PlaylistType::Smooth) => {
+            let manifest = crate::smooth::parse(&meta.text)?;
+            crate::smooth::parse_as_master(
+                &manifest,
+                base_url.as_ref().unwrap_or(&meta.url),
+                meta.url.as_ref(),
+            )?
+            .sort_streams(preferences)
+            .select_streams(quality, quality_fallback, filters, i_frames, prompts.skip, prompts.raw)
+        }
+        _ => bail!("couldn't determine playlist type, only DASH, HLS and Smooth Streaming playlists are supported."),
+    }
+}
+
+fn steer(m3u8: &mut m3u8_rs::MasterPlaylist, url: &Url, client: &Client) {
+    if let Err(e) = crate::hls::steer(m3u8, url, client) {
+        println!(
+            "    {} couldn't load content steering manifest ({}), using default pathway",
+            "Warning".colorize("bold yellow"),
+            e
+        );
+    }
+}
+
+/// Fetch media playlist of a stream, switching to a redundant pathway if it fails.
+/// Variables (`EXT-X-DEFINE`) of fetched playlist are substituted.
+fn fetch_media_playlist(
+    base_url: &Url,
+    client: &Client,
+    stream: &mut MediaPlaylist,
+) -> Result<String> {
+    stream.uri = base_url.join(&stream.uri)?.to_string();
 
-                Ok((video_audio_streams, subtitle_streams))
+    for pathway in &mut stream.pathways {
+        pathway.uri = base_url.join(&pathway.uri)?.to_string();
+    }
+
+    let text = if let Some(data) = data_url::decode(&stream.uri.parse()?, None)? {
+        String::from_utf8(data)?
+    } else {
+        fetch_pathways(client, stream)?
+    };
+
+    Ok(crate::hls::substitute_variables(&text, &stream.uri.parse()?, &stream.variables)?.0)
+}
+
+fn fetch_pathways(client: &Client, stream: &mut MediaPlaylist) -> Result<String> {
+    for _ in 0..stream.pathways.len() {
+        match client
+            .get(&stream.uri)
+            .send()
+            .and_then(|x| x.error_for_status())
+            .and_then(|x| x.text())
+        {
+            Ok(text) => return Ok(text),
+            Err(e) => {
+                // Failed pathway is kept as the last resort for segments.
+                let pathway = stream.pathways.remove(0);
+                println!(
+                    "    {} {} ({}), switching to pathway {}",
+                    "Warning".colorize("bold yellow"),
+                    stream.uri,
+                    e,
+                    pathway.id.as_ref().unwrap_or(&pathway.uri),
+                );
+                stream.pathways.push(Pathway {
+                    id: stream.pathway_id.take(),
+                    uri: std::mem::replace(&mut stream.uri, pathway.uri),
+                });
+                stream.pathway_id = pathway.id;
             }
-            Ok(m3u8_rs::Playlist::MediaPlaylist(m3u8)) => {
-                let mut media_playlist = MediaPlaylist {
-                    uri: meta.url.as_ref().to_owned(),
-                    ..Default::default()
-                };
-                crate::hls::push_segments(&m3u8, &mut media_playlist);
-                Ok((vec![media_playlist], vec![]))
+        }
+    }
+
+    Ok(client
+        .get(&stream.uri)
+        .send()
+        .and_then(|x| x.error_for_status())
+        .and_then(|x| x.text())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fetch_pathways;
+    use crate::playlist::{MediaPlaylist, Pathway};
+    use reqwest::blocking::Client;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Serve `responses` (status line and body) to consecutive requests.
+    fn serve(responses: &'static [(&'static str, &'static str)]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
             }
-            Err(x) => bail!(
-                "couldn't parse response as hls playlist (failed with {}).\n\n{}\n\n{}",
-                x,
-                meta.url,
-                meta.text
-            ),
-        },
-        _ => bail!("couldn't determine playlist type, only DASH and HLS playlists are supported."),
+        });
+
+        url
+    }
+
+    fn stream(url: &str) -> MediaPlaylist {
+        MediaPlaylist {
+            pathway_id: Some("A".to_owned()),
+            pathways: vec![Pathway {
+                id: Some("B".to_owned()),
+                uri: format!("{}b.m3u8", url),
+            }],
+            uri: format!("{}a.m3u8", url),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn next_pathway() {
+        let url = serve(&[("404 Not Found", "missing"), ("200 OK", "#EXTM3U")]);
+        let mut stream = stream(&url);
+        let client = Client::builder().no_proxy().build().unwrap();
+
+        assert_eq!(fetch_pathways(&client, &mut stream).unwrap(), "#EXTM3U");
+        assert_eq!(stream.uri, format!("{}b.m3u8", url));
+        assert_eq!(stream.pathway_id.as_deref(), Some("B"));
+        assert_eq!(stream.pathways[0].id.as_deref(), Some("A"));
+    }
+
+    #[test]
+    fn last_pathway_error() {
+        let url = serve(&[
+            ("404 Not Found", "missing"),
+            ("503 Service Unavailable", "unavailable"),
+        ]);
+        let mut stream = stream(&url);
+        let client = Client::builder().no_proxy().build().unwrap();
+
+        let error = fetch_pathways(&client, &mut stream).unwrap_err();
+        assert!(error.to_string().contains("503"));
     }
 }
@@ -1,9 +1,20 @@
-use crate::{downloader::Stream, playlist::MediaPlaylist, utils};
+use super::data_url;
+use crate::{
+    downloader::Stream,
+    playlist::{MediaPlaylist, MediaType},
+    utils,
+};
 use anyhow::{anyhow, bail, Result};
 use kdam::{term::Colorizer, BarExt, Column, RichProgress};
-use reqwest::{blocking::Client, header, Url};
-use std::{fs::File, io::Write, path::PathBuf};
-use vsd_mp4::text::{ttml_text_parser, Mp4TtmlParser, Mp4VttParser};
+use reqwest::{blocking::Client, Url};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+use vsd_mp4::text::{
+    ttml_text_parser, CeaDecoder, Mp4CeaParser, Mp4TtmlParser, Mp4VttParser, TsCeaParser,
+};
 
 enum SubtitleType {
     Mp4Vtt,
@@ -72,26 +83,12 @@ pub fn download_subtitle_stream(
     for segment in &stream.segments {
         if let Some(map) = &segment.map {
             let url = stream_base_url.join(&map.uri)?;
-            let mut request = client.get(url);
-
-            if let Some(range) = &map.range {
-                request = request.header(header::RANGE, range.as_header_value());
-            }
-
-            let response = request.send()?;
-            let bytes = response.bytes()?;
+            let bytes = data_url::fetch(client, url, map.range.as_ref())?;
             subtitles_data.extend_from_slice(&bytes);
         }
 
         let url = stream_base_url.join(&segment.uri)?;
-        let mut request = client.get(url);
-
-        if let Some(range) = &segment.range {
-            request = request.header(header::RANGE, range.as_header_value());
-        }
-
-        let response = request.send()?;
-        let bytes = response.bytes()?;
+        let bytes = data_url::fetch(client, url, segment.range.as_ref())?;
         subtitles_data.extend_from_slice(&bytes);
 
         if first_run {
@@ -115,9 +112,11 @@ pub fn download_subtitle_stream(
                 .to_string_lossy()
                 .to_string();
             temp_files.push(Stream {
+                dispositions: stream.dispositions(),
                 file_path: temp_file.clone(),
                 language: stream.language.clone(),
                 media_type: stream.media_type.clone(),
+                title: stream.name.clone(),
             });
             pb.write(format!(
                 "{} stream to {}",
@@ -200,3 +199,138 @@ pub fn download_subtitle_streams(
 
     Ok(())
 }
+
+/// Extract closed captions advertised by a video stream from its downloaded file.
+pub fn extract_closed_captions(
+    stream: &MediaPlaylist,
+    temp_file: &str,
+    pb: &mut RichProgress,
+    temp_files: &mut Vec<Stream>,
+) -> Result<()> {
+    pb.write(format!(
+        " {} closed captions ({})",
+        "Extracting".colorize("bold cyan"),
+        stream
+            .closed_captions
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    ))?;
+
+    let mut reader = BufReader::new(File::open(temp_file)?);
+    let mut decoder = CeaDecoder::default();
+    let start_time;
+
+    if reader.fill_buf()?.first() == Some(&0x47) {
+        let mut parser = TsCeaParser::default();
+        let mut buf = vec![0; 1024 * 1024];
+
+        loop {
+            let size = reader.read(&mut buf)?;
+
+            if size == 0 {
+                break;
+            }
+
+            decoder.extend(parser.parse(&buf[..size])?);
+        }
+
+        decoder.extend(parser.flush()?);
+        start_time = parser.start_time();
+    } else {
+        let mut parser = None;
+        let mut moof = vec![];
+
+        // Top level boxes are read one by one to avoid reading whole file in memory.
+        while let Some((name, data)) = read_box(&mut reader)? {
+            match &name {
+                b"moov" => parser = Some(Mp4CeaParser::parse_init(&data)?),
+                b"moof" => moof = data,
+                b"mdat" => {
+                    if let Some(parser) = &mut parser {
+                        moof.extend_from_slice(&data);
+                        decoder.extend(parser.parse_media(&moof)?);
+                        moof.clear();
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        start_time = parser.and_then(|x| x.start_time());
+    }
+
+    let subtitles = decoder.decode(start_time.unwrap_or(0.0));
+    let temp_file = Path::new(temp_file);
+
+    for closed_caption in &stream.closed_captions {
+        if let Some((_, subtitles)) = subtitles
+            .iter()
+            .find(|x| x.0.eq_ignore_ascii_case(&closed_caption.channel))
+        {
+            let file_path = temp_file
+                .with_file_name(format!(
+                    "{}_{}.srt",
+                    temp_file.file_stem().unwrap().to_string_lossy(),
+                    closed_caption.channel.to_lowercase()
+                ))
+                .to_string_lossy()
+                .to_string();
+
+            File::create(&file_path)?.write_all(subtitles.as_srt().as_bytes())?;
+            pb.write(format!(
+                "   {} {} to {}",
+                "Extracted".colorize("bold green"),
+                closed_caption,
+                file_path.colorize("cyan"),
+            ))?;
+            temp_files.push(Stream {
+                dispositions: vec!["captions".to_owned()],
+                file_path,
+                language: closed_caption.language.clone(),
+                media_type: MediaType::Subtitles,
+                title: None,
+            });
+        } else {
+            pb.write(format!(
+                "    {} no captions found in {} channel",
+                "Warning".colorize("bold yellow"),
+                closed_caption.channel,
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read next top level mp4 box as (type, whole box including header).
+fn read_box<R: Read>(reader: &mut R) -> Result<Option<([u8; 4], Vec<u8>)>> {
+    let mut header = [0; 8];
+
+    if reader.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+
+    let name = [header[4], header[5], header[6], header[7]];
+    let mut data = header.to_vec();
+    let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+        // Box extends to end of file.
+        0 => {
+            reader.read_to_end(&mut data)?;
+            return Ok(Some((name, data)));
+        }
+        1 => {
+            let mut large_size = [0; 8];
+            reader.read_exact(&mut large_size)?;
+            data.extend_from_slice(&large_size);
+            u64::from_be_bytes(large_size)
+        }
+        x => x as u64,
+    };
+
+    reader
+        .take(size.saturating_sub(data.len() as u64))
+        .read_to_end(&mut data)?;
+    Ok(Some((name, data)))
+}
@@ -0,0 +1,683 @@
+/*
+    REFERENCES
+    ----------
+
+    Stream filter expressions (`--select-video`, `--select-audio`, `--select-subs` and `--select-other`).
+
+    | Syntax                  | Meaning                                                             |
+    | ----------------------- | ------------------------------------------------------------------- |
+    | field op value          | comparison, ops are = != < <= > >= ~ (contains) ^= (starts with)    |
+    | field op a,b            | any of the values (none of the values for !=)                       |
+    | flag                    | boolean field                                                       |
+    | !x, x & y, x | y, (x)   | not, and, or (& binds tighter than |), grouping                     |
+    | all, none               | matches every stream, matches no stream                             |
+    | x ; y                   | fallback, y is only used when x doesn't match any stream            |
+
+    | Field                   | Type                                                                |
+    | ----------------------- | ------------------------------------------------------------------- |
+    | bandwidth, bitrate      | number (k, M and G suffixes, optional bps)                          |
+    | channels                | number                                                              |
+    | codecs, codec           | text                                                                |
+    | fps, frame_rate         | number                                                              |
+    | group, group_id         | text                                                                |
+    | height, width           | number (optional p suffix)                                          |
+    | lang, language          | text (en also matches en-US)                                        |
+    | name                    | text                                                                |
+    | role                    | text (any of the roles)                                             |
+    | ad, audio_description   | flag                                                                |
+    | default                 | flag                                                                |
+    | forced                  | flag                                                                |
+    | sdh                     | flag                                                                |
+    | supplementary           | flag                                                                |
+
+*/
+
+use crate::playlist::MediaPlaylist;
+use std::str::FromStr;
+
+/// Filter expressions of each stream type.
+#[derive(Default)]
+pub(crate) struct StreamFilters {
+    pub(crate) audio: Option<StreamFilter>,
+    pub(crate) other: Option<StreamFilter>,
+    pub(crate) subs: Option<StreamFilter>,
+    pub(crate) video: Option<StreamFilter>,
+}
+
+/// Parsed filter expression with its fallbacks.
+#[derive(Clone, Debug)]
+pub(crate) struct StreamFilter {
+    alternatives: Vec<Expr>,
+}
+
+impl StreamFilter {
+    /// Filter which matches every stream.
+    pub(crate) fn all() -> Self {
+        Self {
+            alternatives: vec![Expr::All],
+        }
+    }
+
+    /// Indices of streams which are matched by the first matching alternative of expression.
+    pub(crate) fn select(&self, streams: &[MediaPlaylist]) -> Vec<usize> {
+        for expr in &self.alternatives {
+            let indices = streams
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| expr.matches(x))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+
+            if !indices.is_empty() {
+                return indices;
+            }
+        }
+
+        vec![]
+    }
+}
+
+impl FromStr for StreamFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+        };
+        let mut alternatives = vec![parser.parse_or()?];
+
+        while parser.next_if(&Token::Semicolon) {
+            alternatives.push(parser.parse_or()?);
+        }
+
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(format!("unexpected {} in filter expression.", token));
+        }
+
+        Ok(Self { alternatives })
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    All,
+    And(Box<Expr>, Box<Expr>),
+    Flag(Flag),
+    None,
+    Not(Box<Expr>),
+    Number(NumberField, Op, Vec<f64>),
+    Or(Box<Expr>, Box<Expr>),
+    Text(TextField, Op, Vec<String>),
+}
+
+impl Expr {
+    fn matches(&self, stream: &MediaPlaylist) -> bool {
+        match self {
+            Self::All => true,
+            Self::And(x, y) => x.matches(stream) && y.matches(stream),
+            Self::Flag(flag) => match flag {
+                Flag::AudioDescription => stream.audio_description,
+                Flag::Default => stream.default,
+                Flag::Forced => stream.forced,
+                Flag::Sdh => stream.sdh,
+                Flag::Supplementary => stream.is_supplementary(),
+            },
+            Self::None => false,
+            Self::Not(x) => !x.matches(stream),
+            Self::Number(field, op, values) => {
+                let Some(number) = (match field {
+                    NumberField::Bandwidth => stream.bandwidth.map(|x| x as f64),
+                    NumberField::Channels => stream.channels.map(|x| x as f64),
+                    NumberField::FrameRate => stream.frame_rate.map(|x| x as f64),
+                    NumberField::Height => stream.resolution.map(|x| x.1 as f64),
+                    NumberField::Width => stream.resolution.map(|x| x.0 as f64),
+                }) else {
+                    return *op == Op::Ne;
+                };
+
+                match op {
+                    Op::Eq => values.contains(&number),
+                    Op::Ne => !values.contains(&number),
+                    Op::Gt => values.iter().any(|x| number > *x),
+                    Op::Ge => values.iter().any(|x| number >= *x),
+                    Op::Lt => values.iter().any(|x| number < *x),
+                    Op::Le => values.iter().any(|x| number <= *x),
+                    Op::Contains | Op::StartsWith => false,
+                }
+            }
+            Self::Or(x, y) => x.matches(stream) || y.matches(stream),
+            Self::Text(field, op, values) => {
+                let texts = match field {
+                    TextField::Codecs => stream.codecs.iter().cloned().collect::<Vec<_>>(),
+                    TextField::GroupId => stream.group_id.iter().cloned().collect(),
+                    TextField::Language => stream.language.iter().cloned().collect(),
+                    TextField::Name => stream.name.iter().cloned().collect(),
+                    TextField::Role => stream.roles.clone(),
+                };
+                let matches = |value: &String| {
+                    texts.iter().any(|text| {
+                        let text = text.to_lowercase();
+
+                        match op {
+                            Op::Contains => text.contains(value),
+                            Op::StartsWith => text.starts_with(value),
+                            // Language tags also match their more specific tags.
+                            _ if *field == TextField::Language => {
+                                text == *value || text.starts_with(&format!("{}-", value))
+                            }
+                            _ => text == *value,
+                        }
+                    })
+                };
+
+                if *op == Op::Ne {
+                    !values.iter().any(matches)
+                } else {
+                    values.iter().any(matches)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Flag {
+    AudioDescription,
+    Default,
+    Forced,
+    Sdh,
+    Supplementary,
+}
+
+#[derive(Clone, Debug)]
+enum NumberField {
+    Bandwidth,
+    Channels,
+    FrameRate,
+    Height,
+    Width,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TextField {
+    Codecs,
+    GroupId,
+    Language,
+    Name,
+    Role,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Op {
+    Contains,
+    Eq,
+    Ge,
+    Gt,
+    Le,
+    Lt,
+    Ne,
+    StartsWith,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    And,
+    LeftParen,
+    Not,
+    Op(Op),
+    Or,
+    RightParen,
+    Semicolon,
+    Word(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::And => write!(f, "'&'"),
+            Self::LeftParen => write!(f, "'('"),
+            Self::Not => write!(f, "'!'"),
+            Self::Op(_) => write!(f, "comparison operator"),
+            Self::Or => write!(f, "'|'"),
+            Self::RightParen => write!(f, "')'"),
+            Self::Semicolon => write!(f, "';'"),
+            Self::Word(x) => write!(f, "'{}'", x),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            x if x.is_whitespace() => continue,
+            '&' => {
+                chars.next_if_eq(&'&');
+                Token::And
+            }
+            '|' => {
+                chars.next_if_eq(&'|');
+                Token::Or
+            }
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ';' => Token::Semicolon,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '=' => {
+                chars.next_if_eq(&'=');
+                Token::Op(Op::Eq)
+            }
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '~' => Token::Op(Op::Contains),
+            '^' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::StartsWith),
+            '"' | '\'' => {
+                let mut word = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(x) if x == c => break,
+                        Some(x) => word.push(x),
+                        None => return Err("unterminated quote in filter expression.".to_owned()),
+                    }
+                }
+
+                Token::Word(word)
+            }
+            _ => {
+                let mut word = c.to_string();
+
+                while let Some(x) =
+                    chars.next_if(|x| !x.is_whitespace() && !"&|()!;=<>~^\"'".contains(*x))
+                {
+                    word.push(x);
+                }
+
+                Token::Word(word)
+            }
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn next_if(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.position) == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+
+        while self.next_if(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_not()?;
+
+        while self.next_if(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.next_if(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+
+        if self.next_if(&Token::LeftParen) {
+            let expr = self.parse_or()?;
+
+            if !self.next_if(&Token::RightParen) {
+                return Err("missing ')' in filter expression.".to_owned());
+            }
+
+            return Ok(expr);
+        }
+
+        let field = match self.next() {
+            Some(Token::Word(x)) => x.to_lowercase(),
+            Some(x) => return Err(format!("unexpected {} in filter expression.", x)),
+            None => return Err("incomplete filter expression.".to_owned()),
+        };
+
+        let flag = match field.as_str() {
+            "all" => return Ok(Expr::All),
+            "none" => return Ok(Expr::None),
+            "ad" | "audio_description" => Some(Flag::AudioDescription),
+            "default" => Some(Flag::Default),
+            "forced" => Some(Flag::Forced),
+            "sdh" => Some(Flag::Sdh),
+            "supplementary" => Some(Flag::Supplementary),
+            _ => None,
+        };
+
+        if let Some(flag) = flag {
+            return Ok(Expr::Flag(flag));
+        }
+
+        let op = match self.next() {
+            Some(Token::Op(x)) => x.clone(),
+            _ => return Err(format!("expected comparison operator after '{}'.", field)),
+        };
+
+        let values = match self.next() {
+            Some(Token::Word(x)) => x
+                .split(',')
+                .map(|x| x.trim().to_lowercase())
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>(),
+            _ => return Err(format!("expected value after '{}' comparison.", field)),
+        };
+
+        let number_field = match field.as_str() {
+            "bandwidth" | "bitrate" => Some(NumberField::Bandwidth),
+            "channels" => Some(NumberField::Channels),
+            "fps" | "frame_rate" => Some(NumberField::FrameRate),
+            "height" => Some(NumberField::Height),
+            "width" => Some(NumberField::Width),
+            _ => None,
+        };
+
+        if let Some(number_field) = number_field {
+            if matches!(op, Op::Contains | Op::StartsWith) {
+                return Err(format!("'{}' can only be compared with numbers.", field));
+            }
+
+            let values = values
+                .iter()
+                .map(|x| parse_number(x).ok_or(format!("'{}' is not a number.", x)))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Expr::Number(number_field, op, values));
+        }
+
+        let text_field = match field.as_str() {
+            "codecs" | "codec" => TextField::Codecs,
+            "group" | "group_id" => TextField::GroupId,
+            "lang" | "language" => TextField::Language,
+            "name" => TextField::Name,
+            "role" => TextField::Role,
+            x => return Err(format!("unknown field '{}' in filter expression.", x)),
+        };
+
+        if matches!(op, Op::Gt | Op::Ge | Op::Lt | Op::Le) {
+            return Err(format!(
+                "'{}' cannot be compared with <, <=, > or >=.",
+                field
+            ));
+        }
+
+        Ok(Expr::Text(text_field, op, values))
+    }
+}
+
+/// Parse numbers like `720p`, `128k`, `8M` or `8Mbps`.
+fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim_end_matches("bps").trim_end_matches('p');
+    let (number, multiplier) = match text.chars().last()? {
+        'k' => (&text[..text.len() - 1], 1e3),
+        'm' => (&text[..text.len() - 1], 1e6),
+        'g' => (&text[..text.len() - 1], 1e9),
+        _ => (text, 1.0),
+    };
+
+    number.parse::<f64>().ok().map(|x| x * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StreamFilter, StreamFilters};
+    use crate::{
+        commands::{Quality, QualityFallback},
+        playlist::{MasterPlaylist, MediaPlaylist, MediaType, PlaylistType, StreamPreferences},
+    };
+
+    fn video(height: u64, bandwidth: u64, codecs: &str) -> MediaPlaylist {
+        MediaPlaylist {
+            bandwidth: Some(bandwidth),
+            codecs: Some(codecs.to_owned()),
+            media_type: MediaType::Video,
+            resolution: Some((height * 16 / 9, height)),
+            uri: format!("{}_{}.m3u8", codecs, height),
+            ..Default::default()
+        }
+    }
+
+    fn audio(language: &str, channels: f32) -> MediaPlaylist {
+        MediaPlaylist {
+            channels: Some(channels),
+            language: Some(language.to_owned()),
+            media_type: MediaType::Audio,
+            uri: format!("audio_{}_{}.m3u8", language, channels),
+            ..Default::default()
+        }
+    }
+
+    fn subtitles(language: &str, forced: bool) -> MediaPlaylist {
+        MediaPlaylist {
+            forced,
+            language: Some(language.to_owned()),
+            media_type: MediaType::Subtitles,
+            uri: format!("subs_{}_{}.m3u8", language, forced),
+            ..Default::default()
+        }
+    }
+
+    fn select(filter: &str, streams: &[MediaPlaylist]) -> Vec<usize> {
+        filter.parse::<StreamFilter>().unwrap().select(streams)
+    }
+
+    fn error(filter: &str) -> String {
+        filter.parse::<StreamFilter>().unwrap_err()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let streams = [audio("en", 2.0), audio("fr", 6.0), audio("de", 6.0)];
+
+        assert_eq!(select("lang=en | lang=fr & channels>=6", &streams), [0, 1]);
+        assert_eq!(select("(lang=en | lang=fr) & channels>=6", &streams), [1]);
+    }
+
+    #[test]
+    fn not() {
+        let streams = [subtitles("en", false), subtitles("en", true)];
+
+        assert_eq!(select("!forced", &streams), [0]);
+        assert_eq!(select("!!forced", &streams), [1]);
+        assert_eq!(select("!(forced | lang=en)", &streams), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn fallback() {
+        let streams = [video(720, 3_000_000, "avc1.64001f")];
+
+        assert_eq!(select("codecs^=hvc1 ; height>=720", &streams), [0]);
+        assert_eq!(
+            select("codecs^=hvc1 ; height>=1080", &streams),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn missing_fields() {
+        let streams = [audio("en", 2.0), MediaPlaylist::default()];
+
+        assert_eq!(select("channels!=2", &streams), [1]);
+        assert_eq!(select("lang!=en", &streams), [1]);
+        assert_eq!(select("channels<6", &streams), [0]);
+    }
+
+    #[test]
+    fn number_suffixes() {
+        let streams = [
+            video(720, 3_000_000, "avc1.64001f"),
+            video(1080, 9_000_000, "avc1.640028"),
+        ];
+
+        assert_eq!(select("bandwidth<8M", &streams), [0]);
+        assert_eq!(select("bandwidth<8Mbps", &streams), [0]);
+        assert_eq!(select("bitrate>=3000k", &streams), [0, 1]);
+        assert_eq!(select("height>=720p", &streams), [0, 1]);
+        assert_eq!(select("height=1080p", &streams), [1]);
+    }
+
+    #[test]
+    fn language_prefix() {
+        let streams = [audio("en-US", 2.0), audio("eng", 2.0), audio("EN", 2.0)];
+
+        assert_eq!(select("lang=en", &streams), [0, 2]);
+        assert_eq!(select("lang=en-us", &streams), [0]);
+        assert_eq!(select("lang^=en", &streams), [0, 1, 2]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("foo=1"), "unknown field 'foo' in filter expression.");
+        assert_eq!(error("(lang=en"), "missing ')' in filter expression.");
+        assert_eq!(error("lang=en &"), "incomplete filter expression.");
+        assert_eq!(error("lang=en )"), "unexpected ')' in filter expression.");
+        assert_eq!(
+            error("height"),
+            "expected comparison operator after 'height'."
+        );
+        assert_eq!(
+            error("height>="),
+            "expected value after 'height' comparison."
+        );
+        assert_eq!(error("height>=abc"), "'abc' is not a number.");
+        assert_eq!(
+            error("height~7"),
+            "'height' can only be compared with numbers."
+        );
+        assert_eq!(
+            error("lang>en"),
+            "'lang' cannot be compared with <, <=, > or >=."
+        );
+        assert_eq!(
+            error("name=\"main"),
+            "unterminated quote in filter expression."
+        );
+    }
+
+    #[test]
+    fn select_streams() {
+        let playlist = MasterPlaylist {
+            playlist_type: PlaylistType::Hls,
+            uri: "master.m3u8".to_owned(),
+            streams: vec![
+                video(480, 1_000_000, "avc1.4d401e"),
+                video(1080, 5_000_000, "avc1.640028"),
+                video(1080, 6_000_000, "hvc1.2.4.L120.B0"),
+                video(2160, 15_000_000, "hvc1.2.4.L150.B0"),
+                audio("en", 2.0),
+                audio("en", 6.0),
+                audio("fr", 6.0),
+                audio("de", 6.0),
+                subtitles("en", false),
+                subtitles("en", true),
+                subtitles("fr", false),
+            ],
+        };
+        let filters = StreamFilters {
+            audio: "lang=en,fr & channels>=6".parse().ok(),
+            other: None,
+            subs: "!forced".parse().ok(),
+            video: "height>=720 & bandwidth<8M & codecs^=hvc1 ; height>=720 & bandwidth<8M"
+                .parse()
+                .ok(),
+        };
+
+        let (streams, subtitle_streams) = playlist
+            .sort_streams(&StreamPreferences::default())
+            .select_streams(
+                Quality::Highest,
+                QualityFallback::Nearest,
+                &filters,
+                false,
+                true,
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(
+            streams.iter().map(|x| x.uri.as_str()).collect::<Vec<_>>(),
+            [
+                "hvc1.2.4.L120.B0_1080.m3u8",
+                "audio_en_6.m3u8",
+                "audio_fr_6.m3u8"
+            ]
+        );
+        assert_eq!(
+            subtitle_streams
+                .iter()
+                .map(|x| x.uri.as_str())
+                .collect::<Vec<_>>(),
+            ["subs_en_false.m3u8", "subs_fr_false.m3u8"]
+        );
+    }
+
+    #[test]
+    fn select_streams_fallback() {
+        let playlist = MasterPlaylist {
+            playlist_type: PlaylistType::Hls,
+            uri: "master.m3u8".to_owned(),
+            streams: vec![
+                video(1080, 5_000_000, "avc1.640028"),
+                video(2160, 15_000_000, "hvc1.2.4.L150.B0"),
+            ],
+        };
+        let filters = StreamFilters {
+            video: "height>=720 & bandwidth<8M & codecs^=hvc1 ; height>=720 & bandwidth<8M"
+                .parse()
+                .ok(),
+            ..Default::default()
+        };
+
+        let (streams, _) = playlist
+            .select_streams(
+                Quality::Highest,
+                QualityFallback::Nearest,
+                &filters,
+                false,
+                true,
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(
+            streams.iter().map(|x| x.uri.as_str()).collect::<Vec<_>>(),
+            ["avc1.640028_1080.m3u8"]
+        );
+    }
+}
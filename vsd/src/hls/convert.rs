@@ -0,0 +1,635 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis
+    2. https://developer.apple.com/documentation/http-live-streaming/hls-authoring-specification-for-apple-devices
+    3. https://learn.microsoft.com/en-us/playready/specifications/playready-header-specification
+
+    Encryption (`ContentProtection`) is mapped to one `EXT-X-KEY` tag per DRM system.
+    `cenc` scheme uses SAMPLE-AES-CTR method and `cbcs` scheme uses SAMPLE-AES method.
+
+    | KEYFORMAT                                     | URI                                                    |
+    | --------------------------------------------- | ------------------------------------------------------ |
+    | urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed | data:text/plain;base64,<widevine pssh box>              |
+    | com.microsoft.playready                       | data:text/plain;charset=UTF-16;base64,<playready object> |
+
+    Declared pssh boxes and playready objects are written as is. A widevine pssh box is only
+    made from key ids, when the playlist doesn't declare one.
+
+*/
+
+use crate::{
+    playlist::{Key, KeyMethod, MasterPlaylist, MediaPlaylist, MediaType, Range},
+    utils,
+};
+use m3u8_rs::{
+    AlternativeMedia, AlternativeMediaType, ByteRange, ClosedCaptionGroupId, ExtTag, InstreamId,
+    Map, MediaPlaylistType, MediaSegment, QuotedOrUnquoted, Resolution, VariantStream,
+};
+use std::collections::HashMap;
+
+const WIDEVINE_SYSTEM_ID: [u8; 16] = [
+    0xed, 0xef, 0x8b, 0xa9, 0x79, 0xd6, 0x4a, 0xce, 0xa3, 0xc8, 0x27, 0xdc, 0xd5, 0x1d, 0x21, 0xed,
+];
+
+/// HLS master playlist and its media playlists (paired with their file names).
+pub(crate) struct HlsPlaylists {
+    pub(crate) master: m3u8_rs::MasterPlaylist,
+    pub(crate) media: Vec<(String, m3u8_rs::MediaPlaylist)>,
+}
+
+/// Convert a parsed playlist into an HLS playlist set.
+/// Audio renditions are grouped by their codec, so that every variant stream
+/// is a combination of a video stream and an audio group.
+pub(crate) fn convert(playlist: &MasterPlaylist) -> HlsPlaylists {
+    let mut media = vec![];
+    let mut counts = HashMap::new();
+    let mut file_name = |prefix: &str| {
+        let count = counts.entry(prefix.to_owned()).or_insert(0);
+        *count += 1;
+        format!("{}_{}.m3u8", prefix, count)
+    };
+
+    let mut audio_groups: Vec<(String, Vec<&MediaPlaylist>)> = vec![];
+    let mut alternatives = vec![];
+
+    for stream in &playlist.streams {
+        match stream.media_type {
+            MediaType::Audio => {
+                let group_id = format!(
+                    "audio-{}",
+                    stream
+                        .codecs
+                        .as_ref()
+                        .and_then(|x| x.split('.').next())
+                        .unwrap_or("default")
+                );
+
+                if let Some(group) = audio_groups.iter_mut().find(|x| x.0 == group_id) {
+                    group.1.push(stream);
+                } else {
+                    audio_groups.push((group_id, vec![stream]));
+                }
+            }
+            MediaType::Subtitles => {
+                let uri = file_name("subtitles");
+                alternatives.push(rendition(
+                    stream,
+                    AlternativeMediaType::Subtitles,
+                    "subs",
+                    &uri,
+                    &alternatives,
+                ));
+                media.push((uri, media_playlist(stream)));
+            }
+            _ => (),
+        }
+    }
+
+    let video_streams = playlist
+        .streams
+        .iter()
+        .filter(|x| x.media_type == MediaType::Video)
+        .collect::<Vec<_>>();
+    let has_subtitles = alternatives
+        .iter()
+        .any(|x| x.media_type == AlternativeMediaType::Subtitles);
+    let mut variants = vec![];
+
+    for (group_id, streams) in &audio_groups {
+        for stream in streams {
+            let uri = file_name("audio");
+
+            if video_streams.iter().all(|x| x.i_frame) {
+                // Audio only playlists.
+                variants.push(VariantStream {
+                    uri: uri.clone(),
+                    bandwidth: stream.bandwidth.unwrap_or(0),
+                    codecs: stream.codecs.clone(),
+                    subtitles: has_subtitles.then(|| "subs".to_owned()),
+                    ..Default::default()
+                });
+            } else {
+                alternatives.push(rendition(
+                    stream,
+                    AlternativeMediaType::Audio,
+                    group_id,
+                    &uri,
+                    &alternatives,
+                ));
+            }
+
+            media.push((uri, media_playlist(stream)));
+        }
+    }
+
+    let has_closed_captions = video_streams.iter().any(|x| !x.closed_captions.is_empty());
+
+    for stream in &video_streams {
+        for closed_caption in &stream.closed_captions {
+            let Ok(instream_id) = closed_caption.channel.parse::<InstreamId>() else {
+                continue;
+            };
+
+            if alternatives.iter().any(|x| {
+                x.media_type == AlternativeMediaType::ClosedCaptions
+                    && x.instream_id.as_ref() == Some(&instream_id)
+            }) {
+                continue;
+            }
+
+            alternatives.push(AlternativeMedia {
+                media_type: AlternativeMediaType::ClosedCaptions,
+                group_id: "cc".to_owned(),
+                language: closed_caption.language.clone(),
+                name: closed_caption
+                    .name
+                    .clone()
+                    .unwrap_or(closed_caption.to_string()),
+                autoselect: true,
+                instream_id: Some(instream_id),
+                ..Default::default()
+            });
+        }
+    }
+
+    for stream in video_streams {
+        let uri = file_name(if stream.i_frame { "iframes" } else { "video" });
+        let resolution = stream
+            .resolution
+            .map(|(width, height)| Resolution { width, height });
+
+        if stream.i_frame {
+            variants.push(VariantStream {
+                is_i_frame: true,
+                uri: uri.clone(),
+                bandwidth: stream.bandwidth.unwrap_or(0),
+                codecs: stream.codecs.clone(),
+                resolution,
+                ..Default::default()
+            });
+        } else {
+            let groups = if audio_groups.is_empty() {
+                vec![None]
+            } else {
+                audio_groups.iter().map(Some).collect()
+            };
+
+            for group in groups {
+                let audio_bandwidth = group
+                    .and_then(|x| x.1.iter().filter_map(|x| x.bandwidth).max())
+                    .unwrap_or(0);
+                let codecs = [
+                    stream.codecs.as_ref(),
+                    group.and_then(|x| x.1.iter().find_map(|x| x.codecs.as_ref())),
+                ]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>();
+
+                variants.push(VariantStream {
+                    uri: uri.clone(),
+                    bandwidth: stream.bandwidth.unwrap_or(0) + audio_bandwidth,
+                    codecs: (!codecs.is_empty()).then(|| codecs.join(",")),
+                    resolution,
+                    frame_rate: stream.frame_rate.map(|x| x as f64),
+                    audio: group.map(|x| x.0.clone()),
+                    subtitles: has_subtitles.then(|| "subs".to_owned()),
+                    closed_captions: if stream.closed_captions.is_empty() {
+                        has_closed_captions.then_some(ClosedCaptionGroupId::None)
+                    } else {
+                        Some(ClosedCaptionGroupId::GroupId("cc".to_owned()))
+                    },
+                    other_attributes: stream.video_range.as_ref().map(|x| {
+                        HashMap::from([(
+                            "VIDEO-RANGE".to_owned(),
+                            QuotedOrUnquoted::Unquoted(x.to_owned()),
+                        )])
+                    }),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let mut media_playlist = media_playlist(stream);
+        media_playlist.i_frames_only = stream.i_frame;
+        media.push((uri, media_playlist));
+    }
+
+    HlsPlaylists {
+        master: m3u8_rs::MasterPlaylist {
+            version: Some(6),
+            variants,
+            independent_segments: true,
+            alternatives,
+            ..Default::default()
+        },
+        media,
+    }
+}
+
+fn rendition(
+    stream: &MediaPlaylist,
+    media_type: AlternativeMediaType,
+    group_id: &str,
+    uri: &str,
+    alternatives: &[AlternativeMedia],
+) -> AlternativeMedia {
+    let mut name = stream
+        .name
+        .clone()
+        .or(stream.language.clone())
+        .unwrap_or(media_type.to_string().to_lowercase());
+
+    // All renditions of a group must have different names.
+    let base_name = name.clone();
+    let mut count = 1;
+
+    while alternatives
+        .iter()
+        .any(|x| x.group_id == group_id && x.name == name)
+    {
+        count += 1;
+        name = format!("{} ({})", base_name, count);
+    }
+
+    let mut characteristics = vec![];
+
+    if stream.sdh {
+        characteristics.push("public.accessibility.transcribes-spoken-dialog");
+        characteristics.push("public.accessibility.describes-music-and-sound");
+    }
+
+    if stream.audio_description {
+        characteristics.push("public.accessibility.describes-video");
+    }
+
+    let is_first = !alternatives.iter().any(|x| x.group_id == group_id);
+
+    AlternativeMedia {
+        media_type,
+        uri: Some(uri.to_owned()),
+        group_id: group_id.to_owned(),
+        language: stream.language.clone(),
+        name,
+        default: stream.default || (is_first && !stream.forced && !stream.is_supplementary()),
+        autoselect: !stream.forced,
+        forced: stream.forced,
+        characteristics: (!characteristics.is_empty()).then(|| characteristics.join(",")),
+        channels: stream.channels.map(|x| x.to_string()),
+        ..Default::default()
+    }
+}
+
+fn media_playlist(stream: &MediaPlaylist) -> m3u8_rs::MediaPlaylist {
+    let mut segments = vec![];
+    let mut previous_discontinuity_sequence = None;
+    let mut previous_keys = vec![];
+    let mut previous_map = None;
+
+    for segment in &stream.segments {
+        let mut unknown_tags = vec![];
+        let segment_keys = segment.key.as_ref().map(keys).unwrap_or_default();
+
+        // Keys are only written when they change.
+        if segment_keys != previous_keys {
+            unknown_tags.extend(segment_keys.iter().map(|x| ExtTag {
+                tag: "X-KEY".to_owned(),
+                rest: Some(x.to_owned()),
+            }));
+            previous_keys = segment_keys;
+        }
+
+        // Init segments are also only written when they change.
+        let map = segment
+            .map
+            .as_ref()
+            .map(|x| (x.uri.clone(), x.range.as_ref().map(|x| (x.start, x.end))));
+        let map = if map != previous_map {
+            previous_map = map;
+            segment.map.as_ref().map(|x| Map {
+                uri: x.uri.clone(),
+                byte_range: x.range.as_ref().and_then(byte_range),
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+
+        if segment.gap {
+            unknown_tags.push(ExtTag {
+                tag: "X-GAP".to_owned(),
+                rest: None,
+            });
+        }
+
+        segments.push(MediaSegment {
+            uri: segment.uri.clone(),
+            duration: segment.duration,
+            byte_range: segment.range.as_ref().and_then(byte_range),
+            discontinuity: previous_discontinuity_sequence
+                .is_some_and(|x| x != segment.discontinuity_sequence),
+            map,
+            unknown_tags,
+            ..Default::default()
+        });
+
+        previous_discontinuity_sequence = Some(segment.discontinuity_sequence);
+    }
+
+    m3u8_rs::MediaPlaylist {
+        version: Some(6),
+        target_duration: stream
+            .segments
+            .iter()
+            .map(|x| x.duration.ceil() as u64)
+            .max()
+            .unwrap_or(0),
+        segments,
+        end_list: !stream.live,
+        playlist_type: (!stream.live).then_some(MediaPlaylistType::Vod),
+        ..Default::default()
+    }
+}
+
+/// Open ended byte ranges cannot be expressed in HLS playlists.
+fn byte_range(range: &Range) -> Option<ByteRange> {
+    (range.end != u64::MAX).then(|| ByteRange {
+        length: range.end - range.start + 1,
+        offset: Some(range.start),
+    })
+}
+
+/// Attribute lists of `EXT-X-KEY` tags of a key.
+fn keys(key: &Key) -> Vec<String> {
+    let mut tags = vec![];
+
+    match &key.method {
+        KeyMethod::None => tags.push("METHOD=NONE".to_owned()),
+        KeyMethod::Aes128 | KeyMethod::SampleAes | KeyMethod::Other(_) => {
+            let mut tag = format!(
+                "METHOD={}",
+                match &key.method {
+                    KeyMethod::Aes128 => "AES-128",
+                    KeyMethod::Other(x) => x,
+                    _ => "SAMPLE-AES",
+                }
+            );
+
+            if let Some(uri) = &key.uri {
+                tag += &format!(",URI=\"{}\"", uri);
+            }
+
+            if let Some(iv) = &key.iv {
+                tag += &format!(",IV={}", iv);
+            }
+
+            if let Some(key_format) = &key.key_format {
+                tag += &format!(",KEYFORMAT=\"{}\"", key_format);
+            }
+
+            tags.push(tag);
+        }
+        KeyMethod::Cenc => {
+            let method = if key.scheme.as_deref() == Some("cbcs") {
+                "SAMPLE-AES"
+            } else {
+                "SAMPLE-AES-CTR"
+            };
+
+            // Key ids of PlayReady objects are stored as little endian guids, so only
+            // default key id and key ids of other systems are used.
+            let mut kids = vec![];
+
+            for kid in key.default_kid.iter().chain(
+                key.key_ids
+                    .iter()
+                    .filter(|x| x.system != "playready")
+                    .map(|x| &x.value),
+            ) {
+                if let Some(kid) = hex::decode(kid.replace('-', ""))
+                    .ok()
+                    .and_then(|x| <[u8; 16]>::try_from(x).ok())
+                {
+                    if !kids.contains(&kid) {
+                        kids.push(kid);
+                    }
+                }
+            }
+
+            let widevine_psshs = key
+                .psshs
+                .iter()
+                .filter(|x| x.0 == "widevine")
+                .map(|x| x.1.clone())
+                .collect::<Vec<_>>();
+
+            // Without a declared pssh box, one is made from key ids.
+            let widevine_psshs = if !widevine_psshs.is_empty() {
+                widevine_psshs
+            } else if !kids.is_empty() {
+                vec![widevine_pssh(&kids)]
+            } else {
+                vec![]
+            };
+
+            for pssh in widevine_psshs {
+                tags.push(format!(
+                    "METHOD={},URI=\"data:text/plain;base64,{}\"{},KEYFORMAT=\"urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed\",KEYFORMATVERSIONS=\"1\"",
+                    method,
+                    utils::encode_base64(pssh),
+                    kids.first()
+                        .map(|x| format!(",KEYID=0x{}", hex::encode_upper(x)))
+                        .unwrap_or_default()
+                ));
+            }
+
+            for (_, object) in key.psshs.iter().filter(|x| x.0 == "playready") {
+                tags.push(format!(
+                    "METHOD={},URI=\"data:text/plain;charset=UTF-16;base64,{}\",KEYFORMAT=\"com.microsoft.playready\",KEYFORMATVERSIONS=\"1\"",
+                    method,
+                    utils::encode_base64(object)
+                ));
+            }
+        }
+    }
+
+    tags
+}
+
+/// Widevine `pssh` box, its data only contains key ids (protobuf field 2).
+fn widevine_pssh(kids: &[[u8; 16]]) -> Vec<u8> {
+    let data = kids
+        .iter()
+        .flat_map(|x| [&[0x12, 0x10][..], x].concat())
+        .collect::<Vec<_>>();
+
+    let mut pssh = vec![];
+    pssh.extend((32 + data.len() as u32).to_be_bytes());
+    pssh.extend(b"pssh");
+    pssh.extend([0; 4]); // version and flags
+    pssh.extend(WIDEVINE_SYSTEM_ID);
+    pssh.extend((data.len() as u32).to_be_bytes());
+    pssh.extend(data);
+    pssh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::convert;
+    use crate::playlist::{
+        Key, KeyId, KeyMethod, Map, MasterPlaylist, MediaPlaylist, MediaType, PlaylistType, Range,
+        Segment,
+    };
+
+    fn segments(key: Option<Key>) -> Vec<Segment> {
+        (0..2)
+            .map(|i| Segment {
+                duration: 4.0,
+                key: key.clone(),
+                map: Some(Map {
+                    uri: "media.mp4".to_owned(),
+                    range: Some(Range { start: 0, end: 99 }),
+                }),
+                range: Some(Range {
+                    start: 100 + i * 1000,
+                    end: 1099 + i * 1000,
+                }),
+                uri: "media.mp4".to_owned(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn audio(language: &str) -> MediaPlaylist {
+        MediaPlaylist {
+            bandwidth: Some(128000),
+            channels: Some(2.0),
+            codecs: Some("mp4a.40.2".to_owned()),
+            language: Some(language.to_owned()),
+            media_type: MediaType::Audio,
+            segments: segments(None),
+            ..Default::default()
+        }
+    }
+
+    fn write(playlist: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>) -> String {
+        let mut data = vec![];
+        playlist(&mut data).unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn golden() {
+        // Version 0 widevine pssh box with a content id (protobuf field 4).
+        let widevine_pssh = [
+            &[0, 0, 0, 0x26][..],
+            b"pssh",
+            &[0, 0, 0, 0],
+            &[
+                0xed, 0xef, 0x8b, 0xa9, 0x79, 0xd6, 0x4a, 0xce, 0xa3, 0xc8, 0x27, 0xdc, 0xd5, 0x1d,
+                0x21, 0xed,
+            ],
+            &[0, 0, 0, 6, 0x22, 0x04],
+            b"vsd!",
+        ]
+        .concat();
+        let key = Key {
+            default_kid: Some("9eb4050d-e44b-4802-932e-27d75083e266".to_owned()),
+            iv: None,
+            key_format: None,
+            key_ids: vec![KeyId {
+                system: "playready".to_owned(),
+                value: "0d05b49e4be40248932e27d75083e266".to_owned(),
+            }],
+            method: KeyMethod::Cenc,
+            psshs: vec![
+                ("widevine".to_owned(), widevine_pssh),
+                ("playready".to_owned(), b"<WRMHEADER/>".to_vec()),
+            ],
+            scheme: Some("cenc".to_owned()),
+            uri: None,
+        };
+        let playlist = MasterPlaylist {
+            playlist_type: PlaylistType::Dash,
+            uri: "https://example.com/manifest.mpd".to_owned(),
+            streams: vec![
+                MediaPlaylist {
+                    bandwidth: Some(2000000),
+                    codecs: Some("avc1.64001f".to_owned()),
+                    frame_rate: Some(25.0),
+                    media_type: MediaType::Video,
+                    resolution: Some((1280, 720)),
+                    segments: segments(Some(key)),
+                    ..Default::default()
+                },
+                audio("en"),
+                audio("eng"),
+                audio("en"),
+                MediaPlaylist {
+                    language: Some("en".to_owned()),
+                    media_type: MediaType::Subtitles,
+                    segments: vec![Segment {
+                        duration: 8.0,
+                        uri: "subtitles.vtt".to_owned(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let playlists = convert(&playlist);
+
+        assert_eq!(
+            write(|x| playlists.master.write_to(x)),
+            r#"#EXTM3U
+#EXT-X-VERSION:6
+#EXT-X-INDEPENDENT-SEGMENTS
+#EXT-X-MEDIA:TYPE=SUBTITLES,URI="subtitles_1.m3u8",GROUP-ID="subs",LANGUAGE="en",NAME="en",DEFAULT=YES,AUTOSELECT=YES
+#EXT-X-MEDIA:TYPE=AUDIO,URI="audio_1.m3u8",GROUP-ID="audio-mp4a",LANGUAGE="en",NAME="en",DEFAULT=YES,AUTOSELECT=YES,CHANNELS="2"
+#EXT-X-MEDIA:TYPE=AUDIO,URI="audio_2.m3u8",GROUP-ID="audio-mp4a",LANGUAGE="eng",NAME="eng",AUTOSELECT=YES,CHANNELS="2"
+#EXT-X-MEDIA:TYPE=AUDIO,URI="audio_3.m3u8",GROUP-ID="audio-mp4a",LANGUAGE="en",NAME="en (2)",AUTOSELECT=YES,CHANNELS="2"
+#EXT-X-STREAM-INF:BANDWIDTH=2128000,CODECS="avc1.64001f,mp4a.40.2",RESOLUTION=1280x720,FRAME-RATE=25,AUDIO="audio-mp4a",SUBTITLES="subs"
+video_1.m3u8
+"#
+        );
+
+        let names = playlists
+            .media
+            .iter()
+            .map(|x| x.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "subtitles_1.m3u8",
+                "audio_1.m3u8",
+                "audio_2.m3u8",
+                "audio_3.m3u8",
+                "video_1.m3u8"
+            ]
+        );
+
+        assert_eq!(
+            write(|x| playlists.media[4].1.write_to(x)),
+            r#"#EXTM3U
+#EXT-X-VERSION:6
+#EXT-X-TARGETDURATION:4
+#EXT-X-PLAYLIST-TYPE:VOD
+#EXT-X-MAP:URI="media.mp4",BYTERANGE="100@0"
+#EXT-X-BYTERANGE:1000@100
+#EXT-X-KEY:METHOD=SAMPLE-AES-CTR,URI="data:text/plain;base64,AAAAJnBzc2gAAAAA7e+LqXnWSs6jyCfc1R0h7QAAAAYiBHZzZCE=",KEYID=0x9EB4050DE44B4802932E27D75083E266,KEYFORMAT="urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed",KEYFORMATVERSIONS="1"
+#EXT-X-KEY:METHOD=SAMPLE-AES-CTR,URI="data:text/plain;charset=UTF-16;base64,PFdSTUhFQURFUi8+",KEYFORMAT="com.microsoft.playready",KEYFORMATVERSIONS="1"
+#EXTINF:4,
+media.mp4
+#EXT-X-BYTERANGE:1000@1100
+#EXTINF:4,
+media.mp4
+#EXT-X-ENDLIST
+"#
+        );
+    }
+}
@@ -0,0 +1,209 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.3.7
+    2. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.3.8
+    3. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.5.3
+    4. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-6.2.5.2
+
+*/
+
+use super::attributes;
+use crate::playlist::Range;
+
+/// Low-latency extensions of a media playlist.
+///
+/// `m3u8-rs` drops tags which appear after the last segment uri (partial segments
+/// of the segment being currently produced and preload hints), so these tags are
+/// scanned directly from the playlist text.
+#[derive(Default)]
+pub(crate) struct LowLatency {
+    pub(crate) can_block_reload: bool,
+    pub(crate) part_target: Option<f32>,
+    pub(crate) parts: Vec<Part>,
+    pub(crate) preload_hint: Option<Part>,
+}
+
+/// `EXT-X-PART` or `EXT-X-PRELOAD-HINT` (TYPE=PART) tag.
+pub(crate) struct Part {
+    pub(crate) gap: bool,
+    pub(crate) independent: bool,
+    /// Index of part within its parent segment.
+    pub(crate) index: u64,
+    /// Media sequence number of parent segment.
+    pub(crate) msn: u64,
+    pub(crate) range: Option<Range>,
+    pub(crate) uri: String,
+}
+
+impl LowLatency {
+    pub(crate) fn parse(text: &str) -> Self {
+        let mut low_latency = Self::default();
+        let mut msn = 0;
+        let mut index = 0;
+        let mut previous_range_end = 0;
+
+        for line in text.lines().map(|x| x.trim()) {
+            if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+                msn = value.parse().unwrap_or(0);
+            } else if let Some(value) = line.strip_prefix("#EXT-X-SERVER-CONTROL:") {
+                low_latency.can_block_reload = attributes(value)
+                    .get("CAN-BLOCK-RELOAD")
+                    .map(|x| x.as_str())
+                    == Some("YES");
+            } else if let Some(value) = line.strip_prefix("#EXT-X-PART-INF:") {
+                low_latency.part_target = attributes(value)
+                    .get("PART-TARGET")
+                    .and_then(|x| x.parse().ok());
+            } else if let Some(value) = line.strip_prefix("#EXT-X-PART:") {
+                let attributes = attributes(value);
+
+                if let Some(uri) = attributes.get("URI") {
+                    let byte_range =
+                        attributes
+                            .get("BYTERANGE")
+                            .and_then(|x| match x.split_once('@') {
+                                Some((length, offset)) => {
+                                    Some((length.parse::<u64>().ok()?, offset.parse().ok()?))
+                                }
+                                None => Some((x.parse::<u64>().ok()?, previous_range_end)),
+                            });
+
+                    // A part with an empty byte range has nothing to download, but it still
+                    // takes up an index within its parent segment.
+                    if let Some((0, _)) = byte_range {
+                        index += 1;
+                        continue;
+                    }
+
+                    let range = byte_range.map(|(length, offset)| {
+                        previous_range_end = offset + length;
+                        Range {
+                            start: offset,
+                            end: offset + length - 1,
+                        }
+                    });
+
+                    low_latency.parts.push(Part {
+                        gap: attributes.get("GAP").map(|x| x.as_str()) == Some("YES"),
+                        independent: attributes.get("INDEPENDENT").map(|x| x.as_str())
+                            == Some("YES"),
+                        index,
+                        msn,
+                        range,
+                        uri: uri.to_owned(),
+                    });
+                    index += 1;
+                }
+            } else if let Some(value) = line.strip_prefix("#EXT-X-PRELOAD-HINT:") {
+                let attributes = attributes(value);
+
+                if let (Some("PART"), Some(uri)) = (
+                    attributes.get("TYPE").map(|x| x.as_str()),
+                    attributes.get("URI"),
+                ) {
+                    let start = attributes
+                        .get("BYTERANGE-START")
+                        .and_then(|x| x.parse::<u64>().ok());
+                    let length = attributes
+                        .get("BYTERANGE-LENGTH")
+                        .and_then(|x| x.parse::<u64>().ok());
+
+                    if length == Some(0) {
+                        continue;
+                    }
+
+                    low_latency.preload_hint = Some(Part {
+                        gap: false,
+                        independent: false,
+                        index,
+                        msn,
+                        // Open ended byte range requests are handled by server as soon as data is available.
+                        range: start.map(|start| Range {
+                            start,
+                            end: length.map(|x| start + x - 1).unwrap_or(u64::MAX),
+                        }),
+                        uri: uri.to_owned(),
+                    });
+                }
+            } else if !line.is_empty() && !line.starts_with('#') {
+                msn += 1;
+                index = 0;
+            }
+        }
+
+        low_latency
+    }
+
+    /// Parts of a segment which are available in playlist.
+    pub(crate) fn parts_of(&self, msn: u64) -> impl Iterator<Item = &Part> {
+        self.parts.iter().filter(move |x| x.msn == msn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LowLatency;
+
+    #[test]
+    fn parts() {
+        let low_latency = LowLatency::parse(
+            "#EXTM3U
+#EXT-X-MEDIA-SEQUENCE:10
+#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=1.0
+#EXT-X-PART-INF:PART-TARGET=0.33334
+#EXTINF:1.0,
+seg10.mp4
+#EXT-X-PART:DURATION=0.33334,URI=\"seg11.mp4\",BYTERANGE=100@0,INDEPENDENT=YES
+#EXT-X-PART:DURATION=0.33334,URI=\"seg11.mp4\",BYTERANGE=50
+#EXT-X-PART:DURATION=0.33334,URI=\"seg11.mp4\",GAP=YES
+#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"seg11.mp4\",BYTERANGE-START=150
+",
+        );
+
+        assert!(low_latency.can_block_reload);
+        assert_eq!(low_latency.part_target, Some(0.33334));
+        assert_eq!(low_latency.parts_of(10).count(), 0);
+
+        let parts = low_latency.parts_of(11).collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+        assert!(parts[0].independent);
+        assert_eq!(
+            parts[0].range.as_ref().map(|x| (x.start, x.end)),
+            Some((0, 99))
+        );
+        assert_eq!(
+            parts[1].range.as_ref().map(|x| (x.start, x.end)),
+            Some((100, 149))
+        );
+        assert!(parts[2].gap);
+        assert!(parts[2].range.is_none());
+
+        let hint = low_latency.preload_hint.unwrap();
+        assert_eq!((hint.msn, hint.index), (11, 3));
+        assert_eq!(hint.range.map(|x| (x.start, x.end)), Some((150, u64::MAX)));
+    }
+
+    #[test]
+    fn zero_length_byte_ranges() {
+        let low_latency = LowLatency::parse(
+            "#EXTM3U
+#EXT-X-MEDIA-SEQUENCE:0
+#EXT-X-PART:DURATION=0.5,URI=\"seg0.mp4\",BYTERANGE=0@0
+#EXT-X-PART:DURATION=0.5,URI=\"seg0.mp4\",BYTERANGE=100@0
+#EXT-X-PART:DURATION=0.5,URI=\"seg0.mp4\",BYTERANGE=0
+#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"seg0.mp4\",BYTERANGE-START=100,BYTERANGE-LENGTH=0
+",
+        );
+
+        let parts = low_latency.parts_of(0).collect::<Vec<_>>();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].index, 1);
+        assert_eq!(
+            parts[0].range.as_ref().map(|x| (x.start, x.end)),
+            Some((0, 99))
+        );
+        assert!(low_latency.preload_hint.is_none());
+    }
+}
@@ -1,3 +1,50 @@
+mod convert;
+mod low_latency;
 mod playlist;
+mod steering;
+mod variables;
 
+pub(crate) use convert::convert;
+pub(crate) use low_latency::LowLatency;
 pub(crate) use playlist::{parse_as_master, push_segments};
+pub(crate) use steering::steer;
+pub(crate) use variables::substitute_variables;
+
+use std::collections::HashMap;
+
+/// Parse attribute list (`KEY=VALUE,KEY="QUOTED,VALUE"`) of a playlist tag.
+/// Quotes are removed from quoted values.
+fn attributes(text: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = text.trim();
+
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_owned();
+
+        let (value, remaining) = if let Some(value) = value.strip_prefix('"') {
+            match value.split_once('"') {
+                Some((value, remaining)) => (value, remaining),
+                None => (value, ""),
+            }
+        } else {
+            match value.split_once(',') {
+                Some((value, remaining)) => (value, remaining),
+                None => (value, ""),
+            }
+        };
+
+        attributes.insert(key, value.trim().to_owned());
+        rest = remaining.trim_start_matches(',');
+    }
+
+    attributes
+}
+
+/// `PATHWAY-ID` attribute of a variant stream.
+fn pathway_id(variant: &m3u8_rs::VariantStream) -> Option<String> {
+    variant
+        .other_attributes
+        .as_ref()
+        .and_then(|x| x.get("PATHWAY-ID"))
+        .map(|x| x.as_str().to_owned())
+}
@@ -1,22 +1,90 @@
+use super::pathway_id;
 use crate::playlist;
+use std::collections::HashMap;
 
 pub(crate) fn parse_as_master(
     m3u8: &m3u8_rs::MasterPlaylist,
     uri: &str,
 ) -> playlist::MasterPlaylist {
-    let mut streams = vec![];
+    let mut streams: Vec<playlist::MediaPlaylist> = vec![];
+    // Variant streams which differ only by uri and pathway are redundant copies of each other.
+    let mut variants: Vec<(m3u8_rs::VariantStream, usize)> = vec![];
+    // Rendition groups of redundant variant streams mapped to (primary group, pathway).
+    let mut redundant_groups = HashMap::new();
 
     for video_stream in &m3u8.variants {
+        let pathway_id = pathway_id(video_stream);
+        let variant = m3u8_rs::VariantStream {
+            uri: String::new(),
+            audio: None,
+            subtitles: None,
+            closed_captions: None,
+            other_attributes: video_stream
+                .other_attributes
+                .as_ref()
+                .map(|x| {
+                    x.iter()
+                        .filter(|(key, _)| key.as_str() != "PATHWAY-ID")
+                        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                        .collect::<HashMap<_, _>>()
+                })
+                .filter(|x| !x.is_empty()),
+            ..video_stream.clone()
+        };
+
+        if let Some((_, index)) = variants.iter().find(|x| x.0 == variant) {
+            let stream = &mut streams[*index];
+
+            for (group_id, primary_group_id) in [
+                (&video_stream.audio, &stream.audio_group),
+                (&video_stream.subtitles, &stream.subtitles_group),
+            ] {
+                if let (Some(group_id), Some(primary_group_id)) = (group_id, primary_group_id) {
+                    if group_id != primary_group_id {
+                        redundant_groups.insert(
+                            group_id.to_owned(),
+                            (primary_group_id.to_owned(), pathway_id.clone()),
+                        );
+                    }
+                }
+            }
+
+            if stream.uri != video_stream.uri
+                && stream.pathways.iter().all(|x| x.uri != video_stream.uri)
+            {
+                stream.pathways.push(playlist::Pathway {
+                    id: pathway_id,
+                    uri: video_stream.uri.to_owned(),
+                });
+            }
+
+            continue;
+        }
+
+        variants.push((variant, streams.len()));
         streams.push(playlist::MediaPlaylist {
+            audio_description: false,
+            audio_group: video_stream.audio.to_owned(),
+            autoselect: false,
             bandwidth: Some(video_stream.bandwidth),
             channels: None,
+            closed_captions: closed_captions(m3u8, &video_stream.closed_captions),
             codecs: video_stream.codecs.to_owned(),
+            default: false,
+            events: vec![],
             extension: Some("ts".to_owned()), // Cannot be comment here
+            forced: false,
             frame_rate: video_stream.frame_rate.map(|x| x as f32),
+            group_id: None,
             i_frame: video_stream.is_i_frame,
+            inband_events: None,
+            key: None,
             language: None,
             live: false, // Cannot be comment here
             media_type: playlist::MediaType::Video,
+            name: None,
+            pathway_id,
+            pathways: vec![],
             playlist_type: playlist::PlaylistType::Hls,
             resolution: if let Some(m3u8_rs::Resolution { width, height }) = video_stream.resolution
             {
@@ -24,96 +92,131 @@ pub(crate) fn parse_as_master(
             } else {
                 None
             },
+            roles: vec![],
+            sdh: false,
             segments: vec![], // Cannot be comment here
+            start_offset: m3u8.start.as_ref().map(|x| x.time_offset as f32),
+            subtitles_group: video_stream.subtitles.to_owned(),
+            tiles: None,
             uri: video_stream.uri.to_owned(),
+            variables: HashMap::new(),
+            video_range: video_stream
+                .other_attributes
+                .as_ref()
+                .and_then(|x| x.get("VIDEO-RANGE"))
+                .map(|x| x.as_str().to_owned()),
         });
     }
 
-    for alternative_stream in &m3u8.alternatives {
+    // Renditions of primary groups are parsed first so that redundant ones can be attached to them.
+    let mut alternatives = m3u8.alternatives.iter().collect::<Vec<_>>();
+    alternatives.sort_by_key(|x| redundant_groups.contains_key(&x.group_id));
+
+    for alternative_stream in alternatives {
         if let Some(uri) = &alternative_stream.uri {
-            match alternative_stream.media_type {
-                m3u8_rs::AlternativeMediaType::Video => streams.push(playlist::MediaPlaylist {
-                    bandwidth: None, // Cannot be comment here
-                    channels: None,
-                    codecs: None,                     // Cannot be comment here
-                    extension: Some("ts".to_owned()), // Cannot be comment here
-                    frame_rate: None,                 // Cannot be comment here
-                    i_frame: false,                   // Cannot be comment here
-                    language: None,
-                    live: false, // Cannot be comment here
-                    media_type: playlist::MediaType::Video,
-                    playlist_type: playlist::PlaylistType::Hls,
-                    resolution: None, // Cannot be comment here
-                    segments: vec![], // Cannot be comment here
-                    uri: uri.to_owned(),
-                }),
-
-                m3u8_rs::AlternativeMediaType::Audio => streams.push(playlist::MediaPlaylist {
-                    bandwidth: None, // Cannot be comment here
-                    channels: alternative_stream
-                        .channels
-                        .as_ref()
-                        .map(|x| x.parse::<f32>().unwrap()),
-                    codecs: None,                     // Cannot be comment here
-                    extension: Some("ts".to_owned()), // Cannot be comment here
-                    frame_rate: None,
-                    i_frame: false,
-                    language: alternative_stream
-                        .language
-                        .to_owned()
-                        .or(alternative_stream.assoc_language.to_owned()),
-                    live: false, // Cannot be comment here
-                    media_type: playlist::MediaType::Audio,
-                    playlist_type: playlist::PlaylistType::Hls,
-                    resolution: None,
-                    segments: vec![], // Cannot be comment here
-                    uri: uri.to_owned(),
-                }),
-
-                m3u8_rs::AlternativeMediaType::ClosedCaptions
-                | m3u8_rs::AlternativeMediaType::Subtitles => {
-                    streams.push(playlist::MediaPlaylist {
-                        bandwidth: None,
-                        channels: None,
-                        codecs: None,                      // Cannot be comment here
-                        extension: Some("vtt".to_owned()), // Cannot be comment here
-                        frame_rate: None,
-                        i_frame: false,
-                        language: alternative_stream
-                            .language
-                            .to_owned()
-                            .or(alternative_stream.assoc_language.to_owned()),
-                        live: false, // Cannot be comment here
-                        media_type: playlist::MediaType::Subtitles,
-                        playlist_type: playlist::PlaylistType::Hls,
-                        resolution: None,
-                        segments: vec![], // Cannot be comment here
-                        uri: uri.to_owned(),
-                    })
+            if let Some((primary_group_id, pathway_id)) =
+                redundant_groups.get(&alternative_stream.group_id)
+            {
+                if let Some(stream) = streams.iter_mut().find(|x| {
+                    x.group_id.as_ref() == Some(primary_group_id)
+                        && x.name.as_ref() == Some(&alternative_stream.name)
+                }) {
+                    if &stream.uri != uri && stream.pathways.iter().all(|x| &x.uri != uri) {
+                        stream.pathways.push(playlist::Pathway {
+                            id: pathway_id.to_owned(),
+                            uri: uri.to_owned(),
+                        });
+                    }
+
+                    continue;
                 }
+            }
 
-                m3u8_rs::AlternativeMediaType::Other(_) => streams.push(playlist::MediaPlaylist {
-                    bandwidth: None,
-                    channels: alternative_stream
+            let (channels, extension, media_type) = match alternative_stream.media_type {
+                m3u8_rs::AlternativeMediaType::Video => {
+                    (None, Some("ts".to_owned()), playlist::MediaType::Video)
+                }
+                m3u8_rs::AlternativeMediaType::Audio => (
+                    alternative_stream
                         .channels
-                        .as_ref()
-                        .map(|x| x.parse::<f32>().unwrap()),
-                    codecs: None,     // Cannot be comment here
-                    extension: None,  // Cannot be comment here
-                    frame_rate: None, // Cannot be comment here
-                    i_frame: false,   // Cannot be comment here
-                    language: alternative_stream
+                        .as_deref()
+                        .and_then(parse_channels),
+                    Some("ts".to_owned()),
+                    playlist::MediaType::Audio,
+                ),
+                // Closed captions are carried inside video streams.
+                m3u8_rs::AlternativeMediaType::ClosedCaptions => continue,
+                m3u8_rs::AlternativeMediaType::Subtitles => {
+                    (None, Some("vtt".to_owned()), playlist::MediaType::Subtitles)
+                }
+                m3u8_rs::AlternativeMediaType::Other(_) => (
+                    alternative_stream
+                        .channels
+                        .as_deref()
+                        .and_then(parse_channels),
+                    None,
+                    playlist::MediaType::Undefined,
+                ),
+            };
+
+            let characteristics = alternative_stream
+                .characteristics
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(|x| x.trim())
+                .collect::<Vec<_>>();
+
+            streams.push(playlist::MediaPlaylist {
+                audio_description: characteristics
+                    .contains(&"public.accessibility.describes-video"),
+                audio_group: None,
+                autoselect: alternative_stream.autoselect,
+                bandwidth: None, // Cannot be comment here
+                channels,
+                closed_captions: vec![],
+                codecs: None, // Cannot be comment here
+                default: alternative_stream.default,
+                events: vec![],
+                extension,
+                forced: alternative_stream.forced,
+                frame_rate: None, // Cannot be comment here
+                group_id: Some(alternative_stream.group_id.to_owned()),
+                i_frame: false, // Cannot be comment here
+                inband_events: None,
+                key: None,
+                language: if media_type == playlist::MediaType::Video {
+                    None
+                } else {
+                    alternative_stream
                         .language
                         .to_owned()
-                        .or(alternative_stream.assoc_language.to_owned()),
-                    live: false, // Cannot be comment here
-                    media_type: playlist::MediaType::Undefined,
-                    playlist_type: playlist::PlaylistType::Hls,
-                    resolution: None, // Cannot be comment here
-                    segments: vec![], // Cannot be comment here
-                    uri: uri.to_owned(),
-                }),
-            }
+                        .or(alternative_stream.assoc_language.to_owned())
+                },
+                live: false, // Cannot be comment here
+                media_type,
+                name: Some(alternative_stream.name.to_owned()),
+                pathway_id: m3u8
+                    .variants
+                    .iter()
+                    .find(|x| {
+                        x.audio.as_ref() == Some(&alternative_stream.group_id)
+                            || x.subtitles.as_ref() == Some(&alternative_stream.group_id)
+                    })
+                    .and_then(pathway_id),
+                pathways: vec![],
+                playlist_type: playlist::PlaylistType::Hls,
+                resolution: None, // Cannot be comment here
+                roles: vec![],
+                sdh: characteristics.contains(&"public.accessibility.describes-music-and-sound"),
+                segments: vec![], // Cannot be comment here
+                start_offset: m3u8.start.as_ref().map(|x| x.time_offset as f32),
+                subtitles_group: None,
+                tiles: None,
+                uri: uri.to_owned(),
+                variables: HashMap::new(),
+                video_range: None,
+            });
         }
     }
 
@@ -124,50 +227,128 @@ pub(crate) fn parse_as_master(
     }
 }
 
+fn closed_captions(
+    m3u8: &m3u8_rs::MasterPlaylist,
+    group_id: &Option<m3u8_rs::ClosedCaptionGroupId>,
+) -> Vec<playlist::ClosedCaption> {
+    let Some(m3u8_rs::ClosedCaptionGroupId::GroupId(group_id)) = group_id else {
+        return vec![];
+    };
+
+    m3u8.alternatives
+        .iter()
+        .filter(|x| {
+            x.media_type == m3u8_rs::AlternativeMediaType::ClosedCaptions && &x.group_id == group_id
+        })
+        .filter_map(|x| {
+            let channel = match x.instream_id.as_ref()? {
+                m3u8_rs::InstreamId::CC(x) => format!("CC{}", x),
+                m3u8_rs::InstreamId::Service(x) => format!("SERVICE{}", x),
+                m3u8_rs::InstreamId::Other(_) => return None,
+            };
+
+            Some(playlist::ClosedCaption {
+                channel,
+                language: x.language.to_owned().or(x.assoc_language.to_owned()),
+                name: Some(x.name.to_owned()),
+            })
+        })
+        .collect()
+}
+
 pub(crate) fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playlist::MediaPlaylist) {
     playlist.i_frame = m3u8.i_frames_only;
     playlist.live = !m3u8.end_list;
 
-    let mut previous_byterange_end = 0;
+    if let Some(start) = &m3u8.start {
+        playlist.start_offset = Some(start.time_offset as f32);
+    }
+
+    let mut next_byterange_start = 0;
+    let mut discontinuity_sequence = m3u8.discontinuity_sequence;
+    let mut ad_break = false;
+    let mut ad_break_remaining = None;
+
+    for (i, segment) in m3u8.segments.iter().enumerate() {
+        if segment.discontinuity && i != 0 {
+            discontinuity_sequence += 1;
+        }
+
+        let mut gap = false;
+
+        for tag in &segment.unknown_tags {
+            match tag.tag.as_str() {
+                "X-GAP" => gap = true,
+                "X-CUE-OUT" => {
+                    ad_break = true;
+                    ad_break_remaining = tag.rest.as_ref().and_then(|x| {
+                        cue_attribute(x, "DURATION").or(x.trim().parse::<f32>().ok())
+                    });
+                }
+                // Playlist window can start in between an ad break.
+                "X-CUE-OUT-CONT" if !ad_break => {
+                    ad_break = true;
+                    ad_break_remaining = tag.rest.as_ref().and_then(|x| {
+                        Some(
+                            cue_attribute(x, "DURATION")?
+                                - cue_attribute(x, "ELAPSEDTIME").unwrap_or(0.0),
+                        )
+                    });
+                }
+                "X-CUE-IN" => {
+                    ad_break = false;
+                    ad_break_remaining = None;
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(m3u8_rs::DateRange {
+            duration,
+            planned_duration,
+            other_attributes: Some(attributes),
+            ..
+        }) = &segment.daterange
+        {
+            if attributes.contains_key("SCTE35-OUT") {
+                ad_break = true;
+                ad_break_remaining = duration.or(*planned_duration).map(|x| x as f32);
+            } else if attributes.contains_key("SCTE35-IN") {
+                ad_break = false;
+                ad_break_remaining = None;
+            }
+        }
+
+        let ad = ad_break;
+
+        if let Some(remaining) = &mut ad_break_remaining {
+            *remaining -= segment.duration;
+
+            // Leave some room for rounded off EXTINF durations.
+            if *remaining < 0.5 {
+                ad_break = false;
+                ad_break_remaining = None;
+            }
+        }
 
-    for segment in &m3u8.segments {
         let map = segment.map.as_ref().map(|x| playlist::Map {
             uri: x.uri.to_owned(),
-            range: x.byte_range.as_ref().map(|x| {
-                let offset = x.offset.unwrap_or(0);
-
-                let (start, end) = if offset == 0 {
-                    (
-                        previous_byterange_end,
-                        (previous_byterange_end + x.length) - 1,
-                    )
-                } else {
-                    (x.length, (x.length + offset) - 1)
-                };
-
-                previous_byterange_end = end;
-                playlist::Range { start, end }
-            }),
+            // Map byte range without an offset starts at the beginning of resource.
+            range: x.byte_range.as_ref().map(|x| byte_range(x, 0)),
         });
 
         let range = segment.byte_range.as_ref().map(|x| {
-            let offset = x.offset.unwrap_or(0);
-
-            let (start, end) = if offset == 0 {
-                (
-                    previous_byterange_end,
-                    (previous_byterange_end + x.length) - 1,
-                )
-            } else {
-                (x.length, (x.length + offset) - 1)
-            };
-
-            previous_byterange_end = end;
-            playlist::Range { start, end }
+            let range = byte_range(x, next_byterange_start);
+            next_byterange_start = range.end + 1;
+            range
         });
 
         playlist.segments.push(playlist::Segment {
+            ad,
+            discontinuity_sequence,
             duration: segment.duration,
+            gap,
+            gap_before: 0.0,
             key: if let Some(m3u8_rs::Key {
                 iv,
                 keyformat,
@@ -189,11 +370,19 @@ pub(crate) fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playli
                     m3u8_rs::KeyMethod::Other(x) => playlist::KeyMethod::Other(x.to_owned()),
                 };
 
+                let mut scheme = (method == playlist::KeyMethod::Cenc).then(|| "cenc".to_owned());
+
                 if let Some(keyformat) = keyformat {
                     method = match keyformat.as_str() {
                         "urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed"
                         | "com.apple.streamingkeydelivery"
-                        | "com.microsoft.playready" => playlist::KeyMethod::Cenc, // cbcs (pattern-based) | cbc1
+                        | "com.microsoft.playready" => {
+                            if method == playlist::KeyMethod::SampleAes {
+                                scheme = Some("cbcs".to_owned());
+                            }
+
+                            playlist::KeyMethod::Cenc // cbcs (pattern-based) | cbc1
+                        }
                         _ => method,
                     };
                 }
@@ -202,7 +391,10 @@ pub(crate) fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playli
                     default_kid: None,
                     iv: iv.clone(),
                     key_format: keyformat.clone(),
+                    key_ids: vec![],
                     method,
+                    psshs: vec![],
+                    scheme,
                     uri: uri.clone(),
                 })
             } else {
@@ -240,3 +432,149 @@ pub(crate) fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playli
         }
     }
 }
+
+/// Parse a numeric attribute (case insensitive) from `#EXT-X-CUE-OUT` like tags.
+/// For example, `ElapsedTime=5.939,Duration=201.467`.
+/// Absolute range of `length[@offset]` byte range, sub-range begins at `next_start` when offset is
+/// not present.
+fn byte_range(byte_range: &m3u8_rs::ByteRange, next_start: u64) -> playlist::Range {
+    let start = byte_range.offset.unwrap_or(next_start);
+
+    playlist::Range {
+        start,
+        end: (start + byte_range.length).saturating_sub(1),
+    }
+}
+
+fn cue_attribute(attributes: &str, name: &str) -> Option<f32> {
+    attributes.split(',').find_map(|x| {
+        let (key, value) = x.split_once('=')?;
+
+        if key.trim().eq_ignore_ascii_case(name) {
+            value.trim().trim_matches('"').parse::<f32>().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse channel count of `CHANNELS` attribute, which can be followed by
+/// other parameters separated by `/` (e.g. `16/JOC`).
+fn parse_channels(channels: &str) -> Option<f32> {
+    channels.split('/').next()?.trim().parse::<f32>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::push_segments;
+    use crate::playlist::MediaPlaylist;
+
+    #[test]
+    fn i_frame_byte_ranges() {
+        let m3u8 = m3u8_rs::parse_media_playlist_res(
+            br#"#EXTM3U
+#EXT-X-VERSION:4
+#EXT-X-TARGETDURATION:4
+#EXT-X-I-FRAMES-ONLY
+#EXT-X-MAP:URI="main.mp4",BYTERANGE="720@0"
+#EXTINF:4.0,
+#EXT-X-BYTERANGE:5000@720
+main.mp4
+#EXTINF:4.0,
+#EXT-X-BYTERANGE:6000@90000
+main.mp4
+#EXTINF:4.0,
+#EXT-X-BYTERANGE:7000
+main.mp4
+#EXTINF:4.0,
+#EXT-X-BYTERANGE:100@0
+other.mp4
+#EXT-X-ENDLIST
+"#,
+        )
+        .unwrap();
+        let mut playlist = MediaPlaylist::default();
+        push_segments(&m3u8, &mut playlist);
+
+        assert!(playlist.i_frame);
+
+        let map = playlist.segments[0].map.as_ref().unwrap();
+        let map = map.range.as_ref().unwrap();
+        assert_eq!((map.start, map.end), (0, 719));
+
+        let ranges = playlist
+            .segments
+            .iter()
+            .map(|x| x.range.as_ref().map(|x| (x.start, x.end)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            [(720, 5719), (90000, 95999), (96000, 102999), (0, 99)]
+        );
+    }
+
+    #[test]
+    fn ad_breaks() {
+        let m3u8 = m3u8_rs::parse_media_playlist_res(
+            br#"#EXTM3U
+#EXT-X-TARGETDURATION:4
+#EXTINF:4.0,
+main0.ts
+#EXT-X-DISCONTINUITY
+#EXT-X-CUE-OUT:DURATION=8
+#EXTINF:4.0,
+ad1.ts
+#EXTINF:4.0,
+ad2.ts
+#EXT-X-DISCONTINUITY
+#EXTINF:4.0,
+main3.ts
+#EXT-X-CUE-OUT
+#EXTINF:4.0,
+ad4.ts
+#EXT-X-CUE-IN
+#EXTINF:4.0,
+main5.ts
+#EXT-X-DATERANGE:ID="splice-1",START-DATE="2024-01-01T00:00:24Z",PLANNED-DURATION=4,SCTE35-OUT=0xFC302000
+#EXTINF:4.0,
+ad6.ts
+#EXTINF:4.0,
+main7.ts
+#EXT-X-ENDLIST
+"#,
+        )
+        .unwrap();
+        let mut playlist = MediaPlaylist::default();
+        push_segments(&m3u8, &mut playlist);
+
+        assert_eq!(
+            playlist
+                .segments
+                .iter()
+                .map(|x| (x.ad, x.discontinuity_sequence))
+                .collect::<Vec<_>>(),
+            [
+                (false, 0),
+                (true, 1),
+                (true, 1),
+                (false, 2),
+                (true, 2),
+                (false, 2),
+                (true, 2),
+                (false, 2)
+            ]
+        );
+
+        // Discontinuities around the first ad break are joined after skipping it.
+        playlist.skip_ads();
+        assert_eq!(
+            playlist
+                .segments
+                .iter()
+                .map(|x| x.uri.as_str())
+                .collect::<Vec<_>>(),
+            ["main0.ts", "main3.ts", "main5.ts", "main7.ts"]
+        );
+        assert_eq!(playlist.discontinuities().len(), 1);
+    }
+}
@@ -0,0 +1,320 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.6.6
+    2. https://developer.apple.com/streaming/HLSContentSteeringSpecification.pdf
+
+*/
+
+use super::{attributes, pathway_id};
+use anyhow::{anyhow, Result};
+use m3u8_rs::{MasterPlaylist, QuotedOrUnquoted};
+use reqwest::{blocking::Client, Url};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+struct SteeringManifest {
+    #[serde(default)]
+    pathway_clones: Vec<PathwayClone>,
+    pathway_priority: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+struct PathwayClone {
+    base_id: String,
+    id: String,
+    uri_replacement: UriReplacement,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "SCREAMING-KEBAB-CASE")]
+struct UriReplacement {
+    host: Option<String>,
+    params: HashMap<String, String>,
+    per_rendition_uris: HashMap<String, String>,
+    per_variant_uris: HashMap<String, String>,
+}
+
+impl UriReplacement {
+    fn replace(&self, base_url: &Url, uri: &str, stable_uris: Option<&String>) -> Result<String> {
+        if let Some(uri) = stable_uris {
+            return Ok(base_url.join(uri)?.to_string());
+        }
+
+        let mut url = base_url.join(uri)?;
+
+        if let Some(host) = &self.host {
+            // HOST replaces the whole authority, port included.
+            let (host, port) = match host.rsplit_once(':') {
+                Some((host, port)) if host.ends_with(']') || !host.contains(':') => {
+                    (host, Some(port.parse::<u16>()?))
+                }
+                _ => (host.as_str(), None),
+            };
+            url.set_host(Some(host))?;
+            url.set_port(port)
+                .map_err(|_| anyhow!("cannot set port of {}", url))?;
+        }
+
+        if !self.params.is_empty() {
+            url.query_pairs_mut().extend_pairs(&self.params);
+        }
+
+        Ok(url.to_string())
+    }
+}
+
+/// Apply content steering (`EXT-X-CONTENT-STEERING`) of a master playlist.
+/// Pathways listed in the steering manifest are cloned and variant streams
+/// and renditions are reordered so that ones on the most preferred pathway come first.
+pub(crate) fn steer(m3u8: &mut MasterPlaylist, base_url: &Url, client: &Client) -> Result<()> {
+    let Some(tag) = m3u8
+        .unknown_tags
+        .iter()
+        .find(|x| x.tag == "X-CONTENT-STEERING")
+    else {
+        return Ok(());
+    };
+
+    let attributes = attributes(tag.rest.as_deref().unwrap_or_default());
+    let mut pathway_priority = attributes
+        .get("PATHWAY-ID")
+        .cloned()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    if let Some(server_uri) = attributes.get("SERVER-URI") {
+        let mut url = base_url.join(server_uri)?;
+
+        if let Some(pathway_id) = attributes.get("PATHWAY-ID") {
+            url.query_pairs_mut()
+                .append_pair("_HLS_pathway", pathway_id);
+        }
+
+        let text = client.get(url).send()?.error_for_status()?.text()?;
+        let manifest = serde_json::from_str::<SteeringManifest>(&text)?;
+
+        for pathway_clone in &manifest.pathway_clones {
+            clone_pathway(m3u8, base_url, pathway_clone)?;
+        }
+
+        pathway_priority = manifest.pathway_priority;
+    }
+
+    let priority = |pathway_id: String| {
+        pathway_priority
+            .iter()
+            .position(|x| x == &pathway_id)
+            .unwrap_or(pathway_priority.len())
+    };
+
+    let group_priorities = m3u8
+        .variants
+        .iter()
+        .flat_map(|x| {
+            [&x.audio, &x.subtitles]
+                .into_iter()
+                .flatten()
+                .map(|group_id| (group_id.to_owned(), priority(pathway(x))))
+        })
+        .collect::<HashMap<_, _>>();
+
+    m3u8.variants.sort_by_key(|x| priority(pathway(x)));
+    m3u8.alternatives.sort_by_key(|x| {
+        group_priorities
+            .get(&x.group_id)
+            .copied()
+            .unwrap_or(pathway_priority.len())
+    });
+    Ok(())
+}
+
+/// Variant streams without `PATHWAY-ID` belong to the "." pathway.
+fn pathway(variant: &m3u8_rs::VariantStream) -> String {
+    pathway_id(variant).unwrap_or_else(|| ".".to_owned())
+}
+
+/// Create a new pathway from variant streams and renditions of its base pathway.
+fn clone_pathway(
+    m3u8: &mut MasterPlaylist,
+    base_url: &Url,
+    pathway_clone: &PathwayClone,
+) -> Result<()> {
+    if m3u8.variants.iter().any(|x| pathway(x) == pathway_clone.id) {
+        return Ok(());
+    }
+
+    let replacement = &pathway_clone.uri_replacement;
+    let group_id = |x: &String| format!("{}-{}", x, pathway_clone.id);
+    let stable_id = |x: &Option<HashMap<String, QuotedOrUnquoted>>, key: &str| {
+        x.as_ref()
+            .and_then(|x| x.get(key))
+            .map(|x| x.as_str().to_owned())
+    };
+
+    let mut variants = vec![];
+    let mut groups = HashSet::new();
+
+    for variant in m3u8
+        .variants
+        .iter()
+        .filter(|x| pathway(x) == pathway_clone.base_id)
+    {
+        let mut variant = variant.clone();
+        variant.uri = replacement.replace(
+            base_url,
+            &variant.uri,
+            stable_id(&variant.other_attributes, "STABLE-VARIANT-ID")
+                .and_then(|x| replacement.per_variant_uris.get(&x)),
+        )?;
+
+        for group in [&mut variant.audio, &mut variant.subtitles]
+            .into_iter()
+            .flatten()
+        {
+            groups.insert(group.to_owned());
+            *group = group_id(group);
+        }
+
+        variant
+            .other_attributes
+            .get_or_insert_with(HashMap::new)
+            .insert(
+                "PATHWAY-ID".to_owned(),
+                QuotedOrUnquoted::Quoted(pathway_clone.id.to_owned()),
+            );
+        variants.push(variant);
+    }
+
+    let mut alternatives = vec![];
+
+    for alternative in m3u8
+        .alternatives
+        .iter()
+        .filter(|x| groups.contains(&x.group_id))
+    {
+        let mut alternative = alternative.clone();
+        alternative.group_id = group_id(&alternative.group_id);
+
+        if let Some(uri) = &alternative.uri {
+            alternative.uri = Some(
+                replacement.replace(
+                    base_url,
+                    uri,
+                    stable_id(&alternative.other_attributes, "STABLE-RENDITION-ID")
+                        .and_then(|x| replacement.per_rendition_uris.get(&x)),
+                )?,
+            );
+        }
+
+        alternatives.push(alternative);
+    }
+
+    m3u8.variants.extend(variants);
+    m3u8.alternatives.extend(alternatives);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::steer;
+    use reqwest::{blocking::Client, Url};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Serve `body` once and return the request line received.
+    fn serve(body: &'static str) -> (Url, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            request_line
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn steering_manifest() {
+        let (base_url, server) = serve(
+            r#"{
+                "VERSION": 1,
+                "TTL": 300,
+                "PATHWAY-PRIORITY": ["CDN-C", "."],
+                "PATHWAY-CLONES": [{
+                    "BASE-ID": ".",
+                    "ID": "CDN-C",
+                    "URI-REPLACEMENT": {
+                        "HOST": "cdn-c.example.com",
+                        "PARAMS": {"token": "c"}
+                    }
+                }]
+            }"#,
+        );
+        let mut m3u8 = m3u8_rs::parse_master_playlist_res(
+            br#"#EXTM3U
+#EXT-X-CONTENT-STEERING:SERVER-URI="steering.json",PATHWAY-ID="."
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",LANGUAGE="en",URI="audio.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,AUDIO="aac"
+video.m3u8
+"#,
+        )
+        .unwrap();
+
+        steer(
+            &mut m3u8,
+            &base_url,
+            &Client::builder().no_proxy().build().unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            server.join().unwrap().trim_end(),
+            "GET /steering.json?_HLS_pathway=. HTTP/1.1"
+        );
+
+        assert_eq!(m3u8.variants.len(), 2);
+        assert_eq!(
+            m3u8.variants[0].uri,
+            "http://cdn-c.example.com/video.m3u8?token=c"
+        );
+        assert_eq!(m3u8.variants[0].audio.as_deref(), Some("aac-CDN-C"));
+        assert_eq!(m3u8.variants[1].uri, "video.m3u8");
+
+        assert_eq!(m3u8.alternatives.len(), 2);
+        assert_eq!(m3u8.alternatives[0].group_id, "aac-CDN-C");
+        assert_eq!(
+            m3u8.alternatives[0].uri.as_deref(),
+            Some("http://cdn-c.example.com/audio.m3u8?token=c")
+        );
+        assert_eq!(m3u8.alternatives[1].group_id, "aac");
+    }
+}
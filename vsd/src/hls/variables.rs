@@ -0,0 +1,84 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.3
+    2. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.2.3
+
+*/
+
+use super::attributes;
+use anyhow::{bail, Result};
+use reqwest::Url;
+use std::collections::HashMap;
+
+/// Define variables of a playlist (`EXT-X-DEFINE`) and substitute their references (`{$name}`).
+/// `imports` are variables of the master playlist which can be imported by media playlists.
+/// Returns substituted playlist and variables defined by it.
+pub(crate) fn substitute_variables(
+    text: &str,
+    url: &Url,
+    imports: &HashMap<String, String>,
+) -> Result<(String, HashMap<String, String>)> {
+    if !text.contains("#EXT-X-DEFINE:") {
+        return Ok((text.to_owned(), HashMap::new()));
+    }
+
+    let mut variables = HashMap::new();
+    let mut lines = vec![];
+
+    for line in text.lines() {
+        if let Some(rest) = line.strip_prefix("#EXT-X-DEFINE:") {
+            let attributes = attributes(rest);
+
+            if let Some(name) = attributes.get("NAME") {
+                let Some(value) = attributes.get("VALUE") else {
+                    bail!("EXT-X-DEFINE variable {} has no VALUE attribute.", name);
+                };
+                variables.insert(name.to_owned(), value.to_owned());
+            } else if let Some(name) = attributes.get("QUERYPARAM") {
+                let Some((_, value)) = url.query_pairs().find(|(key, _)| key == name.as_str())
+                else {
+                    bail!(
+                        "EXT-X-DEFINE query parameter {} is not present in {}.",
+                        name,
+                        url
+                    );
+                };
+                variables.insert(name.to_owned(), value.into_owned());
+            } else if let Some(name) = attributes.get("IMPORT") {
+                let Some(value) = imports.get(name) else {
+                    bail!(
+                        "EXT-X-DEFINE variable {} is not defined by master playlist.",
+                        name
+                    );
+                };
+                variables.insert(name.to_owned(), value.to_owned());
+            }
+
+            lines.push(line.to_owned());
+            continue;
+        }
+
+        let mut line = line.to_owned();
+        let mut position = 0;
+
+        while let Some(start) = line[position..].find("{$").map(|x| position + x) {
+            let Some(end) = line[start..].find('}').map(|x| start + x) else {
+                break;
+            };
+            let name = &line[(start + 2)..end];
+
+            let Some(value) = variables.get(name) else {
+                bail!("variable {} is referenced before it is defined.", name);
+            };
+
+            position = start + value.len();
+            line.replace_range(start..=end, value);
+        }
+
+        lines.push(line);
+    }
+
+    Ok((lines.join("\n"), variables))
+}
@@ -0,0 +1,189 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/rfc5646 (Tags for Identifying Languages)
+    2. https://datatracker.ietf.org/doc/html/rfc4647 (Matching of Language Tags)
+    3. https://www.loc.gov/standards/iso639-2/php/code_list.php
+
+    Language tags are matched subtag by subtag after converting ISO 639-2 codes to ISO 639-1.
+    Tags with different languages never match.
+
+*/
+
+use std::cmp::Reverse;
+
+/// Rank of a language tag against ordered preferences, lower is better.
+/// Tags are first ranked by index of their matching preference and then by how closely they match it.
+pub(crate) fn rank(preferences: &[String], tag: Option<&str>) -> (usize, Reverse<u8>) {
+    if let Some(tag) = tag {
+        for (i, preference) in preferences.iter().enumerate() {
+            if let Some(score) = score(preference, tag) {
+                return (i, Reverse(score));
+            }
+        }
+    }
+
+    (preferences.len(), Reverse(0))
+}
+
+fn score(preference: &str, tag: &str) -> Option<u8> {
+    let preference = Subtags::parse(preference);
+    let tag = Subtags::parse(tag);
+
+    if preference.language != tag.language {
+        return None;
+    }
+
+    let subtag_score = |x: &Option<String>, y: &Option<String>| match (x, y) {
+        (Some(x), Some(y)) if x != y => 0,
+        (Some(_), None) | (None, Some(_)) => 1,
+        _ => 2,
+    };
+
+    Some(
+        subtag_score(&preference.script, &tag.script) * 3
+            + subtag_score(&preference.region, &tag.region),
+    )
+}
+
+struct Subtags {
+    language: String,
+    region: Option<String>,
+    script: Option<String>,
+}
+
+impl Subtags {
+    fn parse(tag: &str) -> Self {
+        let mut subtags = tag.split(['-', '_']);
+        let language = subtags.next().unwrap_or_default().to_lowercase();
+        let mut script = None;
+        let mut region = None;
+
+        for subtag in subtags {
+            if subtag.len() == 4 && subtag.chars().all(|x| x.is_ascii_alphabetic()) {
+                script = Some(subtag.to_lowercase());
+            } else if (subtag.len() == 2 && subtag.chars().all(|x| x.is_ascii_alphabetic()))
+                || (subtag.len() == 3 && subtag.chars().all(|x| x.is_ascii_digit()))
+            {
+                region = Some(subtag.to_lowercase());
+            }
+        }
+
+        Self {
+            language: iso_639_1(&language).unwrap_or(&language).to_owned(),
+            region,
+            script,
+        }
+    }
+}
+
+/// ISO 639-1 code of common ISO 639-2 (bibliographic and terminology) and deprecated codes.
+fn iso_639_1(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "ara" => "ar",
+        "baq" | "eus" => "eu",
+        "ben" => "bn",
+        "bul" => "bg",
+        "cat" => "ca",
+        "ces" | "cze" => "cs",
+        "chi" | "zho" => "zh",
+        "dan" => "da",
+        "deu" | "ger" => "de",
+        "dut" | "nld" => "nl",
+        "ell" | "gre" => "el",
+        "eng" => "en",
+        "est" => "et",
+        "fas" | "per" => "fa",
+        "fin" => "fi",
+        "fra" | "fre" => "fr",
+        "gle" => "ga",
+        "glg" => "gl",
+        "heb" | "iw" => "he",
+        "hin" => "hi",
+        "hrv" => "hr",
+        "hun" => "hu",
+        "ice" | "isl" => "is",
+        "in" | "ind" => "id",
+        "ita" => "it",
+        "jpn" => "ja",
+        "kan" => "kn",
+        "kor" => "ko",
+        "lav" => "lv",
+        "lit" => "lt",
+        "mal" => "ml",
+        "mar" => "mr",
+        "may" | "msa" => "ms",
+        "nob" => "nb",
+        "nor" => "no",
+        "pol" => "pl",
+        "por" => "pt",
+        "ron" | "rum" => "ro",
+        "rus" => "ru",
+        "slk" | "slo" => "sk",
+        "slv" => "sl",
+        "spa" => "es",
+        "srp" => "sr",
+        "swe" => "sv",
+        "tam" => "ta",
+        "tel" => "te",
+        "tgl" => "tl",
+        "tha" => "th",
+        "tur" => "tr",
+        "ukr" => "uk",
+        "urd" => "ur",
+        "vie" => "vi",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rank, score};
+    use std::cmp::Reverse;
+
+    #[test]
+    fn exact() {
+        assert_eq!(score("en-US", "en-US"), Some(8));
+    }
+
+    #[test]
+    fn missing_region() {
+        assert_eq!(score("en-US", "en"), Some(7));
+        assert_eq!(score("en", "en-AU"), Some(7));
+    }
+
+    #[test]
+    fn different_region() {
+        assert_eq!(score("en-US", "en-GB"), Some(6));
+    }
+
+    #[test]
+    fn iso_639_2() {
+        assert_eq!(score("en", "eng"), Some(8));
+        assert_eq!(score("fr", "fre"), Some(8));
+        assert_eq!(score("de", "ger"), Some(8));
+    }
+
+    #[test]
+    fn script() {
+        assert_eq!(score("zh-Hant", "zh-Hant-TW"), Some(7));
+        assert_eq!(score("zh-Hant", "zh"), Some(5));
+        assert_eq!(score("zh-Hant", "zh-Hans"), Some(2));
+    }
+
+    #[test]
+    fn different_language() {
+        assert_eq!(score("de", "fr"), None);
+    }
+
+    #[test]
+    fn ordered_preferences() {
+        let preferences = ["fr".to_owned(), "en-US".to_owned()];
+
+        assert_eq!(rank(&preferences, Some("fre")), (0, Reverse(8)));
+        assert_eq!(rank(&preferences, Some("en-GB")), (1, Reverse(6)));
+        assert_eq!(rank(&preferences, Some("de")), (2, Reverse(0)));
+        assert_eq!(rank(&preferences, None), (2, Reverse(0)));
+    }
+}
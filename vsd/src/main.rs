@@ -1,10 +1,14 @@
+mod codec;
 mod commands;
 mod cookie;
 mod dash;
 mod downloader;
+mod filter;
 mod hls;
+mod language;
 mod merger;
 mod playlist;
+mod smooth;
 mod utils;
 
 use clap::{ColorChoice, Parser};
@@ -28,7 +32,9 @@ fn run() -> anyhow::Result<()> {
     match args.command {
         #[cfg(feature = "browser")]
         Commands::Capture(args) => args.execute()?,
+        Commands::Convert(args) => args.execute()?,
         Commands::Extract(args) => args.execute()?,
+        Commands::Info(args) => args.execute()?,
         Commands::Merge(args) => args.execute()?,
         Commands::Save(args) => args.execute()?,
     }
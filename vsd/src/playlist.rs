@@ -7,13 +7,19 @@
 
 */
 
-use crate::commands::Quality;
+use crate::{
+    codec::{CodecFamily, DynamicRange, VideoCodec},
+    commands::{Quality, QualityFallback},
+    filter::StreamFilters,
+    language,
+};
 use anyhow::{bail, Result};
+use clap::ValueEnum;
 use kdam::term::Colorizer;
 use requestty::prompt::style::Stylize;
 use reqwest::{header::HeaderValue, Url};
 use serde::Serialize;
-use std::{fmt::Display, io::Write, path::PathBuf};
+use std::{cmp::Reverse, collections::HashMap, fmt::Display, io::Write, path::PathBuf};
 
 #[derive(Serialize)]
 pub(crate) struct MasterPlaylist {
@@ -22,35 +28,43 @@ pub(crate) struct MasterPlaylist {
     pub(crate) streams: Vec<MediaPlaylist>,
 }
 
-impl MasterPlaylist {
-    pub(crate) fn sort_streams(
-        mut self,
-        prefer_audio_lang: Option<String>,
-        prefer_subs_lang: Option<String>,
-    ) -> Self {
-        let prefer_audio_lang = prefer_audio_lang.map(|x| x.to_lowercase());
-        let prefer_subs_lang = prefer_subs_lang.map(|x| x.to_lowercase());
+/// Preferences used while ranking streams.
+#[derive(Default)]
+pub(crate) struct StreamPreferences {
+    /// Audio languages (RFC 5646), in order of preference.
+    pub(crate) audio_langs: Vec<String>,
+    /// Video codec families, in order of preference.
+    pub(crate) codecs: Vec<CodecFamily>,
+    /// Video streams with nearest frame rate are preferred, otherwise highest frame rate.
+    pub(crate) frame_rate: Option<f32>,
+    /// Video dynamic ranges, in order of preference. SDR is preferred when empty.
+    pub(crate) ranges: Vec<DynamicRange>,
+    /// Subtitles languages (RFC 5646), in order of preference.
+    pub(crate) subs_langs: Vec<String>,
+}
 
+impl MasterPlaylist {
+    pub(crate) fn sort_streams(mut self, preferences: &StreamPreferences) -> Self {
         let mut video_streams = vec![];
         let mut audio_streams = vec![];
         let mut subtitle_streams = vec![];
+        let mut image_streams = vec![];
         let mut undefined_streams = vec![];
 
         for stream in self.streams {
             match stream.media_type {
+                MediaType::Image => {
+                    let pixels = if let Some((w, h)) = &stream.resolution {
+                        w * h
+                    } else {
+                        0
+                    };
+
+                    image_streams.push((stream, pixels));
+                }
                 MediaType::Audio => {
-                    let mut language_factor = 0;
-
-                    if let Some(playlist_lang) = &stream.language.as_ref().map(|x| x.to_lowercase())
-                    {
-                        if let Some(prefer_lang) = &prefer_audio_lang {
-                            if playlist_lang == prefer_lang {
-                                language_factor = 2;
-                            } else if playlist_lang.get(0..2) == prefer_lang.get(0..2) {
-                                language_factor = 1;
-                            }
-                        }
-                    }
+                    let language_factor =
+                        language::rank(&preferences.audio_langs, stream.language.as_deref());
 
                     let channels = stream.channels.unwrap_or(0.0);
                     let bandwidth = stream.bandwidth.unwrap_or(0);
@@ -58,18 +72,8 @@ impl MasterPlaylist {
                     audio_streams.push((stream, language_factor, channels, bandwidth));
                 }
                 MediaType::Subtitles => {
-                    let mut language_factor = 0;
-
-                    if let Some(playlist_lang) = &stream.language.as_ref().map(|x| x.to_lowercase())
-                    {
-                        if let Some(prefer_lang) = &prefer_subs_lang {
-                            if playlist_lang == prefer_lang {
-                                language_factor = 2;
-                            } else if playlist_lang.get(0..2) == prefer_lang.get(0..2) {
-                                language_factor = 1;
-                            }
-                        }
-                    }
+                    let language_factor =
+                        language::rank(&preferences.subs_langs, stream.language.as_deref());
 
                     subtitle_streams.push((stream, language_factor));
                 }
@@ -82,299 +86,522 @@ impl MasterPlaylist {
                     };
 
                     let bandwidth = stream.bandwidth.unwrap_or(0);
+                    let codec_factor = match stream.video_codec() {
+                        Some(codec) if !preferences.codecs.is_empty() => preferences
+                            .codecs
+                            .iter()
+                            .position(|x| *x == codec.family)
+                            .unwrap_or(preferences.codecs.len()),
+                        Some(_) => 0,
+                        None => preferences.codecs.len(),
+                    };
+                    let range = stream.dynamic_range();
+                    let range_factor = if preferences.ranges.is_empty() {
+                        (range != DynamicRange::Sdr) as usize
+                    } else {
+                        preferences
+                            .ranges
+                            .iter()
+                            .position(|x| *x == range)
+                            .unwrap_or(preferences.ranges.len())
+                    };
+                    let frame_rate = stream.frame_rate.unwrap_or(0.0);
+                    let frame_rate_factor = match preferences.frame_rate {
+                        Some(prefer_frame_rate) => -(frame_rate - prefer_frame_rate).abs(),
+                        None => frame_rate,
+                    };
 
-                    video_streams.push((stream, pixels, bandwidth));
+                    video_streams.push((
+                        stream,
+                        pixels,
+                        bandwidth,
+                        codec_factor,
+                        range_factor,
+                        frame_rate_factor,
+                    ));
                 }
             }
         }
 
         video_streams.sort_by(|x, y| y.2.cmp(&x.2));
+        video_streams.sort_by(|x, y| y.5.total_cmp(&x.5));
+        video_streams.sort_by_key(|x| x.3);
+        video_streams.sort_by_key(|x| x.4);
         video_streams.sort_by(|x, y| y.1.cmp(&x.1));
         audio_streams.sort_by(|x, y| y.3.cmp(&x.3));
         audio_streams.sort_by(|x, y| y.2.total_cmp(&x.2));
-        audio_streams.sort_by(|x, y| y.1.cmp(&x.1));
-        subtitle_streams.sort_by(|x, y| y.1.cmp(&x.1));
+        audio_streams.sort_by_key(|x| x.0.is_supplementary());
+        audio_streams.sort_by_key(|x| !x.0.autoselect);
+        audio_streams.sort_by_key(|x| !x.0.default);
+        audio_streams.sort_by_key(|x| x.1);
+        subtitle_streams.sort_by_key(|x| (x.0.forced, x.0.is_supplementary()));
+        subtitle_streams.sort_by_key(|x| !x.0.autoselect);
+        subtitle_streams.sort_by_key(|x| !x.0.default);
+        subtitle_streams.sort_by_key(|x| x.1);
+        image_streams.sort_by_key(|x| Reverse(x.1));
 
         self.streams = video_streams
             .into_iter()
             .map(|x| x.0)
             .chain(audio_streams.into_iter().map(|x| x.0))
             .chain(subtitle_streams.into_iter().map(|x| x.0))
+            .chain(image_streams.into_iter().map(|x| x.0))
             .chain(undefined_streams)
             .collect::<Vec<_>>();
 
         self
     }
 
-    fn select_video_stream(&self, quality: &Quality) -> Option<usize> {
-        let video_streams = self
-            .streams
-            .iter()
-            .filter(|x| x.media_type == MediaType::Video)
-            .enumerate();
-
-        let mut has_resolution = None;
-        let mut has_height = None;
-
-        let (w, h) = match quality {
-            Quality::Lowest => return Some(video_streams.count() - 1),
-            Quality::Highest => return Some(0),
-            Quality::Resolution(w, h) => (*w as u64, *h as u64),
-            Quality::Youtube144p => (256, 144),
-            Quality::Youtube240p => (426, 240),
-            Quality::Youtube360p => (640, 360),
-            Quality::Youtube480p => (854, 480),
-            Quality::Youtube720p => (1280, 720),
-            Quality::Youtube1080p => (1920, 1080),
-            Quality::Youtube2k => (2048, 1080),
-            Quality::Youtube1440p => (2560, 1440),
-            Quality::Youtube4k => (3840, 2160),
-            Quality::Youtube8k => (7680, 4320),
-        };
-
-        for (i, stream) in video_streams {
-            if has_resolution.is_some() && has_height.is_some() {
-                break;
-            }
-
-            if let Some((video_w, video_h)) = &stream.resolution {
-                if h == *video_h {
-                    has_height = Some(i);
-
-                    if w == *video_w {
-                        has_resolution = Some(i);
-                    }
-                }
-            }
-        }
-
-        has_resolution.or(has_height)
+    /// Keep either I-frame or normal video streams, renditions are kept as it is.
+    pub(crate) fn retain_i_frames(&mut self, i_frames: bool) {
+        // I-frame (trick play) streams are only useful for previews,
+        // so they are never mixed with normal video streams.
+        self.streams
+            .retain(|x| x.media_type != MediaType::Video || x.i_frame == i_frames);
     }
 
     pub(crate) fn select_streams(
-        self,
+        mut self,
         quality: Quality,
+        quality_fallback: QualityFallback,
+        filters: &StreamFilters,
+        i_frames: bool,
         skip_prompts: bool,
         raw_prompts: bool,
     ) -> Result<(Vec<MediaPlaylist>, Vec<MediaPlaylist>)> {
-        let default_video_stream_index = self.select_video_stream(&quality);
-
-        if let Some(default_video_stream_index) = default_video_stream_index {
-            let mut video_streams = vec![];
-            let mut audio_streams = vec![];
-            let mut subtitle_streams = vec![];
-            // TODO - Add support for downloading undefined streams
-            let mut undefined_streams = vec![];
-
-            for stream in self.streams {
-                match stream.media_type {
-                    MediaType::Audio => audio_streams.push(stream),
-                    MediaType::Subtitles => subtitle_streams.push(stream),
-                    MediaType::Undefined => undefined_streams.push(stream),
-                    MediaType::Video => video_streams.push(stream),
-                }
+        self.retain_i_frames(i_frames);
+
+        let mut video_streams = vec![];
+        let mut audio_streams = vec![];
+        let mut subtitle_streams = vec![];
+        let mut image_streams = vec![];
+        let mut undefined_streams = vec![];
+
+        for stream in self.streams {
+            match stream.media_type {
+                MediaType::Audio => audio_streams.push(stream),
+                MediaType::Image => image_streams.push(stream),
+                MediaType::Subtitles => subtitle_streams.push(stream),
+                MediaType::Undefined => undefined_streams.push(stream),
+                MediaType::Video => video_streams.push(stream),
             }
+        }
 
-            let mut choices_with_default = vec![];
-            let mut choices_with_default_ranges: [std::ops::Range<usize>; 4] =
-                [(0..0), (0..0), (0..0), (0..0)];
-
-            choices_with_default.push(requestty::Separator(
-                "─────── Video Streams ────────".to_owned(),
-            ));
-            choices_with_default.extend(video_streams.iter().enumerate().map(|(i, x)| {
-                requestty::Choice((x.display_video_stream(), i == default_video_stream_index))
-            }));
-            choices_with_default_ranges[0] = 1..choices_with_default.len();
-            choices_with_default.push(requestty::Separator(
-                "─────── Audio Streams ────────".to_owned(),
-            ));
-            choices_with_default.extend(
-                audio_streams
-                    .iter()
-                    .enumerate()
-                    .map(|(i, x)| requestty::Choice((x.display_audio_stream(), i == 0))),
-            );
+        let default_video_stream_index = if let Some(filter) = &filters.video {
+            let index = filter.select(&video_streams).first().copied();
 
-            if skip_prompts || raw_prompts {
-                choices_with_default_ranges[1] =
-                    choices_with_default_ranges[0].end..(choices_with_default.len() - 1);
-            } else {
-                choices_with_default_ranges[1] =
-                    (choices_with_default_ranges[0].end + 1)..choices_with_default.len();
+            if index.is_none() && !video_streams.is_empty() {
+                bail!(
+                    "playlist doesn't contain any video stream matching --select-video expression."
+                )
             }
 
-            choices_with_default.push(requestty::Separator(
-                "────── Subtitle Streams ──────".to_owned(),
-            ));
-            choices_with_default.extend(
-                subtitle_streams
-                    .iter()
-                    .enumerate()
-                    .map(|(i, x)| requestty::Choice((x.display_subtitle_stream(), i == 0))),
-            );
+            index
+        } else if let Some(index) = select_video_stream(&video_streams, &quality, &quality_fallback)
+        {
+            Some(index)
+        } else {
+            bail!("playlist doesn't contain pre-selected video quality stream (see --quality-fallback).")
+        };
 
-            if skip_prompts || raw_prompts {
-                choices_with_default_ranges[2] =
-                    choices_with_default_ranges[1].end..(choices_with_default.len() - 2);
-            } else {
-                choices_with_default_ranges[2] =
-                    (choices_with_default_ranges[1].end + 1)..choices_with_default.len();
+        // Renditions are pre-selected from the groups referenced by the default video stream.
+        // Playlists without any group information fallback to the first stream.
+        let default_video_stream = default_video_stream_index.and_then(|x| video_streams.get(x));
+        let audio_defaults = match &filters.audio {
+            Some(filter) => filter.select(&audio_streams),
+            None if i_frames => vec![],
+            None => default_rendition_index(
+                &audio_streams,
+                default_video_stream.and_then(|x| x.audio_group.as_ref()),
+            )
+            .into_iter()
+            .collect(),
+        };
+        let subtitle_defaults = match &filters.subs {
+            Some(filter) => filter.select(&subtitle_streams),
+            None if i_frames => vec![],
+            None => default_rendition_index(
+                &subtitle_streams,
+                default_video_stream.and_then(|x| x.subtitles_group.as_ref()),
+            )
+            .into_iter()
+            .collect(),
+        };
+        let undefined_defaults = filters
+            .other
+            .as_ref()
+            .map(|x| x.select(&undefined_streams))
+            .unwrap_or_default();
+
+        // Streams in the order of prompt choices, separators are mapped to none.
+        let mut streams = vec![];
+        let mut choice_streams = vec![];
+        let mut choices_with_default = vec![];
+
+        for (separator, group, defaults) in [
+            (
+                "─────── Video Streams ────────",
+                video_streams,
+                default_video_stream_index.into_iter().collect(),
+            ),
+            (
+                "─────── Audio Streams ────────",
+                audio_streams,
+                audio_defaults,
+            ),
+            (
+                "────── Subtitle Streams ──────",
+                subtitle_streams,
+                subtitle_defaults,
+            ),
+            ("───── Thumbnail Streams ──────", image_streams, vec![]),
+            (
+                "─────── Other Streams ────────",
+                undefined_streams,
+                undefined_defaults,
+            ),
+        ] {
+            if group.is_empty() && (separator.contains("Thumbnail") || separator.contains("Other"))
+            {
+                continue;
             }
 
-            // println!("{:?}", choices_with_default_ranges);
+            choices_with_default.push(requestty::Separator(separator.to_owned()));
+            choice_streams.push(None);
+
+            for (i, stream) in group.into_iter().enumerate() {
+                let message = match stream.media_type {
+                    MediaType::Audio => stream.display_audio_stream(),
+                    MediaType::Image => stream.display_image_stream(),
+                    MediaType::Subtitles => stream.display_subtitle_stream(),
+                    MediaType::Undefined => stream.display_undefined_stream(),
+                    MediaType::Video => stream.display_video_stream(),
+                };
+
+                choices_with_default.push(requestty::Choice((message, defaults.contains(&i))));
+                choice_streams.push(Some(streams.len()));
+                streams.push(stream);
+            }
+        }
 
-            if skip_prompts || raw_prompts {
-                println!("Select streams to download:");
-                let mut selected_choices_index = vec![];
-                let mut index = 1;
+        let selected_streams_index = if skip_prompts || raw_prompts {
+            println!("Select streams to download:");
+            let mut selected_streams_index = vec![];
 
-                for choice in choices_with_default {
-                    if let requestty::Separator(seperator) = choice {
-                        println!("{}", seperator.replace('─', "-"));
-                    } else {
-                        let (message, selected) = choice.unwrap_choice();
-
-                        if selected {
-                            selected_choices_index.push(index);
-                        }
-
-                        println!(
-                            "{:2}) [{}] {}",
-                            index,
-                            if selected { 'x' } else { ' ' },
-                            message
-                        );
-                        index += 1;
+            for (choice, index) in choices_with_default.into_iter().zip(choice_streams) {
+                if let Some(index) = index {
+                    let (message, selected) = choice.unwrap_choice();
+
+                    if selected {
+                        selected_streams_index.push(index);
                     }
+
+                    println!(
+                        "{:2}) [{}] {}",
+                        index + 1,
+                        if selected { 'x' } else { ' ' },
+                        message
+                    );
+                } else if let requestty::Separator(seperator) = choice {
+                    println!("{}", seperator.replace('─', "-"));
                 }
+            }
 
-                println!("------------------------------");
+            println!("------------------------------");
 
-                if raw_prompts && !skip_prompts {
-                    print!(
-                        "Press enter to proceed with defaults.\n\
-                        Or select streams to download (1, 2, etc.): "
-                    );
-                    std::io::stdout().flush()?;
-                    let mut input = String::new();
-                    std::io::stdin().read_line(&mut input)?;
+            if raw_prompts && !skip_prompts {
+                print!(
+                    "Press enter to proceed with defaults.\n\
+                    Or select streams to download (1, 2, etc.): "
+                );
+                std::io::stdout().flush()?;
+                let mut input = String::new();
+                std::io::stdin().read_line(&mut input)?;
 
-                    println!("------------------------------");
+                println!("------------------------------");
 
-                    let input = input.trim();
+                let input = input.trim();
 
-                    if !input.is_empty() {
-                        selected_choices_index = input
-                            .split(',')
-                            .filter_map(|x| x.trim().parse::<usize>().ok())
-                            .collect::<Vec<usize>>();
-                    }
+                if !input.is_empty() {
+                    selected_streams_index = input
+                        .split(',')
+                        .filter_map(|x| x.trim().parse::<usize>().ok())
+                        .filter_map(|x| x.checked_sub(1))
+                        .collect::<Vec<usize>>();
                 }
+            }
 
-                let mut selected_streams = vec![];
-                let mut selected_subtitle_streams = vec![];
-                let mut video_streams_offset = 1;
-                let mut audio_streams_offset = video_streams_offset + video_streams.len();
-                let mut subtitle_streams_offset = audio_streams_offset + audio_streams.len();
-
-                for i in selected_choices_index {
-                    if choices_with_default_ranges[0].contains(&i) {
-                        let stream = video_streams.remove(i - video_streams_offset);
-                        println!(
-                            "   {} {}",
-                            "Selected".colorize("bold green"),
-                            stream.display_stream()
-                        );
-                        selected_streams.push(stream);
-                        video_streams_offset += 1;
-                    } else if choices_with_default_ranges[1].contains(&i) {
-                        let stream = audio_streams.remove(i - audio_streams_offset);
-                        println!(
-                            "   {} {}",
-                            "Selected".colorize("bold green"),
-                            stream.display_stream()
-                        );
-                        selected_streams.push(stream);
-                        audio_streams_offset += 1;
-                    } else if choices_with_default_ranges[2].contains(&i) {
-                        let stream = subtitle_streams.remove(i - subtitle_streams_offset);
-                        println!(
-                            "   {} {}",
-                            "Selected".colorize("bold green"),
-                            stream.display_stream()
-                        );
-                        selected_subtitle_streams.push(stream);
-                        subtitle_streams_offset += 1;
-                    }
-                }
+            selected_streams_index
+        } else {
+            let question = requestty::Question::multi_select("streams")
+                .should_loop(false)
+                .message("Select streams to download")
+                .choices_with_default(choices_with_default)
+                .transform(|choices, _, backend| {
+                    backend.write_styled(
+                        &choices
+                            .iter()
+                            .map(|x| x.text.split_whitespace().collect::<Vec<_>>().join(" "))
+                            .collect::<Vec<_>>()
+                            .join(" | ")
+                            .cyan(),
+                    )
+                })
+                .build();
+
+            let answer = requestty::prompt_one(question)?;
+
+            answer
+                .as_list_items()
+                .unwrap()
+                .iter()
+                .filter_map(|x| choice_streams.get(x.index).copied().flatten())
+                .collect()
+        };
+
+        let mut streams = streams.into_iter().map(Some).collect::<Vec<_>>();
+        let mut selected_streams = vec![];
+        let mut selected_subtitle_streams = vec![];
+
+        for i in selected_streams_index {
+            let Some(stream) = streams.get_mut(i).and_then(Option::take) else {
+                continue;
+            };
 
-                Ok((selected_streams, selected_subtitle_streams))
+            if skip_prompts || raw_prompts {
+                println!(
+                    "   {} {}",
+                    "Selected".colorize("bold green"),
+                    stream.display_stream()
+                );
+            }
+
+            if stream.media_type == MediaType::Subtitles {
+                selected_subtitle_streams.push(stream);
             } else {
-                let question = requestty::Question::multi_select("streams")
-                    .should_loop(false)
-                    .message("Select streams to download")
-                    .choices_with_default(choices_with_default)
-                    .transform(|choices, _, backend| {
-                        backend.write_styled(
-                            &choices
-                                .iter()
-                                .map(|x| x.text.split_whitespace().collect::<Vec<_>>().join(" "))
-                                .collect::<Vec<_>>()
-                                .join(" | ")
-                                .cyan(),
-                        )
-                    })
-                    .build();
-
-                let answer = requestty::prompt_one(question)?;
-
-                let mut selected_streams = vec![];
-                let mut selected_subtitle_streams = vec![];
-                let mut video_streams_offset = 1;
-                let mut audio_streams_offset = video_streams_offset + video_streams.len() + 1;
-                let mut subtitle_streams_offset = audio_streams_offset + audio_streams.len() + 1;
-
-                for selected_item in answer.as_list_items().unwrap() {
-                    if choices_with_default_ranges[0].contains(&selected_item.index) {
-                        selected_streams
-                            .push(video_streams.remove(selected_item.index - video_streams_offset));
-                        video_streams_offset += 1;
-                    } else if choices_with_default_ranges[1].contains(&selected_item.index) {
-                        selected_streams
-                            .push(audio_streams.remove(selected_item.index - audio_streams_offset));
-                        audio_streams_offset += 1;
-                    } else if choices_with_default_ranges[2].contains(&selected_item.index) {
-                        selected_subtitle_streams.push(
-                            subtitle_streams.remove(selected_item.index - subtitle_streams_offset),
-                        );
-                        subtitle_streams_offset += 1;
-                    }
-                }
+                selected_streams.push(stream);
+            }
+        }
+
+        Ok((selected_streams, selected_subtitle_streams))
+    }
+}
 
-                Ok((selected_streams, selected_subtitle_streams))
+fn select_video_stream(
+    video_streams: &[MediaPlaylist],
+    quality: &Quality,
+    quality_fallback: &QualityFallback,
+) -> Option<usize> {
+    let mut has_resolution = None;
+    let mut has_height = None;
+
+    let (w, h) = match quality {
+        Quality::Lowest => return Some(video_streams.len().saturating_sub(1)),
+        Quality::Highest => return Some(0),
+        Quality::Bandwidth(bandwidth) => {
+            let bandwidths = video_streams
+                .iter()
+                .enumerate()
+                .filter_map(|(i, x)| x.bandwidth.map(|y| (i, y)));
+
+            // Highest bandwidth which doesn't exceed the target.
+            if let Some((i, _)) = bandwidths
+                .clone()
+                .filter(|x| x.1 <= *bandwidth)
+                .max_by(|x, y| x.1.cmp(&y.1).then(y.0.cmp(&x.0)))
+            {
+                return Some(i);
             }
-        } else {
-            bail!("playlist doesn't contain pre-selected video quality stream.")
+
+            if let QualityFallback::Fail = quality_fallback {
+                return None;
+            }
+
+            // Every stream is higher than the target, so lowest one is the nearest.
+            print_fallback_warning(quality_fallback);
+            return bandwidths.min_by_key(|x| x.1).map(|x| x.0);
         }
+        Quality::HeightRange(min, max) => {
+            let (min, max) = (*min as f64, *max as f64);
+            let scale = |stream: &MediaPlaylist| {
+                stream.resolution.map(|(_, h)| match h as f64 {
+                    h if h < min => h / min,
+                    h if h > max => h / max,
+                    _ => 1.0,
+                })
+            };
+
+            // Streams are sorted from highest to lowest quality.
+            if let Some(i) = video_streams.iter().position(|x| scale(x) == Some(1.0)) {
+                return Some(i);
+            }
+
+            return fallback_video_stream(video_streams, quality_fallback, scale);
+        }
+        Quality::Resolution(w, h) => (*w as u64, *h as u64),
+        Quality::Youtube144p => (256, 144),
+        Quality::Youtube240p => (426, 240),
+        Quality::Youtube360p => (640, 360),
+        Quality::Youtube480p => (854, 480),
+        Quality::Youtube720p => (1280, 720),
+        Quality::Youtube1080p => (1920, 1080),
+        Quality::Youtube2k => (2048, 1080),
+        Quality::Youtube1440p => (2560, 1440),
+        Quality::Youtube4k => (3840, 2160),
+        Quality::Youtube8k => (7680, 4320),
+    };
+
+    for (i, stream) in video_streams.iter().enumerate() {
+        if has_resolution.is_some() && has_height.is_some() {
+            break;
+        }
+
+        if let Some((video_w, video_h)) = &stream.resolution {
+            if h == *video_h {
+                has_height = Some(i);
+
+                if w == *video_w {
+                    has_resolution = Some(i);
+                }
+            }
+        }
+    }
+
+    if let Some(i) = has_resolution.or(has_height) {
+        return Some(i);
+    }
+
+    // Streams with different aspect ratio (e.g. 1920x800) are compared using
+    // the dimension which is nearest to the target resolution.
+    fallback_video_stream(video_streams, quality_fallback, |stream| {
+        stream.resolution.map(|(video_w, video_h)| {
+            let h_scale = video_h as f64 / h as f64;
+
+            if w == 0 {
+                h_scale
+            } else {
+                h_scale.max(video_w as f64 / w as f64)
+            }
+        })
+    })
+}
+
+/// Select stream according to fallback policy, `scale` is the ratio of stream quality to target quality.
+fn fallback_video_stream(
+    video_streams: &[MediaPlaylist],
+    quality_fallback: &QualityFallback,
+    scale: impl Fn(&MediaPlaylist) -> Option<f64>,
+) -> Option<usize> {
+    let scales = video_streams
+        .iter()
+        .enumerate()
+        .filter_map(|(i, x)| scale(x).map(|y| (i, y)))
+        .collect::<Vec<_>>();
+    // Ties are resolved in favour of streams which are ranked higher.
+    let lower = scales
+        .iter()
+        .filter(|x| x.1 <= 1.0)
+        .max_by(|x, y| x.1.total_cmp(&y.1).then(y.0.cmp(&x.0)));
+    let higher = scales
+        .iter()
+        .filter(|x| x.1 >= 1.0)
+        .min_by(|x, y| x.1.total_cmp(&y.1).then(x.0.cmp(&y.0)));
+
+    let selected = match quality_fallback {
+        QualityFallback::Fail => None,
+        QualityFallback::Higher => higher.or(lower),
+        QualityFallback::Lower => lower.or(higher),
+        QualityFallback::Nearest => scales.iter().min_by(|x, y| {
+            x.1.ln()
+                .abs()
+                .total_cmp(&y.1.ln().abs())
+                .then(x.0.cmp(&y.0))
+        }),
+    };
+
+    if selected.is_some() {
+        print_fallback_warning(quality_fallback);
+    }
+
+    selected.map(|x| x.0)
+}
+
+fn print_fallback_warning(quality_fallback: &QualityFallback) {
+    println!(
+        "    {} no stream matches --quality, selecting stream using {} fallback",
+        "Warning".colorize("bold yellow"),
+        quality_fallback
+            .to_possible_value()
+            .map(|x| x.get_name().to_owned())
+            .unwrap_or_default()
+    );
+}
+
+fn default_rendition_index(streams: &[MediaPlaylist], group_id: Option<&String>) -> Option<usize> {
+    match group_id {
+        Some(group_id) => streams
+            .iter()
+            .position(|x| x.group_id.as_ref() == Some(group_id)),
+        // Renditions which are grouped but not referenced by video stream belong to other variants.
+        None if streams.iter().any(|x| x.group_id.is_some()) => None,
+        None => Some(0),
     }
 }
 
 #[derive(Default, Serialize)]
 pub(crate) struct MediaPlaylist {
+    /// Audio describing the video for visually impaired audience.
+    pub(crate) audio_description: bool,
+    /// Group id of audio renditions which belong to this video stream.
+    pub(crate) audio_group: Option<String>,
+    pub(crate) autoselect: bool,
     pub(crate) bandwidth: Option<u64>,
     pub(crate) channels: Option<f32>,
+    /// Closed captions carried inside this video stream.
+    pub(crate) closed_captions: Vec<ClosedCaption>,
     pub(crate) codecs: Option<String>,
+    pub(crate) default: bool,
+    /// Timed metadata events (DASH `EventStream` and in-band `emsg` boxes once downloaded).
+    pub(crate) events: Vec<Event>,
     pub(crate) extension: Option<String>,
+    pub(crate) forced: bool,
     pub(crate) frame_rate: Option<f32>,
+    /// Group id of this rendition.
+    pub(crate) group_id: Option<String>,
     pub(crate) i_frame: bool,
+    /// In-band events declared by DASH playlist (`InbandEventStream`), parsed while downloading.
+    #[serde(skip)]
+    pub(crate) inband_events: Option<InbandEvents>,
+    /// Encryption declared by master playlist, known before segments are parsed.
+    #[serde(skip)]
+    pub(crate) key: Option<Key>,
     pub(crate) language: Option<String>,
     pub(crate) live: bool,
     pub(crate) media_type: MediaType,
+    pub(crate) name: Option<String>,
+    /// Content steering pathway which serves this stream.
+    pub(crate) pathway_id: Option<String>,
+    /// Redundant copies of this stream, in order of preference.
+    pub(crate) pathways: Vec<Pathway>,
     pub(crate) playlist_type: PlaylistType,
     pub(crate) resolution: Option<(u64, u64)>,
+    /// Roles of this stream (`main`, `alternate`, `commentary`, `dub`, ...).
+    pub(crate) roles: Vec<String>,
+    /// Subtitles for the deaf and hard of hearing.
+    pub(crate) sdh: bool,
     pub(crate) segments: Vec<Segment>,
+    /// Preferred start point (`EXT-X-START`) in seconds, negative values are relative to the end of playlist.
+    pub(crate) start_offset: Option<f32>,
+    /// Group id of subtitle renditions which belong to this video stream.
+    pub(crate) subtitles_group: Option<String>,
+    /// Grid (columns, rows) of thumbnails in each image of a thumbnail stream.
+    pub(crate) tiles: Option<(u64, u64)>,
     pub(crate) uri: String,
+    /// Variables defined by master playlist (`EXT-X-DEFINE`) which can be imported by this stream.
+    #[serde(skip)]
+    pub(crate) variables: HashMap<String, String>,
+    /// `SDR`, `PQ` or `HLG` (HLS `VIDEO-RANGE` or DASH transfer characteristics descriptor).
+    pub(crate) video_range: Option<String>,
 }
 
 impl MediaPlaylist {
@@ -382,6 +609,64 @@ impl MediaPlaylist {
         matches!(&self.playlist_type, PlaylistType::Hls)
     }
 
+    /// Streams which supplement the main content (commentary, audio description, forced or sdh subtitles).
+    pub(crate) fn is_supplementary(&self) -> bool {
+        self.audio_description
+            || self.forced
+            || self.sdh
+            || self.roles.iter().any(|x| {
+                matches!(
+                    x.as_str(),
+                    "alternate" | "commentary" | "description" | "supplementary"
+                )
+            })
+    }
+
+    pub(crate) fn video_codec(&self) -> Option<VideoCodec> {
+        self.codecs.as_deref().and_then(VideoCodec::parse)
+    }
+
+    pub(crate) fn dynamic_range(&self) -> DynamicRange {
+        if self.video_codec().is_some_and(|x| x.dolby_vision) {
+            DynamicRange::DolbyVision
+        } else {
+            self.video_range
+                .as_deref()
+                .and_then(DynamicRange::from_video_range)
+                .unwrap_or(DynamicRange::Sdr)
+        }
+    }
+
+    /// Track dispositions of this stream used by ffmpeg while muxing.
+    pub(crate) fn dispositions(&self) -> Vec<String> {
+        let mut dispositions = vec![];
+
+        for role in &self.roles {
+            match role.as_str() {
+                "commentary" => dispositions.push("comment"),
+                "dub" => dispositions.push("dub"),
+                "karaoke" => dispositions.push("karaoke"),
+                _ => (),
+            }
+        }
+
+        if self.forced {
+            dispositions.push("forced");
+        }
+
+        if self.sdh {
+            dispositions.push("hearing_impaired");
+        }
+
+        if self.audio_description {
+            dispositions.push("visual_impaired");
+            dispositions.push("descriptions");
+        }
+
+        dispositions.dedup();
+        dispositions.into_iter().map(|x| x.to_owned()).collect()
+    }
+
     pub(crate) fn default_kid(&self) -> Option<String> {
         if let Some(segment) = self.segments.get(0) {
             if let Some(Key {
@@ -398,12 +683,19 @@ impl MediaPlaylist {
 
     pub(crate) fn extension(&self) -> String {
         if let Some(ext) = &self.extension {
+            // Mime subtypes of unknown streams (e.g. x-emsg or ttml+xml) aren't file extensions.
+            if self.media_type == MediaType::Undefined
+                && !ext.chars().all(|x| x.is_ascii_alphanumeric())
+            {
+                return "bin".to_owned();
+            }
+
             return ext.to_owned();
         }
 
         let mut ext = match &self.playlist_type {
             PlaylistType::Hls => "ts",
-            PlaylistType::Dash => "m4s",
+            PlaylistType::Dash | PlaylistType::Smooth => "m4s",
         };
 
         if let Some(segment) = self.segments.get(0) {
@@ -446,6 +738,7 @@ impl MediaPlaylist {
 
         let prefix = match &self.media_type {
             MediaType::Audio => "vsd_audio",
+            MediaType::Image => "vsd_image",
             MediaType::Subtitles => "vsd_subtitles",
             MediaType::Undefined => "vsd_undefined",
             MediaType::Video => "vsd_video",
@@ -479,8 +772,9 @@ impl MediaPlaylist {
     pub(crate) fn display_stream(&self) -> String {
         match self.media_type {
             MediaType::Audio => self.display_audio_stream(),
+            MediaType::Image => self.display_image_stream(),
             MediaType::Subtitles => self.display_subtitle_stream(),
-            MediaType::Undefined => "".to_owned(),
+            MediaType::Undefined => self.display_undefined_stream(),
             MediaType::Video => self.display_video_stream(),
         }
         .split_whitespace()
@@ -518,10 +812,43 @@ impl MediaPlaylist {
             self.codecs.as_ref().unwrap_or(&"?".to_owned())
         );
 
+        if let Some(codec) = self.video_codec() {
+            extra += &format!(" [{}", codec.family);
+
+            if let Some(profile) = codec.profile {
+                extra += &format!(" {}", profile);
+            }
+
+            extra += "]";
+        }
+
         if let Some(frame_rate) = self.frame_rate {
             extra += &format!(", frame_rate: {}", frame_rate);
         }
 
+        let range = self.dynamic_range();
+
+        if range != DynamicRange::Sdr {
+            extra += &format!(", range: {}", range);
+        }
+
+        if !self.closed_captions.is_empty() {
+            extra += &format!(
+                ", cc: {}",
+                self.closed_captions
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+
+        extra += &self.display_key();
+
+        if !self.pathways.is_empty() {
+            extra += &format!(", pathways: {}", self.pathways.len() + 1);
+        }
+
         if self.i_frame {
             extra += ", iframe";
         }
@@ -559,6 +886,8 @@ impl MediaPlaylist {
             extra += &format!(", channels: {}", channels);
         }
 
+        extra += &self.display_rendition();
+
         if self.live {
             extra += ", live";
         }
@@ -566,6 +895,26 @@ impl MediaPlaylist {
         extra
     }
 
+    pub(crate) fn display_image_stream(&self) -> String {
+        let mut extra = format!(
+            "resolution: {}",
+            self.resolution
+                .map(|(w, h)| format!("{}x{}", w, h))
+                .unwrap_or("?".to_owned())
+        );
+
+        if let Some((columns, rows)) = self.tiles {
+            extra += &format!(", tiles: {}x{}", columns, rows);
+        }
+
+        if let Some(codecs) = &self.codecs {
+            extra += &format!(", codecs: {}", codecs);
+        }
+
+        extra += &self.display_rendition();
+        extra
+    }
+
     pub(crate) fn display_subtitle_stream(&self) -> String {
         let mut extra = format!(
             "language: {}",
@@ -576,12 +925,306 @@ impl MediaPlaylist {
             extra += &format!(", codecs: {}", codecs);
         }
 
+        extra += &self.display_rendition();
+        extra
+    }
+
+    fn display_undefined_stream(&self) -> String {
+        let mut extra = format!(
+            "codecs: {}",
+            self.codecs.as_ref().unwrap_or(&"?".to_owned())
+        );
+
+        if let Some(language) = &self.language {
+            extra += &format!(", language: {}", language);
+        }
+
+        if let Some(bandwidth) = self.bandwidth {
+            extra += &format!(
+                ", bandwidth: {}/s",
+                crate::utils::format_bytes(bandwidth as usize, 2).2
+            );
+        }
+
+        extra += &self.display_rendition();
+        extra
+    }
+
+    fn display_rendition(&self) -> String {
+        let mut extra = String::new();
+
+        if let Some(name) = &self.name {
+            extra += &format!(", name: {}", name);
+        }
+
+        if let Some(group_id) = &self.group_id {
+            extra += &format!(", group: {}", group_id);
+        }
+
+        if self.default {
+            extra += ", default";
+        }
+
+        if !self.roles.is_empty() {
+            extra += &format!(", role: {}", self.roles.join(" "));
+        }
+
+        if self.forced {
+            extra += ", forced";
+        }
+
+        if self.sdh {
+            extra += ", sdh";
+        }
+
+        if self.audio_description {
+            extra += ", audio description";
+        }
+
+        extra += &self.display_key();
+
+        if !self.pathways.is_empty() {
+            extra += &format!(", pathways: {}", self.pathways.len() + 1);
+        }
+
+        extra
+    }
+
+    fn display_key(&self) -> String {
+        let mut extra = String::new();
+
+        if let Some(Key {
+            method: KeyMethod::Cenc,
+            key_ids,
+            scheme,
+            ..
+        }) = self
+            .key
+            .as_ref()
+            .or_else(|| self.segments.first().and_then(|x| x.key.as_ref()))
+        {
+            extra += &format!(", drm: {}", scheme.as_deref().unwrap_or("cenc"));
+
+            if !key_ids.is_empty() {
+                extra += &format!(
+                    ", kids: {}",
+                    key_ids
+                        .iter()
+                        .map(|x| x.uuid())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            } else if let Some(default_kid) = self.default_kid() {
+                extra += &format!(", kid: {}", default_kid);
+            }
+        }
+
         extra
     }
 
+    /// Urls of a resource of this stream on redundant pathways, in order of preference.
+    /// Resources relative to the playlist are rebased on the pathway's playlist and
+    /// absolute resources on the same host have their host swapped.
+    pub(crate) fn pathway_urls(&self, url: &Url) -> Vec<Url> {
+        let Ok(primary) = self.uri.parse::<Url>() else {
+            return vec![];
+        };
+        let Ok(primary_directory) = primary.join(".") else {
+            return vec![];
+        };
+
+        self.pathways
+            .iter()
+            .filter_map(|pathway| {
+                let pathway_url = pathway.uri.parse::<Url>().ok()?;
+
+                if let Some(relative) = url.as_str().strip_prefix(primary_directory.as_str()) {
+                    return pathway_url.join(relative).ok();
+                }
+
+                if url.origin() == primary.origin() {
+                    let mut url = url.clone();
+                    url.set_scheme(pathway_url.scheme()).ok()?;
+                    url.set_host(pathway_url.host_str()).ok()?;
+                    url.set_port(pathway_url.port()).ok()?;
+                    return Some(url);
+                }
+
+                None
+            })
+            .filter(|x| x != url)
+            .collect()
+    }
+
+    /// Drop segments which are marked as a part of an ad break.
+    /// Map and key of a dropped segment are moved to the next kept segment,
+    /// and discontinuities surrounding an ad break are dropped along with it.
+    /// Events inside ad breaks are dropped and later events are moved back in time.
+    pub(crate) fn skip_ads(&mut self) {
+        let mut ad_breaks = vec![];
+        let mut time = 0.0;
+
+        for segment in &self.segments {
+            let duration = segment.duration as f64;
+
+            if segment.ad {
+                ad_breaks.push((time, time + duration));
+            }
+
+            time += duration;
+        }
+
+        self.events.retain_mut(|event| {
+            if ad_breaks
+                .iter()
+                .any(|(start, end)| *start <= event.start_time && event.start_time < *end)
+            {
+                return false;
+            }
+
+            event.start_time -= ad_breaks
+                .iter()
+                .filter(|(_, end)| *end <= event.start_time)
+                .map(|(start, end)| end - start)
+                .sum::<f64>();
+            true
+        });
+
+        self.drop_segments(|_, x| x.ad, true);
+    }
+
+    /// Drop segments which are marked as unavailable. Segments after a gap start a new
+    /// discontinuity and keep the duration of gap, so that it is padded when parts are joined.
+    pub(crate) fn skip_gaps(&mut self) {
+        let mut gap = 0.0;
+        let mut offset = 0;
+
+        for segment in &mut self.segments {
+            if segment.gap {
+                gap += segment.duration;
+                continue;
+            }
+
+            if gap > 0.0 {
+                segment.gap_before = gap;
+                offset += 1;
+                gap = 0.0;
+            }
+
+            segment.discontinuity_sequence += offset;
+        }
+
+        self.drop_segments(|_, x| x.gap, false);
+    }
+
+    /// Drop segments before the preferred start point (`EXT-X-START`) of playlist.
+    pub(crate) fn clip_to_start(&mut self) {
+        if let Some(index) = self.start_index() {
+            self.drop_segments(|i, _| i < index, false);
+        }
+    }
+
+    /// Index of segment which contains the preferred start point.
+    pub(crate) fn start_index(&self) -> Option<usize> {
+        let start_offset = self.start_offset?;
+        let duration = self.segments.iter().map(|x| x.duration).sum::<f32>();
+        let mut time = if start_offset < 0.0 {
+            (duration + start_offset).max(0.0)
+        } else {
+            start_offset.min(duration)
+        };
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            if time < segment.duration {
+                return Some(i);
+            }
+
+            time -= segment.duration;
+        }
+
+        self.segments.len().checked_sub(1)
+    }
+
+    /// Map and key of a dropped segment are moved to the next kept segment.
+    /// Discontinuities surrounding dropped segments are joined when `join_discontinuities` is true.
+    fn drop_segments(
+        &mut self,
+        drop: impl Fn(usize, &Segment) -> bool,
+        join_discontinuities: bool,
+    ) {
+        let mut segments = vec![];
+        let mut map = None;
+        let mut key = None;
+        let mut offset = 0;
+        let mut previous_sequence = None;
+        let mut skipped = false;
+
+        for (i, mut segment) in self.segments.drain(..).enumerate() {
+            if drop(i, &segment) {
+                if segment.map.is_some() {
+                    map = segment.map.take();
+                }
+
+                if segment.key.is_some() {
+                    key = segment.key.take();
+                }
+
+                skipped = join_discontinuities;
+                continue;
+            }
+
+            if segment.map.is_none() {
+                segment.map = map.take();
+            } else {
+                map = None;
+            }
+
+            if segment.key.is_none() {
+                segment.key = key.take();
+            } else {
+                key = None;
+            }
+
+            if skipped {
+                if let Some(previous_sequence) = previous_sequence {
+                    offset += segment.discontinuity_sequence - previous_sequence;
+                }
+
+                skipped = false;
+            }
+
+            previous_sequence = Some(segment.discontinuity_sequence);
+            segment.discontinuity_sequence -= offset;
+            segments.push(segment);
+        }
+
+        self.segments = segments;
+    }
+
+    /// Ranges of segments which share the same discontinuity sequence.
+    pub(crate) fn discontinuities(&self) -> Vec<std::ops::Range<usize>> {
+        let mut ranges = vec![];
+        let mut start = 0;
+
+        for i in 1..self.segments.len() {
+            if self.segments[i].discontinuity_sequence
+                != self.segments[i - 1].discontinuity_sequence
+            {
+                ranges.push(start..i);
+                start = i;
+            }
+        }
+
+        if start < self.segments.len() {
+            ranges.push(start..self.segments.len());
+        }
+
+        ranges
+    }
+
     pub(crate) fn add_query(&mut self, query: &str) {
         for segment in &mut self.segments {
-            if let Some(map) = &mut segment.map {
+            if let Some(map) = segment.map.as_mut().filter(|x| !x.uri.starts_with("data:")) {
                 let mut uri = map.uri.clone();
 
                 if uri.ends_with("??") || !uri.contains('?') {
@@ -594,6 +1237,11 @@ impl MediaPlaylist {
                 map.uri = uri;
             }
 
+            // Query can't be added to embedded resources.
+            if segment.uri.starts_with("data:") {
+                continue;
+            }
+
             let mut uri = segment.uri.clone();
 
             if uri.ends_with("??") || !uri.contains('?') {
@@ -608,16 +1256,105 @@ impl MediaPlaylist {
     }
 }
 
+/// CEA-608/708 caption channel embedded in video stream.
+#[derive(Clone, Serialize)]
+pub(crate) struct ClosedCaption {
+    /// `CC1` to `CC4` or `SERVICE1` to `SERVICE63`.
+    pub(crate) channel: String,
+    pub(crate) language: Option<String>,
+    pub(crate) name: Option<String>,
+}
+
+impl Display for ClosedCaption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.channel)?;
+
+        if let Some(language) = &self.language {
+            write!(f, "({})", language)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Timed metadata event (DASH `EventStream` or in-band `emsg` box).
+#[derive(Clone, Serialize)]
+pub(crate) struct Event {
+    /// Duration in seconds, unknown when none.
+    pub(crate) duration: Option<f64>,
+    pub(crate) id: Option<String>,
+    /// Message data, base64 encoded for `emsg` boxes.
+    pub(crate) message_data: Option<String>,
+    pub(crate) scheme_id_uri: String,
+    /// Presentation time in seconds, relative to the start of stream.
+    pub(crate) start_time: f64,
+    pub(crate) value: Option<String>,
+}
+
+impl Event {
+    /// Event signals an ad break (SCTE-35 splice).
+    pub(crate) fn is_ad(&self) -> bool {
+        self.scheme_id_uri.starts_with("urn:scte:scte35:")
+    }
+
+    /// Title of chapter which starts with this event.
+    pub(crate) fn title(&self) -> String {
+        if self.is_ad() {
+            return "Ad break".to_owned();
+        }
+
+        self.value
+            .clone()
+            .filter(|x| !x.is_empty())
+            .or(self.id.clone())
+            .unwrap_or(self.scheme_id_uri.clone())
+    }
+
+    /// Whether `time` (in seconds) falls inside this event. A little tolerance is given
+    /// at the start, as period and event times don't always match exactly.
+    pub(crate) fn contains(&self, time: f64) -> bool {
+        self.duration
+            .is_some_and(|x| self.start_time - 0.5 <= time && time < self.start_time + x)
+    }
+
+    /// Whether `other` is a repetition of the same event (e.g. from another stream or segment).
+    pub(crate) fn is_same(&self, other: &Event) -> bool {
+        self.scheme_id_uri == other.scheme_id_uri
+            && self.value == other.value
+            && self.id == other.id
+            && (self.id.is_some() || (self.start_time - other.start_time).abs() < 0.001)
+    }
+}
+
+/// In-band events (`emsg` boxes) declared by a DASH stream.
+#[derive(Clone, Default)]
+pub(crate) struct InbandEvents {
+    /// Offset (in seconds) from media timeline to stream timeline of each discontinuity (period).
+    pub(crate) offsets: Vec<f64>,
+    /// Schemes of events carried in segments, other events are ignored.
+    pub(crate) schemes: Vec<String>,
+}
+
+/// Redundant copy of a stream served from another location.
+#[derive(Clone, Serialize)]
+pub(crate) struct Pathway {
+    /// `PATHWAY-ID` of HLS content steering.
+    pub(crate) id: Option<String>,
+    pub(crate) uri: String,
+}
+
 #[derive(Default, Serialize)]
 pub(crate) enum PlaylistType {
     Dash,
     #[default]
     Hls,
+    Smooth,
 }
 
 #[derive(Clone, Default, PartialEq, Serialize)]
 pub(crate) enum MediaType {
     Audio,
+    Image,
     Subtitles,
     #[default]
     Undefined,
@@ -631,6 +1368,7 @@ impl Display for MediaType {
             "{}",
             match self {
                 Self::Audio => "audio",
+                Self::Image => "image",
                 Self::Subtitles => "subtitles",
                 Self::Undefined => "undefined",
                 Self::Video => "video",
@@ -656,7 +1394,11 @@ pub(crate) struct Range {
 
 impl Range {
     pub(crate) fn as_header_value(&self) -> HeaderValue {
-        HeaderValue::from_str(&format!("bytes={}-{}", self.start, self.end)).unwrap()
+        if self.end == u64::MAX {
+            HeaderValue::from_str(&format!("bytes={}-", self.start)).unwrap()
+        } else {
+            HeaderValue::from_str(&format!("bytes={}-{}", self.start, self.end)).unwrap()
+        }
     }
 }
 
@@ -680,15 +1422,210 @@ pub(crate) struct Key {
     pub(crate) default_kid: Option<String>,
     pub(crate) iv: Option<String>,
     pub(crate) key_format: Option<String>,
+    /// Key ids declared by playlist (`cenc:pssh` and `mspr:pro` elements of DASH playlists).
+    pub(crate) key_ids: Vec<KeyId>,
     pub(crate) method: KeyMethod,
+    /// DRM data declared by playlist as (system, data) i.e. widevine `pssh` boxes and playready objects.
+    #[serde(skip)]
+    pub(crate) psshs: Vec<(String, Vec<u8>)>,
+    /// Common encryption scheme (`cenc`, `cbcs`, `cens` or `cbc1`).
+    pub(crate) scheme: Option<String>,
     pub(crate) uri: Option<String>,
 }
 
+#[derive(Clone, Serialize)]
+pub(crate) struct KeyId {
+    pub(crate) system: String,
+    /// In hex (lowercase, without dashes).
+    pub(crate) value: String,
+}
+
+impl KeyId {
+    pub(crate) fn uuid(&self) -> String {
+        if self.value.len() != 32 {
+            return self.value.to_owned();
+        }
+
+        format!(
+            "{}-{}-{}-{}-{}",
+            &self.value[..8],
+            &self.value[8..12],
+            &self.value[12..16],
+            &self.value[16..20],
+            &self.value[20..]
+        )
+    }
+}
+
 #[derive(Clone, Default, Serialize)]
 pub(crate) struct Segment {
     pub(crate) range: Option<Range>,
+    pub(crate) ad: bool,
+    pub(crate) discontinuity_sequence: u64,
     pub(crate) duration: f32, // consider changing it to f64
+    /// Segment is unavailable (`EXT-X-GAP`).
+    pub(crate) gap: bool,
+    /// Duration (in seconds) of unavailable segments which were skipped before this segment.
+    pub(crate) gap_before: f32,
     pub(crate) key: Option<Key>,
     pub(crate) map: Option<Map>,
     pub(crate) uri: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Map, MasterPlaylist, MediaPlaylist, Segment, StreamPreferences};
+    use crate::{
+        commands::{Quality, QualityFallback},
+        filter::StreamFilters,
+    };
+
+    fn playlist(segments: &[(f32, bool)]) -> MediaPlaylist {
+        MediaPlaylist {
+            segments: segments
+                .iter()
+                .enumerate()
+                .map(|(i, (duration, gap))| Segment {
+                    duration: *duration,
+                    gap: *gap,
+                    uri: i.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn gaps_start_discontinuities() {
+        let mut playlist = playlist(&[
+            (4.0, false),
+            (4.0, true),
+            (2.0, true),
+            (4.0, false),
+            (4.0, false),
+            (4.0, true),
+        ]);
+        playlist.skip_gaps();
+
+        assert_eq!(
+            playlist
+                .segments
+                .iter()
+                .map(|x| (x.uri.as_str(), x.discontinuity_sequence, x.gap_before))
+                .collect::<Vec<_>>(),
+            [("0", 0, 0.0), ("3", 1, 6.0), ("4", 1, 0.0)]
+        );
+        assert_eq!(playlist.discontinuities(), [0..1, 1..3]);
+    }
+
+    #[test]
+    fn start_index() {
+        let mut playlist = playlist(&[(4.0, false), (4.0, false), (4.0, false)]);
+        assert_eq!(playlist.start_index(), None);
+
+        playlist.start_offset = Some(5.0);
+        assert_eq!(playlist.start_index(), Some(1));
+
+        playlist.start_offset = Some(-2.0);
+        assert_eq!(playlist.start_index(), Some(2));
+
+        playlist.clip_to_start();
+        assert_eq!(playlist.segments.len(), 1);
+    }
+
+    #[test]
+    fn skip_ads() {
+        let mut playlist = playlist(&[(4.0, false); 5]);
+
+        for (segment, (ad, discontinuity_sequence)) in playlist.segments.iter_mut().zip([
+            (false, 0),
+            (true, 1),
+            (true, 1),
+            (false, 2),
+            (false, 2),
+        ]) {
+            segment.ad = ad;
+            segment.discontinuity_sequence = discontinuity_sequence;
+        }
+
+        playlist.segments[1].map = Some(Map {
+            range: None,
+            uri: "init.mp4".to_owned(),
+        });
+        playlist.events = [2.0, 6.0, 14.0]
+            .into_iter()
+            .map(|start_time| Event {
+                duration: None,
+                id: None,
+                message_data: None,
+                scheme_id_uri: "urn:example".to_owned(),
+                start_time,
+                value: None,
+            })
+            .collect();
+        playlist.skip_ads();
+
+        assert_eq!(
+            playlist
+                .segments
+                .iter()
+                .map(|x| (x.uri.as_str(), x.discontinuity_sequence))
+                .collect::<Vec<_>>(),
+            [("0", 0), ("3", 0), ("4", 0)]
+        );
+        assert_eq!(playlist.discontinuities().len(), 1);
+        assert_eq!(
+            playlist.segments[1].map.as_ref().map(|x| x.uri.as_str()),
+            Some("init.mp4")
+        );
+        assert_eq!(
+            playlist
+                .events
+                .iter()
+                .map(|x| x.start_time)
+                .collect::<Vec<_>>(),
+            [2.0, 6.0]
+        );
+    }
+
+    fn selected_uris(playlist: MasterPlaylist, i_frames: bool) -> Vec<String> {
+        playlist
+            .sort_streams(&StreamPreferences::default())
+            .select_streams(
+                Quality::Highest,
+                QualityFallback::Nearest,
+                &StreamFilters::default(),
+                i_frames,
+                true,
+                false,
+            )
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|x| x.uri)
+            .collect()
+    }
+
+    #[test]
+    fn i_frame_streams_selection() {
+        let m3u8 = m3u8_rs::parse_master_playlist_res(
+            br#"#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",LANGUAGE="en",DEFAULT=YES,URI="audio.m3u8"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=300000,RESOLUTION=1920x1080,URI="iframes_1080p.m3u8"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=100000,RESOLUTION=1280x720,URI="iframes_720p.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,RESOLUTION=1280x720,AUDIO="aac"
+720p.m3u8
+"#,
+        )
+        .unwrap();
+        let playlist = || crate::hls::parse_as_master(&m3u8, "https://example.com/master.m3u8");
+
+        // Higher resolution I-frame streams are never pre-selected in place of normal video streams.
+        assert_eq!(
+            selected_uris(playlist(), false),
+            ["720p.m3u8", "audio.m3u8"]
+        );
+        assert_eq!(selected_uris(playlist(), true), ["iframes_1080p.m3u8"]);
+    }
+}
@@ -0,0 +1,689 @@
+/*
+    REFERENCES
+    ----------
+
+    1. ISO/IEC 14496-12 (ISO base media file format)
+    2. ISO/IEC 14496-15 Section 5.3.3 (avcC) and Section 8.3.3 (hvcC)
+    3. ISO/IEC 14496-14 Section 3.1.2 (esds)
+    4. ETSI TS 102 366 Annex F.6 (dec3)
+    5. ISO/IEC 23001-7 Section 8.2 (tenc)
+    6. https://github.com/Dash-Industry-Forum/dash.js/blob/development/src/mss/MssFragmentMoovProcessor.js
+    7. https://github.com/nilaoda/N_m3u8DL-RE/blob/main/src/N_m3u8DL-RE.Parser/Mp4/MSSMoovProcessor.cs
+
+    Smooth Streaming fragments only contain a `moof` and a `mdat` box, decoders get the
+    `moov` box from an init segment which is synthesized here from the `QualityLevel`
+    attributes. Fragments are patched to use the track id of the synthesized init segment.
+
+*/
+
+use anyhow::{anyhow, bail, Result};
+
+/// Track id used in synthesized init segments.
+pub(crate) const TRACK_ID: u32 = 1;
+
+const MATRIX: [u32; 9] = [0x00010000, 0, 0, 0, 0x00010000, 0, 0, 0, 0x40000000];
+const SAMPLING_FREQUENCIES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum Codec {
+    Aac,
+    Avc,
+    Eac3,
+    Hevc,
+    Ttml,
+}
+
+impl Codec {
+    pub(super) fn from_fourcc(fourcc: &str) -> Option<Self> {
+        match fourcc.to_uppercase().as_str() {
+            "AACL" | "AACH" | "MP4A" => Some(Self::Aac),
+            "AVC1" | "DAVC" | "H264" => Some(Self::Avc),
+            "EC-3" | "EAC3" => Some(Self::Eac3),
+            "HEV1" | "HEVC" | "HVC1" | "H265" => Some(Self::Hevc),
+            "DFXP" | "TTML" => Some(Self::Ttml),
+            _ => None,
+        }
+    }
+}
+
+pub(super) struct Track<'a> {
+    pub(super) bitrate: u32,
+    pub(super) channels: u16,
+    pub(super) codec: Codec,
+    pub(super) codec_private_data: &'a [u8],
+    pub(super) duration: u64,
+    pub(super) height: u16,
+    /// Default key id of an encrypted track.
+    pub(super) kid: Option<[u8; 16]>,
+    /// ISO 639-2/T language code.
+    pub(super) language: Option<&'a str>,
+    pub(super) nal_unit_length: u8,
+    /// System id and data of `pssh` box.
+    pub(super) pssh: Option<([u8; 16], &'a [u8])>,
+    pub(super) sampling_rate: u32,
+    pub(super) timescale: u64,
+    pub(super) width: u16,
+}
+
+impl Track<'_> {
+    /// RFC 6381 codecs string of this track.
+    pub(super) fn codecs(&self) -> Result<String> {
+        Ok(match self.codec {
+            Codec::Aac => format!("mp4a.40.{}", self.audio_specific_config()[0] >> 3),
+            Codec::Avc => {
+                let sps = self.parameter_sets(|x| x[0] & 0x1f, &[7])?.remove(0).1;
+                format!("avc1.{}", hex::encode(sps.get(1..4).unwrap_or_default()))
+            }
+            Codec::Eac3 => "ec-3".to_owned(),
+            Codec::Hevc => {
+                let (ptl, _) = self.profile_tier_level()?;
+                let compatibility = u32::from_be_bytes(ptl[1..5].try_into().unwrap());
+                let mut constraints = ptl[5..11].to_vec();
+
+                while constraints.last() == Some(&0) {
+                    constraints.pop();
+                }
+
+                format!(
+                    "hvc1.{}{}.{:X}.{}{}{}",
+                    ["", "A", "B", "C"][(ptl[0] >> 6) as usize],
+                    ptl[0] & 0x1f,
+                    compatibility.reverse_bits(),
+                    if ptl[0] & 0x20 == 0 { 'L' } else { 'H' },
+                    ptl[11],
+                    constraints
+                        .iter()
+                        .map(|x| format!(".{:X}", x))
+                        .collect::<String>()
+                )
+            }
+            Codec::Ttml => "stpp".to_owned(),
+        })
+    }
+
+    /// Init segment (`ftyp` and `moov` boxes) of this track.
+    pub(super) fn init_segment(&self) -> Result<Vec<u8>> {
+        let mut init = mp4_box(
+            b"ftyp",
+            &[&b"isml"[..], &1_u32.to_be_bytes(), b"piffiso6isom"].concat(),
+        );
+
+        let mut moov = self.mvhd();
+        moov.extend(self.trak()?);
+        moov.extend(mp4_box(
+            b"mvex",
+            &full_box(
+                b"trex",
+                0,
+                0,
+                &[TRACK_ID, 1, 0, 0, 0]
+                    .iter()
+                    .flat_map(|x| x.to_be_bytes())
+                    .collect::<Vec<_>>(),
+            ),
+        ));
+
+        if let Some((system_id, data)) = &self.pssh {
+            moov.extend(full_box(
+                b"pssh",
+                0,
+                0,
+                &[&system_id[..], &(data.len() as u32).to_be_bytes(), data].concat(),
+            ));
+        }
+
+        init.extend(mp4_box(b"moov", &moov));
+        Ok(init)
+    }
+
+    fn mvhd(&self) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(0_u64.to_be_bytes()); // creation_time
+        data.extend(0_u64.to_be_bytes()); // modification_time
+        data.extend((self.timescale as u32).to_be_bytes());
+        data.extend(self.duration.to_be_bytes());
+        data.extend(0x00010000_u32.to_be_bytes()); // rate
+        data.extend(0x0100_u16.to_be_bytes()); // volume
+        data.extend([0; 10]); // reserved
+        data.extend(MATRIX.iter().flat_map(|x| x.to_be_bytes()));
+        data.extend([0; 24]); // pre_defined
+        data.extend((TRACK_ID + 1).to_be_bytes()); // next_track_ID
+        full_box(b"mvhd", 1, 0, &data)
+    }
+
+    fn trak(&self) -> Result<Vec<u8>> {
+        let mut tkhd = vec![];
+        tkhd.extend(0_u64.to_be_bytes()); // creation_time
+        tkhd.extend(0_u64.to_be_bytes()); // modification_time
+        tkhd.extend(TRACK_ID.to_be_bytes());
+        tkhd.extend([0; 4]); // reserved
+        tkhd.extend(self.duration.to_be_bytes());
+        tkhd.extend([0; 8]); // reserved
+        tkhd.extend([0; 4]); // layer and alternate_group
+        tkhd.extend(if self.codec == Codec::Aac || self.codec == Codec::Eac3 {
+            [0x01, 0x00] // volume
+        } else {
+            [0, 0]
+        });
+        tkhd.extend([0; 2]); // reserved
+        tkhd.extend(MATRIX.iter().flat_map(|x| x.to_be_bytes()));
+        tkhd.extend(((self.width as u32) << 16).to_be_bytes());
+        tkhd.extend(((self.height as u32) << 16).to_be_bytes());
+
+        let mut mdhd = vec![];
+        mdhd.extend(0_u64.to_be_bytes()); // creation_time
+        mdhd.extend(0_u64.to_be_bytes()); // modification_time
+        mdhd.extend((self.timescale as u32).to_be_bytes());
+        mdhd.extend(self.duration.to_be_bytes());
+        mdhd.extend(language_code(self.language).to_be_bytes());
+        mdhd.extend([0; 2]); // pre_defined
+
+        let (handler_type, name, media_header) = match self.codec {
+            Codec::Aac | Codec::Eac3 => (b"soun", "SoundHandler", full_box(b"smhd", 0, 0, &[0; 4])),
+            Codec::Avc | Codec::Hevc => (b"vide", "VideoHandler", full_box(b"vmhd", 0, 1, &[0; 8])),
+            Codec::Ttml => (b"subt", "SubtitleHandler", full_box(b"sthd", 0, 0, &[])),
+        };
+
+        let hdlr = full_box(
+            b"hdlr",
+            0,
+            0,
+            &[&[0; 4][..], handler_type, &[0; 12], name.as_bytes(), &[0]].concat(),
+        );
+
+        let dinf = mp4_box(
+            b"dinf",
+            &full_box(
+                b"dref",
+                0,
+                0,
+                &[&1_u32.to_be_bytes()[..], &full_box(b"url ", 0, 1, &[])].concat(),
+            ),
+        );
+
+        let stbl = mp4_box(
+            b"stbl",
+            &[
+                full_box(
+                    b"stsd",
+                    0,
+                    0,
+                    &[&1_u32.to_be_bytes()[..], &self.sample_entry()?].concat(),
+                ),
+                full_box(b"stts", 0, 0, &[0; 4]),
+                full_box(b"stsc", 0, 0, &[0; 4]),
+                full_box(b"stsz", 0, 0, &[0; 8]),
+                full_box(b"stco", 0, 0, &[0; 4]),
+            ]
+            .concat(),
+        );
+
+        let minf = mp4_box(b"minf", &[media_header, dinf, stbl].concat());
+        let mdia = mp4_box(
+            b"mdia",
+            &[full_box(b"mdhd", 1, 0, &mdhd), hdlr, minf].concat(),
+        );
+
+        Ok(mp4_box(
+            b"trak",
+            &[full_box(b"tkhd", 1, 7, &tkhd), mdia].concat(),
+        ))
+    }
+
+    fn sample_entry(&self) -> Result<Vec<u8>> {
+        // reserved and data_reference_index
+        let mut data = vec![0, 0, 0, 0, 0, 0, 0, 1];
+
+        let (format, config) = match self.codec {
+            Codec::Aac | Codec::Eac3 => {
+                data.extend([0; 8]); // reserved
+                data.extend(self.channels.to_be_bytes());
+                data.extend(16_u16.to_be_bytes()); // samplesize
+                data.extend([0; 4]); // pre_defined and reserved
+                data.extend((self.sampling_rate.min(u16::MAX as u32) << 16).to_be_bytes());
+
+                if self.codec == Codec::Aac {
+                    (b"mp4a", self.esds())
+                } else {
+                    (b"ec-3", self.dec3())
+                }
+            }
+            Codec::Avc | Codec::Hevc => {
+                data.extend([0; 16]); // pre_defined and reserved
+                data.extend(self.width.to_be_bytes());
+                data.extend(self.height.to_be_bytes());
+                data.extend(0x00480000_u32.to_be_bytes()); // horizresolution
+                data.extend(0x00480000_u32.to_be_bytes()); // vertresolution
+                data.extend([0; 4]); // reserved
+                data.extend(1_u16.to_be_bytes()); // frame_count
+                data.extend([0; 32]); // compressorname
+                data.extend(0x0018_u16.to_be_bytes()); // depth
+                data.extend((-1_i16).to_be_bytes()); // pre_defined
+
+                if self.codec == Codec::Avc {
+                    (b"avc1", self.avcc()?)
+                } else {
+                    (b"hvc1", self.hvcc()?)
+                }
+            }
+            Codec::Ttml => {
+                data.extend(b"http://www.w3.org/ns/ttml\0"); // namespace
+                data.extend([0; 2]); // schema_location and auxiliary_mime_types
+                return Ok(mp4_box(b"stpp", &data));
+            }
+        };
+
+        data.extend(config);
+
+        let Some(kid) = &self.kid else {
+            return Ok(mp4_box(format, &data));
+        };
+
+        let mut tenc = vec![0, 0, 1, 8]; // reserved, default_isProtected and default_Per_Sample_IV_Size
+        tenc.extend(kid);
+
+        data.extend(mp4_box(
+            b"sinf",
+            &[
+                mp4_box(b"frma", format),
+                full_box(
+                    b"schm",
+                    0,
+                    0,
+                    &[&b"cenc"[..], &0x00010000_u32.to_be_bytes()].concat(),
+                ),
+                mp4_box(b"schi", &full_box(b"tenc", 0, 0, &tenc)),
+            ]
+            .concat(),
+        ));
+
+        Ok(mp4_box(
+            if self.codec == Codec::Aac || self.codec == Codec::Eac3 {
+                b"enca"
+            } else {
+                b"encv"
+            },
+            &data,
+        ))
+    }
+
+    /// Audio specific config of AAC, generated from sampling rate and channels when
+    /// codec private data is missing.
+    fn audio_specific_config(&self) -> Vec<u8> {
+        if self.codec_private_data.len() >= 2 {
+            return self.codec_private_data.to_vec();
+        }
+
+        let index = SAMPLING_FREQUENCIES
+            .iter()
+            .position(|x| *x == self.sampling_rate)
+            .unwrap_or(3) as u8;
+        let object_type = 2; // AAC LC
+
+        vec![
+            (object_type << 3) | (index >> 1),
+            ((index & 1) << 7) | ((self.channels as u8 & 0x0f) << 3),
+        ]
+    }
+
+    fn esds(&self) -> Vec<u8> {
+        let mut decoder_config = vec![0x40, 0x15, 0, 0, 0]; // object type, stream type and bufferSizeDB
+        decoder_config.extend(self.bitrate.to_be_bytes()); // maxBitrate
+        decoder_config.extend(self.bitrate.to_be_bytes()); // avgBitrate
+        decoder_config.extend(descriptor(0x05, &self.audio_specific_config()));
+
+        let mut es = vec![];
+        es.extend((TRACK_ID as u16).to_be_bytes());
+        es.push(0); // flags
+        es.extend(descriptor(0x04, &decoder_config));
+        es.extend(descriptor(0x06, &[0x02]));
+
+        full_box(b"esds", 0, 0, &descriptor(0x03, &es))
+    }
+
+    fn dec3(&self) -> Vec<u8> {
+        let fscod = match self.sampling_rate {
+            44100 => 1,
+            32000 => 2,
+            _ => 0,
+        };
+        let (acmod, lfeon) = match self.channels {
+            1 => (1, 0),
+            2 => (2, 0),
+            3 => (3, 0),
+            6 => (7, 1),
+            _ => (7, 0),
+        };
+        let data_rate = (self.bitrate / 1000).min(0x1fff) as u16;
+
+        mp4_box(
+            b"dec3",
+            &[
+                (data_rate >> 5) as u8,
+                ((data_rate & 0x1f) << 3) as u8, // num_ind_sub = 0 (one substream)
+                (fscod << 6) | (16 << 1),        // bsid = 16
+                (acmod << 1) | lfeon,            // asvc = 0, bsmod = 0
+                0,                               // num_dep_sub = 0
+            ],
+        )
+    }
+
+    fn avcc(&self) -> Result<Vec<u8>> {
+        let sps = self.parameter_sets(|x| x[0] & 0x1f, &[7])?;
+        let pps = self.parameter_sets(|x| x[0] & 0x1f, &[8])?;
+        let profile = sps[0].1.get(1..4).unwrap_or(&[0; 3]);
+
+        let mut data = vec![1, profile[0], profile[1], profile[2]];
+        data.push(0xfc | (self.nal_unit_length.saturating_sub(1) & 0x03));
+        data.push(0xe0 | sps.len() as u8);
+
+        for (_, nal) in &sps {
+            data.extend((nal.len() as u16).to_be_bytes());
+            data.extend(*nal);
+        }
+
+        data.push(pps.len() as u8);
+
+        for (_, nal) in &pps {
+            data.extend((nal.len() as u16).to_be_bytes());
+            data.extend(*nal);
+        }
+
+        Ok(mp4_box(b"avcC", &data))
+    }
+
+    fn hvcc(&self) -> Result<Vec<u8>> {
+        let (ptl, sps_header) = self.profile_tier_level()?;
+        let max_sub_layers = ((sps_header >> 1) & 0x07) + 1;
+        let temporal_id_nested = sps_header & 0x01;
+        // Main 10 profile, chroma format and bit depths are read by decoders from SPS anyway.
+        let bit_depth_minus8 = if ptl[0] & 0x1f == 2 { 2 } else { 0 };
+
+        let mut data = vec![1];
+        data.extend(&ptl);
+        data.extend(0xf000_u16.to_be_bytes()); // min_spatial_segmentation_idc
+        data.push(0xfc); // parallelismType
+        data.push(0xfd); // chroma_format_idc = 1 (4:2:0)
+        data.push(0xf8 | bit_depth_minus8);
+        data.push(0xf8 | bit_depth_minus8);
+        data.extend([0; 2]); // avgFrameRate
+        data.push(
+            (max_sub_layers << 3)
+                | (temporal_id_nested << 2)
+                | (self.nal_unit_length.saturating_sub(1) & 0x03),
+        );
+
+        let nals = self.parameter_sets(|x| (x[0] >> 1) & 0x3f, &[32, 33, 34])?;
+        let types = [32, 33, 34]
+            .into_iter()
+            .filter(|x| nals.iter().any(|y| y.0 == *x))
+            .collect::<Vec<_>>();
+
+        data.push(types.len() as u8);
+
+        for nal_type in types {
+            let nals = nals.iter().filter(|x| x.0 == nal_type).collect::<Vec<_>>();
+            data.push(0x80 | nal_type); // array_completeness
+            data.extend((nals.len() as u16).to_be_bytes());
+
+            for (_, nal) in nals {
+                data.extend((nal.len() as u16).to_be_bytes());
+                data.extend(*nal);
+            }
+        }
+
+        Ok(mp4_box(b"hvcC", &data))
+    }
+
+    /// General profile, tier and level (12 bytes) and first byte after nal unit header of HEVC SPS.
+    fn profile_tier_level(&self) -> Result<(Vec<u8>, u8)> {
+        let sps = self.parameter_sets(|x| (x[0] >> 1) & 0x3f, &[33])?;
+        let rbsp = rbsp(sps[0].1);
+
+        if rbsp.len() < 15 {
+            bail!("HEVC sequence parameter set in codec private data is too short.");
+        }
+
+        Ok((rbsp[3..15].to_vec(), rbsp[2]))
+    }
+
+    /// Nal units (with their types) of codec private data whose types are in `types`.
+    fn parameter_sets(&self, nal_type: fn(&[u8]) -> u8, types: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+        let nals = split_nal_units(self.codec_private_data)
+            .into_iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| (nal_type(x), x))
+            .filter(|x| types.contains(&x.0))
+            .collect::<Vec<_>>();
+
+        if nals.is_empty() {
+            return Err(anyhow!(
+                "codec private data doesn't contain nal units of type {:?}.",
+                types
+            ));
+        }
+
+        Ok(nals)
+    }
+}
+
+/// Set track id of all `tfhd` boxes inside `moof` boxes of a fragment.
+pub(crate) fn patch_track_id(data: &mut [u8], track_id: u32) {
+    for (name, start, end) in child_boxes(data, 0, data.len()) {
+        if &name != b"moof" {
+            continue;
+        }
+
+        for (name, start, end) in child_boxes(data, start, end) {
+            if &name != b"traf" {
+                continue;
+            }
+
+            for (name, start, _) in child_boxes(data, start, end) {
+                // Track id follows version and flags.
+                if &name == b"tfhd" && start + 8 <= data.len() {
+                    data[(start + 4)..(start + 8)].copy_from_slice(&track_id.to_be_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// Name, payload start and end of boxes between `start` and `end`.
+fn child_boxes(data: &[u8], mut start: usize, end: usize) -> Vec<([u8; 4], usize, usize)> {
+    let mut boxes = vec![];
+
+    while start + 8 <= end {
+        let size = u32::from_be_bytes(data[start..(start + 4)].try_into().unwrap()) as usize;
+        let name = data[(start + 4)..(start + 8)].try_into().unwrap();
+
+        let (header, size) = match size {
+            0 => (8, end - start),
+            1 if start + 16 <= end => (
+                16,
+                u64::from_be_bytes(data[(start + 8)..(start + 16)].try_into().unwrap()) as usize,
+            ),
+            _ => (8, size),
+        };
+
+        if size < header || start + size > end {
+            break;
+        }
+
+        boxes.push((name, start + header, start + size));
+        start += size;
+    }
+
+    boxes
+}
+
+fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(payload.len() + 8);
+    data.extend((payload.len() as u32 + 8).to_be_bytes());
+    data.extend(name);
+    data.extend(payload);
+    data
+}
+
+fn full_box(name: &[u8; 4], version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+    mp4_box(
+        name,
+        &[
+            &(((version as u32) << 24) | flags).to_be_bytes()[..],
+            payload,
+        ]
+        .concat(),
+    )
+}
+
+/// MPEG-4 descriptor, size is always written in four bytes.
+fn descriptor(tag: u8, payload: &[u8]) -> Vec<u8> {
+    let size = payload.len();
+    let mut data = vec![
+        tag,
+        0x80 | ((size >> 21) & 0x7f) as u8,
+        0x80 | ((size >> 14) & 0x7f) as u8,
+        0x80 | ((size >> 7) & 0x7f) as u8,
+        (size & 0x7f) as u8,
+    ];
+    data.extend(payload);
+    data
+}
+
+/// Packed ISO 639-2/T language code of `mdhd` box.
+fn language_code(language: Option<&str>) -> u16 {
+    let language = language
+        .map(|x| x.to_lowercase())
+        .filter(|x| x.len() == 3 && x.bytes().all(|x| x.is_ascii_lowercase()))
+        .unwrap_or("und".to_owned());
+
+    language
+        .bytes()
+        .fold(0, |code, x| (code << 5) | (x - 0x60) as u16)
+}
+
+/// Split annex b byte stream (`00 00 00 01` or `00 00 01` start codes) into nal units.
+fn split_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = vec![];
+    let mut start = None;
+    let mut i = 0;
+
+    while i + 3 <= data.len() {
+        if data[i..(i + 3)] == [0, 0, 1] {
+            if let Some(start) = start {
+                let mut end = i;
+
+                if end > start && data[end - 1] == 0 {
+                    end -= 1;
+                }
+
+                nals.push(&data[start..end]);
+            }
+
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+
+    if let Some(start) = start {
+        nals.push(&data[start..]);
+    }
+
+    nals
+}
+
+/// Remove emulation prevention bytes (`00 00 03`) of a nal unit.
+fn rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        data.push(byte);
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Codec, Track};
+
+    fn track(codec: Codec, codec_private_data: &[u8]) -> Track<'_> {
+        Track {
+            bitrate: 2000000,
+            channels: 0,
+            codec,
+            codec_private_data,
+            duration: 60000000,
+            height: 720,
+            kid: None,
+            language: None,
+            nal_unit_length: 4,
+            pssh: None,
+            sampling_rate: 0,
+            timescale: 10000000,
+            width: 1280,
+        }
+    }
+
+    #[test]
+    fn avc() {
+        // CodecPrivateData of a 1280x720 H264 QualityLevel of a Smooth Streaming manifest.
+        let codec_private_data = hex::decode(
+            "000000016764001FAC2CA5014016EFFC100010014808080A000007D200017700C100005A648000B4C9FE31C6080002D3240005A64FF18E1DA12251600000000168E9093525",
+        )
+        .unwrap();
+        let track = track(Codec::Avc, &codec_private_data);
+
+        assert_eq!(track.codecs().unwrap(), "avc1.64001f");
+        assert_eq!(
+            hex::encode(track.avcc().unwrap()),
+            concat!(
+                "00000050", "61766343", // avcC
+                "0164001f", "ff", "e1", // version, profile, level, length size, sps count
+                "0038", "6764001fac2ca5014016effc100010014808080a000007d200017700c100005a648000b4c9fe31c6080002d3240005a64ff18e1da1225160",
+                "01", "0005", "68e9093525",
+            )
+        );
+        assert!(track
+            .init_segment()
+            .unwrap()
+            .windows(80)
+            .any(|x| x == track.avcc().unwrap()));
+    }
+
+    #[test]
+    fn hevc() {
+        // x265 Main profile parameter sets with emulation prevention bytes.
+        let codec_private_data = hex::decode(
+            "0000000140010C01FFFF016000000300900000030000030078999809\
+             00000001420101016000000300900000030000030078A003C08010E596666924CAE010000003001000000301E080\
+             000000014401C172B46240",
+        )
+        .unwrap();
+        let track = track(Codec::Hevc, &codec_private_data);
+
+        assert_eq!(track.codecs().unwrap(), "hvc1.1.6.L120.90");
+        assert_eq!(
+            hex::encode(track.hvcc().unwrap()),
+            concat!(
+                "00000077", "68766343", // hvcC
+                "01", "016000000090000000000078", // version, general profile, tier and level
+                "f000", "fc", "fd", "f8", "f8", "0000", "0f", "03",
+                "a0", "0001", "0018", "40010c01ffff016000000300900000030000030078999809",
+                "a1", "0001", "002a", "420101016000000300900000030000030078a003c08010e596666924cae010000003001000000301e080",
+                "a2", "0001", "0007", "4401c172b46240",
+            )
+        );
+    }
+}
@@ -0,0 +1,5 @@
+mod init;
+mod playlist;
+
+pub(crate) use init::{patch_track_id, TRACK_ID};
+pub(crate) use playlist::{parse, parse_as_master};
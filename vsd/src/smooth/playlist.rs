@@ -0,0 +1,407 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-sstr/8383f27f-7efe-4c60-832a-387274457251
+    2. https://github.com/Dash-Industry-Forum/dash.js/blob/development/src/mss/parser/MssParser.js
+
+*/
+
+use super::init::{Codec, Track};
+use crate::{
+    playlist::{
+        Key, KeyId, KeyMethod, Map, MasterPlaylist, MediaPlaylist, MediaType, PlaylistType, Segment,
+    },
+    utils,
+};
+use anyhow::{anyhow, Result};
+use kdam::term::Colorizer;
+use reqwest::Url;
+use serde::Deserialize;
+use vsd_mp4::pssh::Pssh;
+
+const PLAYREADY_SYSTEM_ID: [u8; 16] = [
+    0x9a, 0x04, 0xf0, 0x79, 0x98, 0x40, 0x42, 0x86, 0xab, 0x92, 0xe6, 0x5b, 0xe0, 0x88, 0x5f, 0x95,
+];
+
+#[derive(Deserialize)]
+pub(crate) struct SmoothStreamingMedia {
+    #[serde(rename = "@IsLive")]
+    is_live: Option<String>,
+    #[serde(rename = "Protection")]
+    protection: Option<Protection>,
+    #[serde(rename = "StreamIndex", default)]
+    stream_indexes: Vec<StreamIndex>,
+    #[serde(rename = "@TimeScale")]
+    timescale: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Protection {
+    #[serde(rename = "ProtectionHeader", default)]
+    protection_headers: Vec<ProtectionHeader>,
+}
+
+#[derive(Deserialize)]
+struct ProtectionHeader {
+    #[serde(rename = "$text")]
+    content: Option<String>,
+    #[serde(rename = "@SystemID")]
+    system_id: String,
+}
+
+#[derive(Deserialize)]
+struct StreamIndex {
+    #[serde(rename = "c", default)]
+    chunks: Vec<Chunk>,
+    #[serde(rename = "@DisplayHeight")]
+    display_height: Option<u64>,
+    #[serde(rename = "@DisplayWidth")]
+    display_width: Option<u64>,
+    #[serde(rename = "@Language")]
+    language: Option<String>,
+    #[serde(rename = "@Name")]
+    name: Option<String>,
+    #[serde(rename = "QualityLevel", default)]
+    quality_levels: Vec<QualityLevel>,
+    #[serde(rename = "@Type")]
+    stream_type: String,
+    #[serde(rename = "@Subtype")]
+    subtype: Option<String>,
+    #[serde(rename = "@TimeScale")]
+    timescale: Option<u64>,
+    #[serde(rename = "@Url")]
+    url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct QualityLevel {
+    #[serde(rename = "@Bitrate")]
+    bitrate: u64,
+    #[serde(rename = "@Channels")]
+    channels: Option<u16>,
+    #[serde(rename = "@CodecPrivateData")]
+    codec_private_data: Option<String>,
+    #[serde(rename = "CustomAttributes")]
+    custom_attributes: Option<CustomAttributes>,
+    #[serde(rename = "@FourCC")]
+    fourcc: Option<String>,
+    #[serde(rename = "@MaxHeight")]
+    max_height: Option<u64>,
+    #[serde(rename = "@MaxWidth")]
+    max_width: Option<u64>,
+    #[serde(rename = "@NALUnitLengthField")]
+    nal_unit_length_field: Option<u8>,
+    #[serde(rename = "@SamplingRate")]
+    sampling_rate: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct CustomAttributes {
+    #[serde(rename = "Attribute", default)]
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Deserialize)]
+struct Attribute {
+    #[serde(rename = "@Name")]
+    name: String,
+    #[serde(rename = "@Value")]
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    #[serde(rename = "@d")]
+    d: Option<u64>,
+    #[serde(rename = "@r")]
+    r: Option<u64>,
+    #[serde(rename = "@t")]
+    t: Option<u64>,
+}
+
+pub(crate) fn parse(text: &str) -> Result<SmoothStreamingMedia> {
+    quick_xml::de::from_str::<SmoothStreamingMedia>(text.trim_start_matches('\u{feff}')).map_err(
+        |x| {
+            anyhow!(
+                "couldn't parse response as smooth streaming manifest (failed with {}).\n\n{}",
+                x,
+                text
+            )
+        },
+    )
+}
+
+/// Every quality level of every stream index is a stream. Chunks are same for all quality levels
+/// of a stream index, so segments are pushed along with streams.
+pub(crate) fn parse_as_master(
+    manifest: &SmoothStreamingMedia,
+    base_url: &Url,
+    uri: &str,
+) -> Result<MasterPlaylist> {
+    let drm = drm(manifest);
+    let mut streams = vec![];
+
+    for stream_index in &manifest.stream_indexes {
+        let media_type = match stream_index.stream_type.to_lowercase().as_str() {
+            "audio" => MediaType::Audio,
+            "video" => MediaType::Video,
+            // SCMD, CHAP, CTRL and DATA subtypes are metadata.
+            "text"
+                if stream_index
+                    .subtype
+                    .as_ref()
+                    .is_none_or(|x| x == "SUBT" || x == "CAPT") =>
+            {
+                MediaType::Subtitles
+            }
+            _ => continue,
+        };
+
+        let timescale = stream_index
+            .timescale
+            .or(manifest.timescale)
+            .unwrap_or(10_000_000);
+        let chunks = chunks(&stream_index.chunks);
+        let duration = chunks.iter().map(|x| x.1).sum::<u64>();
+        let url = stream_index
+            .url
+            .as_ref()
+            .ok_or_else(|| anyhow!("missing @Url on StreamIndex node."))?;
+
+        for quality_level in &stream_index.quality_levels {
+            let Some(codec) = quality_level
+                .fourcc
+                .as_ref()
+                .and_then(|x| Codec::from_fourcc(x))
+                .or((media_type == MediaType::Audio).then_some(Codec::Aac))
+                .or((media_type == MediaType::Subtitles).then_some(Codec::Ttml))
+            else {
+                eprintln!(
+                    "    {} skipping {} stream with unsupported fourcc {}",
+                    "Warning".colorize("bold yellow"),
+                    media_type,
+                    quality_level.fourcc.as_deref().unwrap_or("(none)")
+                );
+                continue;
+            };
+
+            let codec_private_data = hex::decode(
+                quality_level
+                    .codec_private_data
+                    .as_deref()
+                    .unwrap_or_default()
+                    .trim(),
+            )?;
+            let width = quality_level.max_width.or(stream_index.display_width);
+            let height = quality_level.max_height.or(stream_index.display_height);
+            let drm = drm.as_ref().filter(|_| codec != Codec::Ttml);
+
+            let track = Track {
+                bitrate: quality_level.bitrate as u32,
+                channels: quality_level.channels.unwrap_or(2),
+                codec,
+                codec_private_data: &codec_private_data,
+                duration,
+                height: height.unwrap_or(0) as u16,
+                kid: drm.map(|x| x.kid),
+                language: stream_index.language.as_deref(),
+                nal_unit_length: quality_level.nal_unit_length_field.unwrap_or(4),
+                pssh: drm.map(|x| (PLAYREADY_SYSTEM_ID, x.playready_object.as_slice())),
+                sampling_rate: quality_level.sampling_rate.unwrap_or(48000),
+                timescale,
+                width: width.unwrap_or(0) as u16,
+            };
+
+            let (codecs, init_segment) = match track
+                .codecs()
+                .and_then(|x| track.init_segment().map(|y| (x, y)))
+            {
+                Ok(x) => x,
+                Err(e) => {
+                    eprintln!(
+                        "    {} skipping {} stream of {} bitrate ({})",
+                        "Warning".colorize("bold yellow"),
+                        media_type,
+                        quality_level.bitrate,
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            let custom_attributes = quality_level
+                .custom_attributes
+                .as_ref()
+                .map(|x| {
+                    x.attributes
+                        .iter()
+                        .map(|x| format!("{}={}", x.name, x.value))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .unwrap_or_default();
+            let url = url
+                .replace("{bitrate}", &quality_level.bitrate.to_string())
+                .replace("{Bitrate}", &quality_level.bitrate.to_string())
+                .replace("{CustomAttributes}", &custom_attributes);
+
+            let mut segments = chunks
+                .iter()
+                .map(|(time, duration)| {
+                    Ok(Segment {
+                        duration: (*duration as f64 / timescale as f64) as f32,
+                        uri: base_url
+                            .join(
+                                &url.replace("{start time}", &time.to_string())
+                                    .replace("{start_time}", &time.to_string()),
+                            )?
+                            .to_string(),
+                        ..Default::default()
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            if let Some(first_segment) = segments.first_mut() {
+                first_segment.key = drm.map(|x| x.key.clone());
+                first_segment.map = Some(Map {
+                    range: None,
+                    uri: format!(
+                        "data:application/mp4;base64,{}",
+                        utils::encode_base64(&init_segment)
+                    ),
+                });
+            }
+
+            streams.push(MediaPlaylist {
+                bandwidth: Some(quality_level.bitrate),
+                channels: (media_type == MediaType::Audio)
+                    .then(|| quality_level.channels.map(|x| x as f32))
+                    .flatten(),
+                codecs: Some(codecs),
+                extension: Some("mp4".to_owned()),
+                key: drm.map(|x| x.key.clone()),
+                language: stream_index.language.clone(),
+                live: manifest
+                    .is_live
+                    .as_ref()
+                    .is_some_and(|x| x.eq_ignore_ascii_case("true")),
+                media_type: media_type.clone(),
+                name: stream_index.name.clone(),
+                playlist_type: PlaylistType::Smooth,
+                resolution: (media_type == MediaType::Video)
+                    .then(|| width.zip(height))
+                    .flatten(),
+                sdh: stream_index.subtype.as_deref() == Some("CAPT"),
+                segments,
+                uri: uri.to_owned(),
+                ..Default::default()
+            });
+        }
+    }
+
+    Ok(MasterPlaylist {
+        playlist_type: PlaylistType::Smooth,
+        uri: uri.to_owned(),
+        streams,
+    })
+}
+
+/// Start time and duration of chunks (`c` elements).
+/// `@r` is the number of consecutive chunks with same duration (Smooth Streaming 2.2).
+/// Duration of a chunk without `@d` is the time till start of next chunk.
+fn chunks(chunks: &[Chunk]) -> Vec<(u64, u64)> {
+    let mut expanded = vec![];
+    let mut time = 0;
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        if let Some(t) = chunk.t {
+            time = t;
+        }
+
+        let Some(duration) = chunk.d.or_else(|| {
+            chunks
+                .get(i + 1)
+                .and_then(|x| x.t)
+                .map(|x| x.saturating_sub(time))
+        }) else {
+            continue;
+        };
+
+        for _ in 0..chunk.r.unwrap_or(1).max(1) {
+            expanded.push((time, duration));
+            time += duration;
+        }
+    }
+
+    expanded
+}
+
+/// Encryption of a protected manifest.
+struct Drm {
+    key: Key,
+    /// Default key id (big endian).
+    kid: [u8; 16],
+    /// PlayReady object of `ProtectionHeader`, used as data of `pssh` box.
+    playready_object: Vec<u8>,
+}
+
+fn drm(manifest: &SmoothStreamingMedia) -> Option<Drm> {
+    let protection_header = manifest
+        .protection
+        .as_ref()?
+        .protection_headers
+        .iter()
+        .find(|x| {
+            hex::decode(x.system_id.replace('-', "")).ok() == Some(PLAYREADY_SYSTEM_ID.to_vec())
+        })?;
+    let playready_object = utils::decode_base64(protection_header.content.as_ref()?.trim()).ok()?;
+    let mut key_ids: Vec<KeyId> = vec![];
+    let mut kid = None;
+
+    for key_id in Pssh::from_playready_object(&playready_object)
+        .map(|x| x.key_ids)
+        .unwrap_or_default()
+    {
+        // PlayReady headers store key ids as little endian guids.
+        let Some(value) = hex::decode(&key_id.value)
+            .ok()
+            .and_then(|x| <[u8; 16]>::try_from(x).ok())
+            .map(guid_to_uuid)
+        else {
+            continue;
+        };
+
+        kid.get_or_insert(value);
+
+        if !key_ids.iter().any(|x| x.value == hex::encode(value)) {
+            key_ids.push(KeyId {
+                system: key_id.system_type.to_string(),
+                value: hex::encode(value),
+            });
+        }
+    }
+
+    Some(Drm {
+        key: Key {
+            default_kid: key_ids.first().map(|x| x.uuid()),
+            iv: None,
+            key_format: None,
+            key_ids,
+            method: KeyMethod::Cenc,
+            psshs: vec![("playready".to_owned(), playready_object.clone())],
+            scheme: Some("cenc".to_owned()),
+            uri: None,
+        },
+        kid: kid?,
+        playready_object,
+    })
+}
+
+fn guid_to_uuid(guid: [u8; 16]) -> [u8; 16] {
+    let mut uuid = guid;
+    uuid[..4].reverse();
+    uuid[4..6].reverse();
+    uuid[6..8].reverse();
+    uuid
+}
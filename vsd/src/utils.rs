@@ -48,9 +48,9 @@ pub(super) fn decode_base64<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>> {
         .map_err(|x| x.into())
 }
 
-// pub(super) fn encode_base64<T: AsRef<[u8]>>(input: T) -> String {
-//     base64::engine::general_purpose::STANDARD.encode(input)
-// }
+pub(super) fn encode_base64<T: AsRef<[u8]>>(input: T) -> String {
+    base64::engine::general_purpose::STANDARD.encode(input)
+}
 
 pub(super) fn decrypt_aes_128_cbc(
     input: &mut [u8],